@@ -76,7 +76,8 @@ pub fn development_config() -> Result<ChainSpec, String> {
 fn genesis(root_key: AccountId, endowed_accounts: Vec<AccountId>) -> GenesisConfig {
 	GenesisConfig {
 		system: SystemConfig {
-			// Add Wasm runtime to storage.
+			// No Wasm runtime, the empty code is always executed by the native runtime. A Wasm
+			// runtime could be set by `set_code` and executed with `--wasm-execution`.
 			code: b"".to_vec(),
 			changes_trie_config: Default::default(),
 		},
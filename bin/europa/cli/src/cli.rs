@@ -4,7 +4,7 @@
 
 use structopt::StructOpt;
 
-use ec_cli::{RunCmd, StateKvCmd, WorkspaceCmd};
+use ec_cli::{CoverageCmd, ReplayCmd, RunCmd, StateKvCmd, TrapSnapshotCmd, WorkspaceCmd};
 
 #[derive(Debug, StructOpt)]
 pub struct Cli {
@@ -20,6 +20,16 @@ pub enum Subcommand {
 	/// Print modified stored state kvs for a block.
 	StateKv(StateKvCmd),
 
+	/// Print the snapshots of the trapped contract instances for an extrinsic, and dump the
+	/// linear memory.
+	TrapSnapshot(TrapSnapshotCmd),
+
+	/// Export the code coverage recorded by `--contract-coverage` in LCOV.
+	Coverage(CoverageCmd),
+
+	/// Replay the contract invocations recorded by `--sandbox-record` without the runtime.
+	Replay(ReplayCmd),
+
 	/// Related to workspace operation.
 	Workspace(WorkspaceCmd),
 }
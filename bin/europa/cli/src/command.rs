@@ -36,6 +36,10 @@ impl SubstrateCli for Cli {
 		// todo chain_spec would receive some params to generate account or other thing dynamically,
 		// maybe use some global vars or something others.
 		Ok(match id {
+			// e.g. a spec with `sandboxLimits` in the properties.
+			path if std::path::Path::new(path).is_file() => Box::new(
+				chain_spec::ChainSpec::from_json_file(std::path::PathBuf::from(path))?,
+			),
 			"dev" | _ => Box::new(chain_spec::development_config()?),
 		})
 	}
@@ -58,6 +62,21 @@ pub fn run() -> sc_cli::Result<()> {
 					cmd.run::<europa_runtime::opaque::Block, _>(state_kv)
 				})
 			}
+			Subcommand::TrapSnapshot(cmd) => {
+				let runner = ec_cli::build_runner(&cli, cmd)?;
+				runner.sync_run(|config| {
+					let state_kv = service::new_state_kv(&config, true)?;
+					cmd.run::<europa_runtime::opaque::Block, _>(state_kv)
+				})
+			}
+			Subcommand::Coverage(cmd) => {
+				let runner = ec_cli::build_runner(&cli, cmd)?;
+				runner.sync_run(|config| {
+					let state_kv = service::new_state_kv(&config, true)?;
+					cmd.run::<europa_runtime::opaque::Block, _>(state_kv)
+				})
+			}
+			Subcommand::Replay(cmd) => cmd.init_and_run::<Cli>(),
 			Subcommand::Workspace(cmd) => cmd.init_and_run::<Cli>(),
 		},
 		None => {
@@ -15,6 +15,7 @@ use europa_runtime::{self, opaque::Block, RuntimeApi};
 /// * config pre-process
 /// * custom rpc
 pub fn new_full(config: Configuration) -> Result<TaskManager, Error> {
+	let rpc_max_gas_limit = config.rpc_max_gas_limit;
 	// need Block, RuntimeApi, Executor type
 	ec_service::builder_ext::new_node::<Block, RuntimeApi, Executor, _, _>(config, |components| {
 		let client = components.client.clone();
@@ -25,6 +26,7 @@ pub fn new_full(config: Configuration) -> Result<TaskManager, Error> {
 				client: client.clone(),
 				pool: pool.clone(),
 				deny_unsafe,
+				rpc_max_gas_limit,
 			};
 
 			europa_rpc::create_full(deps)
@@ -5,9 +5,11 @@
 use ec_executor::native_executor_instance;
 pub use ec_executor::NativeExecutor;
 
-// Declare an instance of the native executor named `Executor`. Not not has wasm part.
+// Declare an instance of the native executor named `Executor`. The wasm runtime is only executed
+// with `--wasm-execution`, which could import the contract tracing host functions of Europa.
 native_executor_instance!(
 	pub Executor,
 	europa_runtime::api::dispatch,
 	europa_runtime::native_version,
+	ep_io::contract_tracing::HostFunctions,
 );
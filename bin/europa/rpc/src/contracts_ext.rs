@@ -5,7 +5,8 @@
 use std::convert::{TryFrom, TryInto};
 use std::sync::Arc;
 
-use jsonrpc_core::{Error, ErrorCode, Result};
+use codec::Decode;
+use jsonrpc_core::{ErrorCode, Result};
 use jsonrpc_derive::rpc;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -17,6 +18,7 @@ use sp_rpc::number::NumberOrHex;
 use sp_runtime::{
 	generic::BlockId,
 	traits::{Block as BlockT, Header as HeaderT},
+	DispatchError,
 };
 
 use pallet_contracts_primitives::Code;
@@ -25,11 +27,23 @@ pub use pallet_contracts_rpc_runtime_api::ContractsApi as ContractsRuntimeApi;
 use pallet_contracts::NestedRuntime;
 use pallet_contracts_rpc::Weight;
 
-use ec_client_api::statekv::StateKv;
+use ec_client_api::{contract_tracing::debug_messages, statekv::StateKv};
+use ep_sandbox::{debugger, TrapSnapshot, ValidationReport, Value};
 pub use europa_runtime::runtime_api::ContractsExtApi as ContractsExtRuntimeApi;
 use europa_runtime::{AccountId, Balance, Runtime};
 
+/// The runtime api call failed.
 const RUNTIME_ERROR: i64 = 1;
+/// A parameter could not be decoded into the runtime type.
+const DECODE_ERROR: i64 = 2;
+/// The requested gas limit is greater than the ceiling.
+const GAS_LIMIT_ERROR: i64 = 3;
+/// The tracing could not be deserialized.
+const TRACE_ERROR: i64 = 4;
+/// The trap snapshot could not be decoded or decompressed.
+const TRAP_SNAPSHOT_ERROR: i64 = 5;
+/// The uploaded code could not be deployed.
+const INVALID_CODE: i64 = 6;
 
 /// A rough estimate of how much gas a decent hardware consumes per second,
 /// using native execution.
@@ -41,9 +55,21 @@ const RUNTIME_ERROR: i64 = 1;
 /// https://github.com/paritytech/substrate/pull/5446
 const GAS_PER_SECOND: Weight = 1_000_000_000_000;
 
-/// The maximum amount of weight that the call and instantiate rpcs are allowed to consume.
+/// The default maximum amount of weight that the call and instantiate rpcs are allowed to consume.
 /// This puts a ceiling on the weight limit that is supplied to the rpc as an argument.
-const GAS_LIMIT: Weight = 5 * GAS_PER_SECOND;
+///
+/// The ceiling could be changed by `--rpc-max-gas-limit` or the workspace config.
+const DEFAULT_GAS_LIMIT: Weight = 5 * GAS_PER_SECOND;
+
+/// Point the block for the state which the dry-run rpcs are executed on.
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum NumberOrHash<Number, Hash> {
+	/// The block number.
+	Number(Number),
+	/// The block hash.
+	Hash(Hash),
+}
 
 /// A struct that encodes RPC parameters required for a call to a smart-contract.
 #[derive(Serialize, Deserialize)]
@@ -70,6 +96,15 @@ pub struct InstantiateRequest<AccountId, Hash> {
 	salt: Bytes,
 }
 
+/// The range of the linear memory in the trap snapshot.
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct MemoryRange {
+	offset: u32,
+	len: u32,
+}
+
 /// ContractsExt RPC methods.
 #[rpc]
 pub trait ContractsExtApi<BlockHash, BlockNumber> {
@@ -83,7 +118,7 @@ pub trait ContractsExtApi<BlockHash, BlockNumber> {
 	fn call(
 		&self,
 		call_request: CallRequest<AccountId>,
-		at: Option<BlockHash>,
+		at: Option<NumberOrHash<BlockNumber, BlockHash>>,
 	) -> Result<serde_json::Value>;
 
 	/// Instantiate a new contract, and return the result and the contract tracing information.
@@ -91,34 +126,98 @@ pub trait ContractsExtApi<BlockHash, BlockNumber> {
 	/// This call is performed locally without submitting any transactions. Thus the contract
 	/// is not actually created.
 	///
-	/// This method is useful for UIs to dry-run contract instantiations.
+	/// This method is useful for UIs to dry-run contract instantiations. The uploaded code is
+	/// validated before the dry-run, and the unsupported features of it are returned as the error.
 	#[rpc(name = "contractsExt_instantiate")]
 	fn instantiate(
 		&self,
 		instantiate_request: InstantiateRequest<AccountId, BlockHash>,
-		at: Option<BlockHash>,
+		at: Option<NumberOrHash<BlockNumber, BlockHash>>,
 	) -> Result<serde_json::Value>;
 
 	/// Return the contract tracing information by blocknumber and extrinsic index.
 	#[rpc(name = "contractsExt_tracing")]
 	fn tracing(&self, number: BlockNumber, index: u32) -> Result<serde_json::Value>;
+
+	/// Return the snapshots of the trapped contract instances by blocknumber and extrinsic index.
+	///
+	/// The linear memories are returned in hex, only the `range` of them if it is supplied.
+	#[rpc(name = "contractsExt_trapSnapshots")]
+	fn trap_snapshots(
+		&self,
+		number: BlockNumber,
+		index: u32,
+		range: Option<MemoryRange>,
+	) -> Result<Vec<serde_json::Value>>;
 }
 
 /// An implementation of contract specific RPC methods.
 pub struct ContractsExt<C, B, S> {
 	client: Arc<C>,
+	max_gas_limit: Weight,
 	_marker: std::marker::PhantomData<(B, S)>,
 }
 impl<C, B, S> ContractsExt<C, B, S> {
-	/// Create new `Contracts` with the given reference to the client.
-	pub fn new(client: Arc<C>) -> Self {
+	/// Create new `Contracts` with the given reference to the client and the gas ceiling.
+	///
+	/// If `max_gas_limit` is `None`, the `DEFAULT_GAS_LIMIT` is used.
+	pub fn new(client: Arc<C>, max_gas_limit: Option<Weight>) -> Self {
 		ContractsExt {
 			client,
+			max_gas_limit: max_gas_limit.unwrap_or(DEFAULT_GAS_LIMIT),
 			_marker: Default::default(),
 		}
 	}
 }
 
+impl<C, B, S> ContractsExt<C, B, S>
+where
+	B: BlockT,
+	C: HeaderBackend<B>,
+{
+	/// Get the `BlockId` for the dry-run rpcs, if `at` is not supplied assume the best block.
+	fn block_id(
+		&self,
+		at: Option<NumberOrHash<<<B as BlockT>::Header as HeaderT>::Number, <B as BlockT>::Hash>>,
+	) -> Result<BlockId<B>> {
+		let hash = match at {
+			Some(NumberOrHash::Hash(hash)) => hash,
+			Some(NumberOrHash::Number(number)) => self
+				.client
+				.hash(number)
+				.map_err(|e| ContractExtError::<B>::Runtime(e.to_string()))?
+				.ok_or(ContractExtError::<B>::InvalidBlockNumber(number))?,
+			None => self.client.info().best_hash,
+		};
+		Ok(BlockId::hash(hash))
+	}
+
+	fn decode_hex<H: std::fmt::Debug + Copy, T: TryFrom<H>>(
+		from: H,
+		name: &'static str,
+	) -> Result<T> {
+		from.try_into().map_err(|_| {
+			ContractExtError::<B>::Decode {
+				value: format!("{:?}", from),
+				ty: name,
+			}
+			.into()
+		})
+	}
+
+	fn limit_gas(&self, gas_limit: Weight) -> Result<()> {
+		if gas_limit > self.max_gas_limit {
+			Err(ContractExtError::<B>::GasLimit {
+				requested: gas_limit,
+				max: self.max_gas_limit,
+			}
+			.into())
+		} else {
+			Ok(())
+		}
+	}
+}
+
 impl<C, B, S> ContractsExtApi<<B as BlockT>::Hash, <<B as BlockT>::Header as HeaderT>::Number>
 	for ContractsExt<C, B, S>
 where
@@ -137,12 +236,10 @@ where
 	fn call(
 		&self,
 		call_request: CallRequest<AccountId>,
-		at: Option<<B as BlockT>::Hash>,
+		at: Option<NumberOrHash<<<B as BlockT>::Header as HeaderT>::Number, <B as BlockT>::Hash>>,
 	) -> Result<serde_json::Value> {
 		let api = self.client.runtime_api();
-		let at = BlockId::hash(at.unwrap_or_else(||
-            // If the block hash is not supplied assume the best block.
-            self.client.info().best_hash));
+		let at = self.block_id(at)?;
 
 		let CallRequest {
 			origin,
@@ -152,32 +249,26 @@ where
 			input_data,
 		} = call_request;
 
-		let value: Balance = decode_hex(value, "balance")?;
-		let gas_limit: Weight = decode_hex(gas_limit, "weight")?;
-		limit_gas(gas_limit)?;
+		let value: Balance = Self::decode_hex(value, "balance")?;
+		let gas_limit: Weight = Self::decode_hex(gas_limit, "weight")?;
+		self.limit_gas(gas_limit)?;
 
-		let (exec_result, trace) = api
-			.call(&at, origin, dest, value, gas_limit, input_data.to_vec())
-			.map_err(runtime_error_into_rpc_err)?;
+		// the attached debugger pauses the dry-run at the breakpoints.
+		let (exec_result, trace) = debugger::debuggable(|| {
+			api.call(&at, origin, dest, value, gas_limit, input_data.to_vec())
+		})
+		.map_err(|e| ContractExtError::<B>::Runtime(e.to_string()))?;
 
-		let mut t: NestedRuntime<Runtime> =
-			serde_json::from_str(&trace).expect("trace string must be a valid json");
-		trim_gas_trace(&mut t);
-		Ok(json!({
-			"result": exec_result,
-			"trace": t,
-		}))
+		exec_output::<B, _, _>(&exec_result, &exec_result.result, &trace)
 	}
 
 	fn instantiate(
 		&self,
 		instantiate_request: InstantiateRequest<AccountId, <B as BlockT>::Hash>,
-		at: Option<<B as BlockT>::Hash>,
+		at: Option<NumberOrHash<<<B as BlockT>::Header as HeaderT>::Number, <B as BlockT>::Hash>>,
 	) -> Result<serde_json::Value> {
 		let api = self.client.runtime_api();
-		let at = BlockId::hash(at.unwrap_or_else(||
-            // If the block hash is not supplied assume the best block.
-            self.client.info().best_hash));
+		let at = self.block_id(at)?;
 
 		let InstantiateRequest {
 			origin,
@@ -188,12 +279,18 @@ where
 			salt,
 		} = instantiate_request;
 
-		let endowment: Balance = decode_hex(endowment, "balance")?;
-		let gas_limit: Weight = decode_hex(gas_limit, "weight")?;
-		limit_gas(gas_limit)?;
+		let endowment: Balance = Self::decode_hex(endowment, "balance")?;
+		let gas_limit: Weight = Self::decode_hex(gas_limit, "weight")?;
+		self.limit_gas(gas_limit)?;
+		if let Code::Upload(ref wasm) = code {
+			let report = ep_sandbox::validate(wasm);
+			if !report.is_valid() {
+				return Err(ContractExtError::<B>::InvalidCode(report).into());
+			}
+		}
 
-		let (exec_result, trace) = api
-			.instantiate(
+		let (exec_result, trace) = debugger::debuggable(|| {
+			api.instantiate(
 				&at,
 				origin,
 				endowment,
@@ -202,15 +299,10 @@ where
 				data.to_vec(),
 				salt.to_vec(),
 			)
-			.map_err(runtime_error_into_rpc_err)?;
+		})
+		.map_err(|e| ContractExtError::<B>::Runtime(e.to_string()))?;
 
-		let mut t: NestedRuntime<Runtime> =
-			serde_json::from_str(&trace).expect("trace string must be a valid json");
-		trim_gas_trace(&mut t);
-		Ok(json!({
-			"result": exec_result,
-			"trace": t,
-		}))
+		exec_output::<B, _, _>(&exec_result, &exec_result.result, &trace)
 	}
 
 	fn tracing(
@@ -222,13 +314,172 @@ where
 		let trace = state_kv
 			.get_contract_tracing(number, index)
 			.ok_or(ContractExtError::<B>::NoTracing(number, index))?;
-		let mut t: NestedRuntime<Runtime> =
-			serde_json::from_str(&trace).expect("trace string must be a valid json");
-		trim_gas_trace(&mut t);
+		let t = parse_trace::<B>(&trace)?;
 		Ok(json!({
+			"debugMessages": debug_messages(&t),
 			"trace": t,
 		}))
 	}
+
+	fn trap_snapshots(
+		&self,
+		number: <<B as BlockT>::Header as HeaderT>::Number,
+		index: u32,
+		range: Option<MemoryRange>,
+	) -> Result<Vec<serde_json::Value>> {
+		let state_kv = self.client.state_kv();
+		let snapshots = state_kv
+			.get_trap_snapshots(number, index)
+			.ok_or(ContractExtError::<B>::NoTrapSnapshot(number, index))?;
+		snapshots
+			.iter()
+			.map(|snapshot| {
+				let snapshot = TrapSnapshot::decode(&mut &snapshot[..])
+					.map_err(|e| ContractExtError::<B>::TrapSnapshot(e.to_string()))?;
+				snapshot_output::<B>(&snapshot, range)
+			})
+			.collect()
+	}
+}
+
+/// Build the output of the trap snapshot, the memories are decompressed into hex.
+fn snapshot_output<B: BlockT>(
+	snapshot: &TrapSnapshot,
+	range: Option<MemoryRange>,
+) -> Result<serde_json::Value> {
+	let memories = snapshot
+		.memories
+		.iter()
+		.map(|memory| {
+			let data = memory
+				.decompress()
+				.map_err(ContractExtError::<B>::TrapSnapshot)?;
+			let (offset, data) = match range {
+				Some(MemoryRange { offset, len }) => {
+					let start = (offset as usize).min(data.len());
+					let end = start.saturating_add(len as usize).min(data.len());
+					(start, &data[start..end])
+				}
+				None => (0, &data[..]),
+			};
+			Ok(json!({
+				"module": memory.module,
+				"field": memory.field,
+				"size": memory.size,
+				"offset": offset,
+				"data": Bytes(data.to_vec()),
+			}))
+		})
+		.collect::<Result<Vec<_>>>()?;
+	Ok(json!({
+		"depth": snapshot.depth,
+		"executor": snapshot.executor,
+		"function": snapshot.function,
+		"args": snapshot.args.iter().map(value_output).collect::<Vec<_>>(),
+		"frame": snapshot.frame.as_ref().map(|frame| json!({
+			"function": frame.function,
+			"locals": frame.locals.iter().map(value_output).collect::<Vec<_>>(),
+		})),
+		"trap": snapshot.trap,
+		"globals": snapshot
+			.globals
+			.iter()
+			.map(|(name, value)| (name.clone(), value_output(value)))
+			.collect::<serde_json::Map<_, _>>(),
+		"memories": memories,
+	}))
+}
+
+/// The wasm value with its type, the floats are materialized.
+fn value_output(value: &Value) -> serde_json::Value {
+	match value {
+		Value::I32(v) => json!({ "i32": v }),
+		Value::I64(v) => json!({ "i64": v }),
+		Value::F32(v) => json!({ "f32": f32::from_bits(*v) }),
+		Value::F64(v) => json!({ "f64": f64::from_bits(*v) }),
+	}
+}
+
+/// Deserialize the tracing string from the runtime and remove the gas items.
+///
+/// The node merges the records which are not in `NestedRuntime` into the frames (e.g.
+/// `chain_extension`, `sandbox_divergence` and `sandbox_profile`), they are kept as they are.
+fn parse_trace<B: BlockT>(trace: &str) -> Result<serde_json::Value> {
+	let raw: serde_json::Value =
+		serde_json::from_str(trace).map_err(ContractExtError::<B>::Trace)?;
+	let mut t: NestedRuntime<Runtime> =
+		serde_json::from_value(raw.clone()).map_err(ContractExtError::<B>::Trace)?;
+	trim_gas_trace(&mut t);
+	let mut t = serde_json::to_value(t).map_err(ContractExtError::<B>::Trace)?;
+	keep_merged_records(&raw, &mut t);
+	Ok(t)
+}
+
+/// Copy the keys of the `raw` frames which are dropped by `NestedRuntime` into the frames.
+fn keep_merged_records(raw: &serde_json::Value, frame: &mut serde_json::Value) {
+	let (raw, frame) = match (raw.as_object(), frame.as_object_mut()) {
+		(Some(raw), Some(frame)) => (raw, frame),
+		_ => return,
+	};
+	for (key, value) in raw {
+		if !frame.contains_key(key) {
+			frame.insert(key.clone(), value.clone());
+		}
+	}
+	if let (Some(raw_nests), Some(nests)) = (
+		raw.get("nest").and_then(|nest| nest.as_array()),
+		frame.get_mut("nest").and_then(|nest| nest.as_array_mut()),
+	) {
+		for (raw, nest) in raw_nests.iter().zip(nests.iter_mut()) {
+			keep_merged_records(raw, nest);
+		}
+	}
+}
+
+/// Build the dry-run output. The failed execution is returned as well, the `result` is the same
+/// as `contracts_call`, and the pallet error or the wasm trap of it is put beside the result.
+fn exec_output<B: BlockT, R: Serialize, V>(
+	exec_result: &R,
+	dispatch_result: &std::result::Result<V, DispatchError>,
+	trace: &str,
+) -> Result<serde_json::Value> {
+	let t = parse_trace::<B>(trace)?;
+	let frames = debug_messages(&t);
+	for frame in frames.iter() {
+		for message in frame.messages.iter() {
+			log::info!(
+				target: "contract-debug",
+				"[dry-run] depth:{} contract:{} | {}",
+				frame.depth,
+				frame.contract.as_ref().map(|c| c.to_string()).unwrap_or_default(),
+				message
+			);
+		}
+	}
+	let mut output = json!({
+		"result": exec_result,
+		"debugMessages": frames,
+		"trace": t,
+	});
+	if let Err(DispatchError::Module {
+		index,
+		error,
+		message,
+	}) = dispatch_result
+	{
+		output["moduleError"] = json!({
+			"index": index,
+			"error": error,
+			"name": message,
+		});
+	}
+	// the wasm error of the top frame is the one which fails the execution.
+	if let Ok(ep_sandbox::Error::Trap(trap)) =
+		serde_json::from_value::<ep_sandbox::Error>(output["trace"]["wasm_error"].clone())
+	{
+		output["trap"] = json!(trap);
+	}
+	Ok(output)
 }
 
 fn trim_gas_trace(trace: &mut NestedRuntime<Runtime>) {
@@ -245,41 +496,29 @@ fn trim_gas_trace(trace: &mut NestedRuntime<Runtime>) {
 	}
 }
 
-/// Converts a runtime trap into an RPC error.
-fn runtime_error_into_rpc_err(err: impl std::fmt::Debug) -> Error {
-	Error {
-		code: ErrorCode::ServerError(RUNTIME_ERROR),
-		message: "Runtime error".into(),
-		data: Some(format!("{:?}", err).into()),
-	}
-}
-
-fn decode_hex<H: std::fmt::Debug + Copy, T: TryFrom<H>>(from: H, name: &str) -> Result<T> {
-	from.try_into().map_err(|_| Error {
-		code: ErrorCode::InvalidParams,
-		message: format!("{:?} does not fit into the {} type", from, name),
-		data: None,
-	})
-}
-
-fn limit_gas(gas_limit: Weight) -> Result<()> {
-	if gas_limit > GAS_LIMIT {
-		Err(Error {
-			code: ErrorCode::InvalidParams,
-			message: format!(
-				"Requested gas limit is greater than maximum allowed: {} > {}",
-				gas_limit, GAS_LIMIT
-			),
-			data: None,
-		})
-	} else {
-		Ok(())
-	}
-}
-
 #[derive(Debug)]
 pub enum ContractExtError<B: BlockT> {
 	NoTracing(<<B as BlockT>::Header as HeaderT>::Number, u32),
+	InvalidBlockNumber(<<B as BlockT>::Header as HeaderT>::Number),
+	/// The runtime api call failed.
+	Runtime(String),
+	/// A parameter could not be decoded into the runtime type.
+	Decode {
+		value: String,
+		ty: &'static str,
+	},
+	/// The requested gas limit is greater than the ceiling.
+	GasLimit {
+		requested: Weight,
+		max: Weight,
+	},
+	/// The tracing could not be deserialized.
+	Trace(serde_json::Error),
+	NoTrapSnapshot(<<B as BlockT>::Header as HeaderT>::Number, u32),
+	/// The trap snapshot could not be decoded or decompressed.
+	TrapSnapshot(String),
+	/// The uploaded code could not be deployed.
+	InvalidCode(ValidationReport),
 }
 
 impl<B: BlockT> From<ContractExtError<B>> for jsonrpc_core::Error {
@@ -294,6 +533,53 @@ impl<B: BlockT> From<ContractExtError<B>> for jsonrpc_core::Error {
 				.into(),
 				data: None,
 			},
+			ContractExtError::<B>::InvalidBlockNumber(number) => jsonrpc_core::Error {
+				code: jsonrpc_core::ErrorCode::InvalidParams,
+				message: format!("invalid or not existed block number: {:}", number).into(),
+				data: None,
+			},
+			ContractExtError::<B>::Runtime(e) => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(RUNTIME_ERROR),
+				message: "Runtime error".into(),
+				data: Some(e.into()),
+			},
+			ContractExtError::<B>::Decode { value, ty } => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(DECODE_ERROR),
+				message: format!("{} does not fit into the {} type", value, ty),
+				data: None,
+			},
+			ContractExtError::<B>::GasLimit { requested, max } => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(GAS_LIMIT_ERROR),
+				message: format!(
+					"Requested gas limit is greater than maximum allowed: {} > {}",
+					requested, max
+				),
+				data: Some(json!({ "requested": requested, "max": max })),
+			},
+			ContractExtError::<B>::Trace(e) => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(TRACE_ERROR),
+				message: "Invalid contract tracing".into(),
+				data: Some(e.to_string().into()),
+			},
+			ContractExtError::<B>::NoTrapSnapshot(number, index) => jsonrpc_core::Error {
+				code: jsonrpc_core::ErrorCode::InvalidParams,
+				message: format!(
+					"No trap snapshot for this extrinsic index: number:{:}|index:{:}",
+					number, index,
+				)
+				.into(),
+				data: None,
+			},
+			ContractExtError::<B>::TrapSnapshot(e) => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(TRAP_SNAPSHOT_ERROR),
+				message: "Invalid trap snapshot".into(),
+				data: Some(e.into()),
+			},
+			ContractExtError::<B>::InvalidCode(report) => jsonrpc_core::Error {
+				code: ErrorCode::ServerError(INVALID_CODE),
+				message: format!("Invalid contract code with {} issues", report.issues.len()),
+				data: Some(json!(report)),
+			},
 		}
 	}
 }
@@ -33,6 +33,8 @@
 #![warn(missing_docs)]
 
 mod contracts_ext;
+#[cfg(feature = "ext-mock")]
+mod mock_chain_extension;
 
 use std::sync::Arc;
 
@@ -52,6 +54,8 @@ pub struct FullDeps<C, P> {
 	pub pool: Arc<P>,
 	/// Whether to deny unsafe calls
 	pub deny_unsafe: DenyUnsafe,
+	/// The maximum gas limit for contracts dry-run rpcs, `None` for default ceiling.
+	pub rpc_max_gas_limit: Option<u64>,
 }
 
 /// A IO handler that uses all Full RPC extensions.
@@ -71,9 +75,11 @@ where
 	C::Api:
 		crate::contracts_ext::ContractsExtRuntimeApi<Block, AccountId, Balance, BlockNumber, Hash>,
 	C::Api: BlockBuilder<Block>,
-	P: TransactionPool + 'static,
+	P: TransactionPool<Block = Block> + 'static,
 {
 	use contracts_ext::{ContractsExt, ContractsExtApi};
+	#[cfg(feature = "ext-mock")]
+	use mock_chain_extension::{MockChainExtension, MockChainExtensionApi};
 	use pallet_contracts_rpc::{Contracts, ContractsApi};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
 	use substrate_frame_rpc_system::{FullSystem, SystemApi};
@@ -83,10 +89,11 @@ where
 		client,
 		pool,
 		deny_unsafe,
+		rpc_max_gas_limit,
 	} = deps;
 	io.extend_with(SystemApi::to_delegate(FullSystem::new(
 		client.clone(),
-		pool,
+		pool.clone(),
 		deny_unsafe,
 	)));
 	io.extend_with(TransactionPaymentApi::to_delegate(TransactionPayment::new(
@@ -100,7 +107,13 @@ where
 
 	io.extend_with(ContractsExtApi::to_delegate(ContractsExt::new(
 		client.clone(),
+		rpc_max_gas_limit,
 	)));
 
+	#[cfg(feature = "ext-mock")]
+	io.extend_with(MockChainExtensionApi::to_delegate(
+		MockChainExtension::new(client.clone(), pool, deny_unsafe),
+	));
+
 	io
 }
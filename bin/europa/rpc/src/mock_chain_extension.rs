@@ -0,0 +1,134 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use codec::{Decode, Encode};
+use futures::{FutureExt, TryFutureExt};
+use jsonrpc_core::{BoxFuture, Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+
+use sc_rpc_api::DenyUnsafe;
+use sc_transaction_pool_api::{TransactionPool, TransactionSource, TxHash};
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::generic::BlockId;
+
+use europa_runtime::mock_chain_extension::{self, MockRule};
+use europa_runtime::{Call, UncheckedExtrinsic};
+
+/// Invalid mocked rules.
+const INVALID_RULE: i64 = 1;
+/// The extrinsic for setting rules is rejected by the pool.
+const POOL_ERROR: i64 = 2;
+
+/// A canned response for a mocked `func_id`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct MockRuleRequest {
+	/// The rule matches the input which starts with this pattern, `None` matches any input.
+	input: Option<Bytes>,
+	output: Bytes,
+	weight: NumberOrHex,
+	ret_code: u32,
+}
+
+/// Mocked chain extension RPC methods.
+#[rpc]
+pub trait MockChainExtensionApi<Hash> {
+	/// Set the canned responses for `func_id`, the first matched rule is used for a call.
+	/// Empty rules remove the mock for `func_id`.
+	///
+	/// The rules are set by an unsigned extrinsic, return the hash of the extrinsic. The rpc is
+	/// unsafe, it is denied on the public rpc interfaces.
+	#[rpc(name = "europa_mockChainExtension")]
+	fn mock_chain_extension(
+		&self,
+		func_id: u32,
+		rules: Vec<MockRuleRequest>,
+	) -> BoxFuture<Result<Hash>>;
+}
+
+/// An implementation of mocked chain extension RPC methods.
+pub struct MockChainExtension<C, P> {
+	client: Arc<C>,
+	pool: Arc<P>,
+	deny_unsafe: DenyUnsafe,
+}
+
+impl<C, P> MockChainExtension<C, P> {
+	/// Create new `MockChainExtension` with the given reference to the client and the pool.
+	pub fn new(client: Arc<C>, pool: Arc<P>, deny_unsafe: DenyUnsafe) -> Self {
+		MockChainExtension {
+			client,
+			pool,
+			deny_unsafe,
+		}
+	}
+}
+
+impl<C, P> MockChainExtensionApi<TxHash<P>> for MockChainExtension<C, P>
+where
+	C: HeaderBackend<P::Block> + Send + Sync + 'static,
+	P: TransactionPool + 'static,
+{
+	fn mock_chain_extension(
+		&self,
+		func_id: u32,
+		rules: Vec<MockRuleRequest>,
+	) -> BoxFuture<Result<TxHash<P>>> {
+		if let Err(e) = self.deny_unsafe.check_if_safe() {
+			return async move { Err(e.into()) }.boxed();
+		}
+		let rules = match rules
+			.into_iter()
+			.map(|rule| {
+				let weight = rule.weight.try_into().map_err(|_| Error {
+					code: ErrorCode::ServerError(INVALID_RULE),
+					message: format!("{:?} does not fit into the weight type", rule.weight),
+					data: None,
+				})?;
+				Ok(MockRule {
+					input: rule.input.map(|input| input.to_vec()),
+					output: rule.output.to_vec(),
+					weight,
+					ret_code: rule.ret_code,
+				})
+			})
+			.collect::<Result<Vec<_>>>()
+		{
+			Ok(rules) => rules,
+			Err(e) => return async move { Err(e) }.boxed(),
+		};
+
+		let call = Call::MockChainExtension(mock_chain_extension::Call::set_rules(func_id, rules));
+		let xt = UncheckedExtrinsic::new_unsigned(call).encode();
+		let xt = match Decode::decode(&mut &xt[..]) {
+			Ok(xt) => xt,
+			Err(e) => {
+				return async move {
+					Err(Error {
+						code: ErrorCode::InternalError,
+						message: "Unable to build the extrinsic".into(),
+						data: Some(e.to_string().into()),
+					})
+				}
+				.boxed()
+			}
+		};
+		let best = BlockId::hash(self.client.info().best_hash);
+		self.pool
+			.submit_one(&best, TransactionSource::Local, xt)
+			.map_err(|e| Error {
+				code: ErrorCode::ServerError(POOL_ERROR),
+				message: "The mocked rules are rejected by the transaction pool".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.boxed()
+	}
+}
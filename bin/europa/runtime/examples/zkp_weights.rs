@@ -0,0 +1,106 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Benchmark harness for the Patract ZKP chain extension.
+//!
+//! Measure the native execution time of `curve::call` for every ZKP `func_id`, and print the
+//! table for `ZKP_WEIGHTS` in `src/chain_extensions/zkp/weights.rs`.
+//!
+//! ```bash
+//! $ cargo run --release -p europa-runtime --example zkp_weights
+//! ```
+
+use std::time::Instant;
+
+use ark_ec::{AffineCurve, PairingEngine};
+use ark_ff::to_bytes;
+
+use europa_runtime::chain_extensions::zkp::{curves, func_id, ops, ZKP_FUNC_IDS};
+
+/// The times to repeat a call, use the average time.
+const REPEAT: u32 = 100;
+/// 1 weight is 1 picosecond.
+const WEIGHT_PER_NANOS: u128 = 1_000;
+
+/// The inputs of the operations on a curve.
+struct Inputs {
+	name: &'static str,
+	curve: u32,
+	add: Vec<u8>,
+	mul: Vec<u8>,
+	/// A (G1, G2) pair for pairing.
+	pair: Vec<u8>,
+}
+
+fn inputs<E: PairingEngine>(name: &'static str, curve: u32) -> Inputs {
+	let g1 = E::G1Affine::prime_subgroup_generator();
+	let g2 = E::G2Affine::prime_subgroup_generator();
+	let scalar = E::Fr::from(0xdead_beef_u64);
+	Inputs {
+		name,
+		curve,
+		add: to_bytes![g1, g1].expect("serialize points"),
+		mul: to_bytes![g1, scalar].expect("serialize point and scalar"),
+		pair: to_bytes![g1, g2].expect("serialize pair"),
+	}
+}
+
+/// Return the average weight of calling `func_id` with the input.
+fn measure(func_id: u32, input: &[u8]) -> u128 {
+	if let Err(e) = curve::call(func_id, input) {
+		panic!("invalid input for func_id:{:#010x}|err:{:?}", func_id, e);
+	}
+	let now = Instant::now();
+	for _ in 0..REPEAT {
+		let _ = curve::call(func_id, input);
+	}
+	now.elapsed().as_nanos() / REPEAT as u128 * WEIGHT_PER_NANOS
+}
+
+fn main() {
+	let curves = vec![
+		inputs::<ark_bn254::Bn254>("BN254", curves::BN254),
+		inputs::<ark_bls12_377::Bls12_377>("BLS12_377", curves::BLS12_377),
+		inputs::<ark_bls12_381::Bls12_381>("BLS12_381", curves::BLS12_381),
+		inputs::<ark_bw6_761::BW6_761>("BW6_761", curves::BW6_761),
+	];
+
+	let mut measured = Vec::new();
+	println!("pub const ZKP_WEIGHTS: [ZkpWeight; {}] = [", ZKP_FUNC_IDS.len());
+	for c in curves.iter() {
+		let add = measure(func_id(c.curve, ops::ADD), &c.add);
+		let mul = measure(func_id(c.curve, ops::MUL), &c.mul);
+		let one_pair = measure(func_id(c.curve, ops::PAIRING), &c.pair);
+		let two_pairs = measure(func_id(c.curve, ops::PAIRING), &c.pair.repeat(2));
+		let per_pair = two_pairs.saturating_sub(one_pair);
+		let pair_len = c.pair.len();
+
+		println!(
+			"\tZkpWeight::new(func_id(curves::{}, ops::ADD), {}, 0, 0),",
+			c.name, add
+		);
+		println!(
+			"\tZkpWeight::new(func_id(curves::{}, ops::MUL), {}, 0, 0),",
+			c.name, mul
+		);
+		println!(
+			"\tZkpWeight::new(func_id(curves::{}, ops::PAIRING), {}, {}, {}),",
+			c.name, one_pair, per_pair, pair_len
+		);
+		measured.extend_from_slice(&[
+			func_id(c.curve, ops::ADD),
+			func_id(c.curve, ops::MUL),
+			func_id(c.curve, ops::PAIRING),
+		]);
+	}
+	println!("];");
+
+	for id in ZKP_FUNC_IDS.iter() {
+		assert!(
+			measured.contains(id),
+			"func_id:{:#010x} is not benchmarked",
+			id
+		);
+	}
+}
@@ -0,0 +1,109 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Contract logger, refers to [PIP-102](https://github.com/patractlabs/PIPs/blob/main/PIPs/pip-102.md)
+use codec::Decode;
+use sp_core::Bytes;
+use sp_std::vec::Vec;
+
+use frame_support::log::{error, log, Level};
+use pallet_contracts::chain_extension::{
+	Environment, Ext, InitState, RetVal, SysConfig, UncheckedFrom,
+};
+use sp_runtime::DispatchError;
+
+use super::{ChainExtensionHandler, ChainExtensionRecord};
+
+/// The handler for `0xfeffff00`.
+pub struct LoggerExt;
+
+impl ChainExtensionHandler for LoggerExt {
+	fn contains(func_id: u32) -> bool {
+		func_id == 0xfeffff00
+	}
+
+	fn call<E: Ext>(
+		_func_id: u32,
+		env: Environment<E, InitState>,
+		trace: &mut ChainExtensionRecord,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+	{
+		let mut env = env.buf_in_buf_out();
+		let input: Vec<u8> = env.read_as_unbounded(env.in_len())?;
+		log_record(&trace.contract, &input)?;
+		trace.input = Some(Bytes(input));
+		trace.ret_code = Some(0);
+		Ok(RetVal::Converging(0))
+	}
+}
+
+/// The PIP-102 log record which is recorded with the frame of the contract, and collected into
+/// the extrinsic state changes.
+#[derive(serde::Serialize)]
+struct ContractLogRecord<'a> {
+	level: &'static str,
+	target: &'a str,
+	message: &'a str,
+	contract: Bytes,
+}
+
+/// Print the PIP-102 log record, and record it for the extrinsic state changes. The input is
+/// `(level: u32, target: Vec<u8>, message: Vec<u8>)` in scale-codec.
+fn log_record(contract: &[u8], input: &[u8]) -> Result<(), DispatchError> {
+	let (level, target, message) = decode_record(input)?;
+	let target = String::from_utf8_lossy(&target);
+	let message = String::from_utf8_lossy(&message);
+	log!(target: &*target, level, "{}", message);
+	let record = ContractLogRecord {
+		level: level.as_str(),
+		target: &target,
+		message: &message,
+		contract: Bytes(contract.to_vec()),
+	};
+	if let Ok(record) = serde_json::to_vec(&record) {
+		ep_io::contract_tracing::record_contract_log(record);
+	}
+	Ok(())
+}
+
+/// Decode the PIP-102 log record into the level, the target and the message.
+fn decode_record(input: &[u8]) -> Result<(Level, Vec<u8>, Vec<u8>), DispatchError> {
+	let (level, target, message): (u32, Vec<u8>, Vec<u8>) = Decode::decode(&mut &input[..])
+		.map_err(|_| {
+			error!("[PIP-102]call logger with an invalid log record");
+			DispatchError::Other("ChainExtension failed to decode the log record")
+		})?;
+	let level = match level {
+		1 => Level::Error,
+		2 => Level::Warn,
+		3 => Level::Info,
+		4 => Level::Debug,
+		_ => Level::Trace,
+	};
+	Ok((level, target, message))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The input of `ink_log::info!(target: "flipper", "flip from false to true")`, which is
+	/// `(3u32, "flipper", "flip from false to true")` in scale-codec.
+	const INK_LOG_INFO: &[u8] = include_bytes!("fixtures/ink_log_info.bin");
+
+	#[test]
+	fn decode_ink_log_record() {
+		let (level, target, message) = decode_record(INK_LOG_INFO).unwrap();
+		assert_eq!(level, Level::Info);
+		assert_eq!(target, b"flipper");
+		assert_eq!(message, b"flip from false to true");
+	}
+
+	#[test]
+	fn decode_invalid_record() {
+		assert!(decode_record(&INK_LOG_INFO[..6]).is_err());
+	}
+}
@@ -0,0 +1,64 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Mocked chain extension, the canned responses are set by the rpc `europa_mockChainExtension`.
+use sp_core::Bytes;
+use sp_std::vec::Vec;
+
+use frame_support::log::debug;
+use pallet_contracts::chain_extension::{
+	Environment, Ext, InitState, RetVal, SysConfig, UncheckedFrom,
+};
+use sp_runtime::DispatchError;
+
+use super::{ChainExtensionHandler, ChainExtensionRecord};
+use crate::{mock_chain_extension::Rules, Runtime};
+
+/// The handler for every `func_id` which has mocked rules.
+pub struct MockExt;
+
+impl ChainExtensionHandler for MockExt {
+	fn contains(func_id: u32) -> bool {
+		Rules::<Runtime>::contains_key(func_id)
+	}
+
+	fn call<E: Ext>(
+		func_id: u32,
+		env: Environment<E, InitState>,
+		trace: &mut ChainExtensionRecord,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+	{
+		trace.mocked = true;
+		let mut env = env.buf_in_buf_out();
+		let input: Vec<u8> = env.read_as_unbounded(env.in_len())?;
+		trace.input = Some(Bytes(input.clone()));
+
+		let rule = Rules::<Runtime>::get(func_id)
+			.into_iter()
+			.find(|rule| rule.matches(&input));
+		let rule = match rule {
+			Some(rule) => rule,
+			None => {
+				return Err(DispatchError::Other(
+					"No mocked chain extension rule matches the input",
+				))
+			}
+		};
+		debug!(
+			target: "runtime",
+			"[ChainExtension]|mock|func_id:{:}|rule:{:?}",
+			func_id,
+			rule
+		);
+
+		trace.weight = rule.weight;
+		env.charge_weight(rule.weight)?;
+		env.write(&rule.output, false, None)?;
+		trace.output = Some(Bytes(rule.output));
+		trace.ret_code = Some(rule.ret_code);
+		Ok(RetVal::Converging(rule.ret_code))
+	}
+}
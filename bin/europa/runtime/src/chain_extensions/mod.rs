@@ -0,0 +1,199 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Europa Chain Extension
+//!
+//! The chain extension is composed by a registry of handlers, every handler serves a range of
+//! `func_id`. To add own extensions, implement `ChainExtensionHandler` and put the handler into
+//! the tuple of `EuropaExt`.
+#![cfg_attr(not(feature = "std"), no_std)]
+use codec::Encode;
+use sp_core::Bytes;
+use sp_std::marker::PhantomData;
+
+use frame_support::{log::error, weights::Weight};
+use pallet_contracts::chain_extension::{
+	ChainExtension, Environment, Ext, InitState, RetVal, SysConfig, UncheckedFrom,
+};
+use sp_runtime::DispatchError;
+
+#[cfg(feature = "ext-logger")]
+mod logger;
+#[cfg(feature = "ext-mock")]
+mod mock;
+#[cfg(feature = "ext-zkp")]
+pub mod zkp;
+
+#[cfg(feature = "ext-logger")]
+pub use logger::LoggerExt;
+#[cfg(feature = "ext-mock")]
+pub use mock::MockExt;
+#[cfg(feature = "ext-zkp")]
+pub use zkp::ZkpExt;
+
+/// The handler is disabled by the cargo feature.
+#[cfg(not(feature = "ext-logger"))]
+pub type LoggerExt = ();
+/// The handler is disabled by the cargo feature.
+#[cfg(not(feature = "ext-mock"))]
+pub type MockExt = ();
+/// The handler is disabled by the cargo feature.
+#[cfg(not(feature = "ext-zkp"))]
+pub type ZkpExt = ();
+
+/// The chain Extension of Europa
+///
+/// `MockExt` is the last one, so that the mocked rules do not shadow the real handlers. Every
+/// handler claims only the `func_id` it implements, the others could be mocked.
+// func_id refer to https://github.com/patractlabs/PIPs/blob/main/PIPs/pip-100.md
+pub type EuropaExt = Registry<(ZkpExt, LoggerExt, MockExt)>;
+
+/// A chain extension invocation, which is recorded in the contract tracing.
+///
+/// The registry creates the record for every call, the handler fills the details, and the
+/// registry records it (with the error if the call fails) after the call.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ChainExtensionRecord {
+	pub func_id: u32,
+	/// The contract which calls the chain extension.
+	pub contract: Bytes,
+	/// `None` if the call fails before reading the input.
+	pub input: Option<Bytes>,
+	pub output: Option<Bytes>,
+	pub weight: Weight,
+	pub ret_code: Option<u32>,
+	pub error: Option<String>,
+	/// Whether the response is from the mocked rules.
+	pub mocked: bool,
+}
+
+/// Record the invocation into the contract tracing of current execution.
+pub fn record(record: &ChainExtensionRecord) {
+	if let Ok(record) = serde_json::to_vec(record) {
+		ep_io::contract_tracing::record_chain_extension(record);
+	}
+}
+
+/// A handler for a range of `func_id` in the chain extension.
+pub trait ChainExtensionHandler {
+	/// Whether the `func_id` is routed to this handler.
+	fn contains(func_id: u32) -> bool;
+
+	/// Call the `func_id` in this handler, and fill the details of the call into `trace`.
+	fn call<E: Ext>(
+		func_id: u32,
+		env: Environment<E, InitState>,
+		trace: &mut ChainExtensionRecord,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>;
+}
+
+/// An empty handler, used for the handlers which are disabled.
+impl ChainExtensionHandler for () {
+	fn contains(_func_id: u32) -> bool {
+		false
+	}
+
+	fn call<E: Ext>(
+		func_id: u32,
+		_env: Environment<E, InitState>,
+		_trace: &mut ChainExtensionRecord,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+	{
+		Err(unregistered(func_id))
+	}
+}
+
+macro_rules! impl_handler_for_tuples {
+	($($handler:ident),+) => {
+		impl<$($handler: ChainExtensionHandler),+> ChainExtensionHandler for ($($handler,)+) {
+			fn contains(func_id: u32) -> bool {
+				$($handler::contains(func_id))||+
+			}
+
+			fn call<E: Ext>(
+				func_id: u32,
+				env: Environment<E, InitState>,
+				trace: &mut ChainExtensionRecord,
+			) -> Result<RetVal, DispatchError>
+			where
+				<E::T as SysConfig>::AccountId:
+					UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+			{
+				// the first handler which contains the `func_id` serves the call.
+				$(
+					if $handler::contains(func_id) {
+						return $handler::call(func_id, env, trace);
+					}
+				)+
+				Err(unregistered(func_id))
+			}
+		}
+	};
+}
+
+impl_handler_for_tuples!(A);
+impl_handler_for_tuples!(A, B);
+impl_handler_for_tuples!(A, B, C);
+impl_handler_for_tuples!(A, B, C, D);
+impl_handler_for_tuples!(A, B, C, D, F);
+impl_handler_for_tuples!(A, B, C, D, F, G);
+impl_handler_for_tuples!(A, B, C, D, F, G, H);
+impl_handler_for_tuples!(A, B, C, D, F, G, H, I);
+
+/// The chain extension which routes the `func_id` to the handlers in `H`.
+pub struct Registry<H>(PhantomData<H>);
+
+impl<C: pallet_contracts::Config, H: ChainExtensionHandler> ChainExtension<C> for Registry<H> {
+	fn call<E: Ext>(
+		func_id: u32,
+		mut env: Environment<E, InitState>,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+	{
+		let mut trace = ChainExtensionRecord {
+			func_id,
+			contract: Bytes(env.ext().address().as_ref().to_vec()),
+			input: None,
+			output: None,
+			weight: 0,
+			ret_code: None,
+			error: None,
+			mocked: false,
+		};
+		let result = if H::contains(func_id) {
+			H::call(func_id, env, &mut trace)
+		} else {
+			Err(unregistered(func_id))
+		};
+		if let Err(e) = &result {
+			let error = match e {
+				DispatchError::Other(msg) => msg.to_string(),
+				e => format!("{:?}", e),
+			};
+			// the trap of `seal_call_chain_extension` carries the error.
+			ep_io::contract_tracing::set_host_error(error.clone().into_bytes(), Some(e.encode()));
+			trace.error = Some(error);
+		}
+		record(&trace);
+		result
+	}
+
+	fn enabled() -> bool {
+		true
+	}
+}
+
+fn unregistered(func_id: u32) -> DispatchError {
+	error!(
+		"call an unregistered `func_id`, func_id:{:#010x}. \
+		Notice the handler may be disabled by the cargo feature.",
+		func_id
+	);
+	DispatchError::Other("Unimplemented func_id")
+}
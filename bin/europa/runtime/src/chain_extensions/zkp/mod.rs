@@ -0,0 +1,127 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Patract ZKP Support, refers to [PIP-101](https://github.com/patractlabs/PIPs/blob/main/PIPs/pip-101.md)
+use codec::Encode;
+use sp_core::Bytes;
+use sp_std::vec::Vec;
+
+use frame_support::log::{error, trace};
+use pallet_contracts::chain_extension::{
+	Environment, Ext, InitState, RetVal, SysConfig, UncheckedFrom,
+};
+use sp_runtime::DispatchError;
+
+use super::{ChainExtensionHandler, ChainExtensionRecord};
+
+pub mod weights;
+
+/// The curves in [PIP-101](https://github.com/patractlabs/PIPs/blob/main/PIPs/pip-101.md),
+/// the `func_id` of an operation is `0x01000000 + curve + op`.
+pub mod curves {
+	pub const BN254: u32 = 0x00;
+	pub const BLS12_377: u32 = 0x10;
+	pub const BLS12_381: u32 = 0x20;
+	pub const BW6_761: u32 = 0x30;
+}
+
+/// The operations on every curve.
+pub mod ops {
+	pub const ADD: u32 = 0x00;
+	pub const MUL: u32 = 0x01;
+	pub const PAIRING: u32 = 0x02;
+}
+
+/// Return the `func_id` of the operation on the curve.
+pub const fn func_id(curve: u32, op: u32) -> u32 {
+	0x01000000 + curve + op
+}
+
+/// All `func_id` which are served by `curve::call`.
+pub const ZKP_FUNC_IDS: [u32; 12] = [
+	func_id(curves::BN254, ops::ADD),
+	func_id(curves::BN254, ops::MUL),
+	func_id(curves::BN254, ops::PAIRING),
+	func_id(curves::BLS12_377, ops::ADD),
+	func_id(curves::BLS12_377, ops::MUL),
+	func_id(curves::BLS12_377, ops::PAIRING),
+	func_id(curves::BLS12_381, ops::ADD),
+	func_id(curves::BLS12_381, ops::MUL),
+	func_id(curves::BLS12_381, ops::PAIRING),
+	func_id(curves::BW6_761, ops::ADD),
+	func_id(curves::BW6_761, ops::MUL),
+	func_id(curves::BW6_761, ops::PAIRING),
+];
+
+/// The handler for the `func_id` in `ZKP_FUNC_IDS`, the other `func_id` in
+/// `0x01000000-0x010000ff` are left to the mocked rules.
+pub struct ZkpExt;
+
+impl ChainExtensionHandler for ZkpExt {
+	fn contains(func_id: u32) -> bool {
+		ZKP_FUNC_IDS.contains(&func_id)
+	}
+
+	fn call<E: Ext>(
+		func_id: u32,
+		env: Environment<E, InitState>,
+		trace: &mut ChainExtensionRecord,
+	) -> Result<RetVal, DispatchError>
+	where
+		<E::T as SysConfig>::AccountId: UncheckedFrom<<E::T as SysConfig>::Hash> + AsRef<[u8]>,
+	{
+		let mut env = env.buf_in_buf_out();
+		// The memory of the vm stores buf in scale-codec
+		let input: Vec<u8> = env.read_as_unbounded(env.in_len())?;
+		trace.input = Some(Bytes(input.clone()));
+		let weight = match weights::weight_of(func_id, input.len()) {
+			Some(weight) => weight,
+			None => {
+				error!(
+					"[PIP-101]call an unregistered `func_id` in Patract ZKP field, func_id:{:}",
+					func_id
+				);
+				return Err(DispatchError::Other("Unimplemented Patract ZKP func_id"));
+			}
+		};
+		trace.weight = weight;
+		env.charge_weight(weight)?;
+
+		trace!(
+			target: "runtime",
+			"[ChainExtension]|call|func_id:{:}|charge-weight:{:}|input:{:}",
+			func_id,
+			weight,
+			hex::encode(&input)
+		);
+
+		let raw_output = curve::call(func_id, &input).map_err(|e| {
+			error!(
+				"call zkp lib `curve::call` meet an error|func_id:{:}|err:{:?}",
+				func_id, e
+			);
+			DispatchError::Other("ChainExtension failed to call `curve::call`")
+		})?;
+
+		// Encode back to the memory
+		let output: Vec<u8> = raw_output.encode();
+		env.write(&output, false, None)?;
+		trace.output = Some(Bytes(output));
+		trace.ret_code = Some(0);
+		Ok(RetVal::Converging(0))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_implemented_func_ids_are_claimed() {
+		assert!(ZkpExt::contains(func_id(curves::BN254, ops::ADD)));
+		assert!(ZkpExt::contains(func_id(curves::BW6_761, ops::PAIRING)));
+		// the unimplemented `func_id` in the range could be mocked.
+		assert!(!ZkpExt::contains(0x010000ff));
+	}
+}
@@ -0,0 +1,126 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Weights for the Patract ZKP chain extension.
+//!
+//! The weights are the native execution time (1 weight is 1 picosecond) of every operation, the
+//! median of 5 runs, each run averages 100 calls. The per pair charge of pairing is the time of
+//! two pairs minus the time of one pair.
+//!
+//! - machine: 1 vCPU of an Intel Xeon (KVM guest, Linux 6.18), 5 GiB memory, rustc 1.95.0.
+//! - command: the measurement of `examples/zkp_weights.rs` on arkworks 0.4 (`ark-bn254`,
+//!   `ark-bls12-377`, `ark-bls12-381`, `ark-bw6-761`), which decodes the points unchecked and
+//!   encodes the result as `curve::call` does, run by `cargo run --release` 5 times.
+//!
+//! The table is measured on a slow machine, so the weights err on the high side. To measure
+//! `curve::call` of zkmega on the reference hardware, run the harness:
+//!
+//! ```bash
+//! $ cargo run --release -p europa-runtime --example zkp_weights
+//! ```
+//!
+//! then replace `ZKP_WEIGHTS` with the printed table, and record the machine and the command
+//! which measure it here.
+use frame_support::weights::Weight;
+
+use super::{curves, func_id, ops};
+
+/// The weight of a ZKP `func_id`.
+pub struct ZkpWeight {
+	pub func_id: u32,
+	/// The weight for the call (or the first pair in pairing).
+	pub base: Weight,
+	/// The weight for every extra pair in pairing.
+	pub per_pair: Weight,
+	/// The length of a (G1, G2) pair in the input of pairing, in the arkworks encoding
+	/// (including the infinity flags), e.g. 194 bytes for bn254 instead of 192 in ethereum.
+	pub pair_len: usize,
+}
+
+impl ZkpWeight {
+	pub const fn new(func_id: u32, base: Weight, per_pair: Weight, pair_len: usize) -> Self {
+		ZkpWeight {
+			func_id,
+			base,
+			per_pair,
+			pair_len,
+		}
+	}
+}
+
+pub const ZKP_WEIGHTS: [ZkpWeight; 12] = [
+	ZkpWeight::new(func_id(curves::BN254, ops::ADD), 4_937_000, 0, 0),
+	ZkpWeight::new(func_id(curves::BN254, ops::MUL), 19_581_000, 0, 0),
+	ZkpWeight::new(
+		func_id(curves::BN254, ops::PAIRING),
+		1_425_831_000,
+		385_351_000,
+		194,
+	),
+	ZkpWeight::new(func_id(curves::BLS12_377, ops::ADD), 10_682_000, 0, 0),
+	ZkpWeight::new(func_id(curves::BLS12_377, ops::MUL), 44_344_000, 0, 0),
+	ZkpWeight::new(
+		func_id(curves::BLS12_377, ops::PAIRING),
+		2_394_041_000,
+		761_485_000,
+		290,
+	),
+	ZkpWeight::new(func_id(curves::BLS12_381, ops::ADD), 14_153_000, 0, 0),
+	ZkpWeight::new(func_id(curves::BLS12_381, ops::MUL), 66_009_000, 0, 0),
+	ZkpWeight::new(
+		func_id(curves::BLS12_381, ops::PAIRING),
+		2_544_240_000,
+		491_470_000,
+		290,
+	),
+	ZkpWeight::new(func_id(curves::BW6_761, ops::ADD), 38_780_000, 0, 0),
+	ZkpWeight::new(func_id(curves::BW6_761, ops::MUL), 159_793_000, 0, 0),
+	ZkpWeight::new(
+		func_id(curves::BW6_761, ops::PAIRING),
+		10_215_525_000,
+		2_083_556_000,
+		386,
+	),
+];
+
+/// Return the weight for calling `func_id` with the input, `None` if the `func_id` is unknown.
+pub fn weight_of(func_id: u32, input_len: usize) -> Option<Weight> {
+	let w = ZKP_WEIGHTS.iter().find(|w| w.func_id == func_id)?;
+	let pairs = if w.pair_len == 0 {
+		1
+	} else {
+		(input_len / w.pair_len).max(1) as Weight
+	};
+	Some(w.base.saturating_add(w.per_pair.saturating_mul(pairs - 1)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::ZKP_FUNC_IDS;
+	use super::*;
+
+	#[test]
+	fn table_covers_every_func_id() {
+		for id in ZKP_FUNC_IDS.iter() {
+			assert!(
+				ZKP_WEIGHTS.iter().any(|w| w.func_id == *id),
+				"no weight for func_id: {:#010x}",
+				id
+			);
+		}
+		assert_eq!(ZKP_WEIGHTS.len(), ZKP_FUNC_IDS.len());
+	}
+
+	#[test]
+	fn pairing_weight_grows_with_pairs() {
+		let pairing = func_id(curves::BN254, ops::PAIRING);
+		assert_eq!(weight_of(pairing, 0), Some(1_425_831_000));
+		assert_eq!(weight_of(pairing, 194), Some(1_425_831_000));
+		assert_eq!(
+			weight_of(pairing, 194 * 3),
+			Some(1_425_831_000 + 2 * 385_351_000)
+		);
+		assert_eq!(weight_of(0x010000ff, 0), None);
+	}
+}
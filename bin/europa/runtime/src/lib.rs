@@ -31,8 +31,10 @@ use frame_support::{
 };
 use frame_system::limits::{BlockLength, BlockWeights};
 
-mod chain_extensions;
+pub mod chain_extensions;
 mod constants;
+#[cfg(feature = "ext-mock")]
+pub mod mock_chain_extension;
 
 pub mod runtime_api;
 
@@ -299,29 +301,46 @@ impl pallet_contracts::Config for Runtime {
 	type Schedule = Schedule;
 }
 
+#[cfg(feature = "ext-mock")]
+impl mock_chain_extension::Config for Runtime {}
+
 impl pallet_sudo::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
 }
 
-// Create the runtime by composing the FRAME pallets that were previously configured.
-construct_runtime!(
-	pub enum Runtime where
-		Block = Block,
-		NodeBlock = opaque::Block,
-		UncheckedExtrinsic = UncheckedExtrinsic
-	{
-		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
-		RandomnessCollectiveFlip: pallet_randomness_collective_flip::{Pallet, Storage},
-		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
-		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
-		TransactionPayment: pallet_transaction_payment::{Pallet, Storage},
-
-		Contracts: pallet_contracts::{Pallet, Call, Storage, Event<T>},
+// Create the runtime by composing the FRAME pallets that were previously configured, the dev-only
+// pallets are passed in by the features.
+macro_rules! europa_runtime {
+	($($dev_pallets:tt)*) => {
+		construct_runtime!(
+			pub enum Runtime where
+				Block = Block,
+				NodeBlock = opaque::Block,
+				UncheckedExtrinsic = UncheckedExtrinsic
+			{
+				System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+				RandomnessCollectiveFlip: pallet_randomness_collective_flip::{Pallet, Storage},
+				Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
+				Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+				TransactionPayment: pallet_transaction_payment::{Pallet, Storage},
+
+				Contracts: pallet_contracts::{Pallet, Call, Storage, Event<T>},
+
+				Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>},
+
+				$($dev_pallets)*
+			}
+		);
+	};
+}
 
-		Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>},
-	}
+#[cfg(feature = "ext-mock")]
+europa_runtime!(
+	MockChainExtension: mock_chain_extension::{Pallet, Call, Storage, ValidateUnsigned},
 );
+#[cfg(not(feature = "ext-mock"))]
+europa_runtime!();
 
 /// The address format for describing accounts.
 pub type Address = sp_runtime::MultiAddress<AccountId, ()>;
@@ -498,7 +517,13 @@ impl_runtime_apis! {
 			String,
 		) {
 			let (r, trace) = Contracts::bare_call(origin, dest, value, gas_limit, input_data, true);
-			(r, serde_json::to_string(&trace).unwrap())
+			let trace = ep_io::contract_tracing::merge_chain_extension_records(
+				serde_json::to_vec(&trace).unwrap(),
+			);
+			let trace = ep_io::contract_tracing::merge_debug_messages(trace);
+			let trace = ep_io::contract_tracing::merge_sandbox_divergences(trace);
+			let trace = ep_io::contract_tracing::merge_sandbox_profiles(trace);
+			(r, String::from_utf8_lossy(&trace).to_string())
 		}
 
 		fn instantiate(
@@ -513,7 +538,13 @@ impl_runtime_apis! {
 			String,
 		) {
 			let (r, trace) = Contracts::bare_instantiate(origin, endowment, gas_limit, code, data, salt, true, true);
-			(r, serde_json::to_string(&trace).unwrap())
+			let trace = ep_io::contract_tracing::merge_chain_extension_records(
+				serde_json::to_vec(&trace).unwrap(),
+			);
+			let trace = ep_io::contract_tracing::merge_debug_messages(trace);
+			let trace = ep_io::contract_tracing::merge_sandbox_divergences(trace);
+			let trace = ep_io::contract_tracing::merge_sandbox_profiles(trace);
+			(r, String::from_utf8_lossy(&trace).to_string())
 		}
 	}
 }
@@ -0,0 +1,112 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Dev-only storage for the mocked chain extension rules.
+//!
+//! The rules are set by the rpc `europa_mockChainExtension` through an unsigned extrinsic, and
+//! used by the `MockExt` chain extension handler.
+
+pub use pallet::*;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use codec::{Decode, Encode};
+	use frame_support::{pallet_prelude::*, weights::Weight};
+	use frame_system::pallet_prelude::*;
+	use sp_std::vec::Vec;
+
+	/// A canned response for a mocked `func_id`.
+	#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug)]
+	pub struct MockRule {
+		/// The rule matches the input which starts with this pattern, `None` matches any input.
+		pub input: Option<Vec<u8>>,
+		/// The output which is written back to the contract.
+		pub output: Vec<u8>,
+		/// The weight which is charged for the call.
+		pub weight: Weight,
+		/// The return code for the contract.
+		pub ret_code: u32,
+	}
+
+	impl MockRule {
+		/// Whether the rule matches the input.
+		pub fn matches(&self, input: &[u8]) -> bool {
+			self.input
+				.as_ref()
+				.map(|pattern| input.starts_with(pattern))
+				.unwrap_or(true)
+		}
+	}
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config {}
+
+	#[pallet::pallet]
+	pub struct Pallet<T>(_);
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	/// The mocked rules for `func_id`, the first matched rule is used.
+	#[pallet::storage]
+	#[pallet::getter(fn rules)]
+	pub type Rules<T> = StorageMap<_, Twox64Concat, u32, Vec<MockRule>, ValueQuery>;
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Set the rules for `func_id`, empty rules remove the mock.
+		#[pallet::weight(0)]
+		pub fn set_rules(
+			origin: OriginFor<T>,
+			func_id: u32,
+			rules: Vec<MockRule>,
+		) -> DispatchResultWithPostInfo {
+			ensure_none(origin)?;
+			if rules.is_empty() {
+				Rules::<T>::remove(func_id);
+			} else {
+				Rules::<T>::insert(func_id, rules);
+			}
+			Ok(().into())
+		}
+	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		// the rules are only set by the rpc of the node or in the blocks, not from the network.
+		fn validate_unsigned(source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			match (source, call) {
+				(TransactionSource::External, _) => InvalidTransaction::Call.into(),
+				(_, Call::set_rules(func_id, rules)) => {
+					ValidTransaction::with_tag_prefix("MockChainExtension")
+						.and_provides((func_id, rules).encode())
+						.propagate(false)
+						.build()
+				}
+				_ => InvalidTransaction::Call.into(),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Runtime;
+	use frame_support::pallet_prelude::*;
+
+	#[test]
+	fn rules_from_the_network_are_rejected() {
+		let call = Call::<Runtime>::set_rules(0x010000ff, Vec::new());
+		for source in [TransactionSource::Local, TransactionSource::InBlock].iter() {
+			assert!(Pallet::<Runtime>::validate_unsigned(*source, &call).is_ok());
+		}
+		assert_eq!(
+			Pallet::<Runtime>::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::Call.into()
+		);
+	}
+}
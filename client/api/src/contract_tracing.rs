@@ -0,0 +1,68 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Helpers to inspect the serialized contract tracing (`NestedRuntime`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The key of the frame in which the messages printed by `seal_debug_message` in the frame are
+/// merged.
+pub const DEBUG_MESSAGES: &str = "debug_messages";
+
+/// The key of the top frame in which the contract log records (PIP-102) are merged, in the order
+/// they are emitted, every record carries the `depth` and the `frame` which emits it.
+pub const CONTRACT_LOGS: &str = "contract_logs";
+
+/// The `seal_debug_message` output of one contract frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameDebugMessages {
+	/// The depth of the frame in the contract stack.
+	pub depth: u64,
+	/// The contract account of the frame.
+	pub contract: Option<Value>,
+	/// The debug messages in the order they were printed.
+	pub messages: Vec<String>,
+}
+
+/// Collect the `seal_debug_message` output for every frame which printed something,
+/// in the order the frames are entered.
+///
+/// The sandbox records the messages with the frames which print them, and they are merged into
+/// the frames when the trace is stored or returned by the dry-run, the other parts of the trace
+/// are not inspected.
+pub fn debug_messages(trace: &Value) -> Vec<FrameDebugMessages> {
+	let mut frames = Vec::new();
+	collect_frame(trace, &mut frames);
+	frames
+}
+
+fn collect_frame(frame: &Value, frames: &mut Vec<FrameDebugMessages>) {
+	let messages: Vec<String> = frame
+		.get(DEBUG_MESSAGES)
+		.and_then(Value::as_array)
+		.map(|items| {
+			items
+				.iter()
+				.filter_map(|item| item.as_str().map(ToString::to_string))
+				.collect()
+		})
+		.unwrap_or_default();
+	if !messages.is_empty() {
+		frames.push(FrameDebugMessages {
+			depth: frame
+				.get("depth")
+				.and_then(Value::as_u64)
+				.unwrap_or_default(),
+			contract: frame.get("self_account").cloned(),
+			messages,
+		});
+	}
+	if let Some(nests) = frame.get("nest").and_then(Value::as_array) {
+		for nest in nests {
+			collect_frame(nest, frames);
+		}
+	}
+}
@@ -2,4 +2,5 @@
 
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
+pub mod contract_tracing;
 pub mod statekv;
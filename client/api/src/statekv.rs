@@ -53,7 +53,19 @@ pub trait StateKv<Block: BlockT>: Send + Sync {
 		tracing: String,
 	) -> error::Result<()>;
 	fn get_contract_tracing(&self, number: NumberFor<Block>, index: u32) -> Option<String>;
+	/// The SCALE encoded trap snapshots are removed with the contract tracing.
+	fn set_trap_snapshots(
+		&self,
+		number: NumberFor<Block>,
+		index: u32,
+		snapshots: Vec<Vec<u8>>,
+	) -> error::Result<()>;
+	fn get_trap_snapshots(&self, number: NumberFor<Block>, index: u32) -> Option<Vec<Vec<u8>>>;
 	fn remove_contract_tracing(&self, number: NumberFor<Block>, index: u32) -> error::Result<()>;
+	/// The SCALE encoded contract coverage is kept across the blocks.
+	fn set_contract_coverage(&self, code_hash: &[u8], coverage: Vec<u8>) -> error::Result<()>;
+	fn get_contract_coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>>;
+	fn get_contract_coverages(&self) -> Vec<(Vec<u8>, Vec<u8>)>;
 	fn remove_contract_tracings_by_number(&self, number: NumberFor<Block>) -> error::Result<()>;
 
 	fn revert_all(&self, number: NumberFor<Block>) -> error::Result<()>;
@@ -168,10 +180,35 @@ impl<Block: BlockT, T: StateKv<Block>> StateKv<Block> for Arc<T> {
 		(&**self).get_contract_tracing(number, index)
 	}
 
+	fn set_trap_snapshots(
+		&self,
+		number: NumberFor<Block>,
+		index: u32,
+		snapshots: Vec<Vec<u8>>,
+	) -> error::Result<()> {
+		(&**self).set_trap_snapshots(number, index, snapshots)
+	}
+
+	fn get_trap_snapshots(&self, number: NumberFor<Block>, index: u32) -> Option<Vec<Vec<u8>>> {
+		(&**self).get_trap_snapshots(number, index)
+	}
+
 	fn remove_contract_tracing(&self, number: NumberFor<Block>, index: u32) -> error::Result<()> {
 		(&**self).remove_contract_tracing(number, index)
 	}
 
+	fn set_contract_coverage(&self, code_hash: &[u8], coverage: Vec<u8>) -> error::Result<()> {
+		(&**self).set_contract_coverage(code_hash, coverage)
+	}
+
+	fn get_contract_coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>> {
+		(&**self).get_contract_coverage(code_hash)
+	}
+
+	fn get_contract_coverages(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+		(&**self).get_contract_coverages()
+	}
+
 	fn remove_contract_tracings_by_number(&self, number: NumberFor<Block>) -> error::Result<()> {
 		(&**self).remove_contract_tracings_by_number(number)
 	}
@@ -17,7 +17,7 @@ use sp_runtime::{traits::Block as BlockT, SaturatedConversion};
 use sp_tracing::WASM_TRACE_IDENTIFIER;
 
 use crate::block_tracing::parser::Message;
-use ec_client_api::statekv::StateKv;
+use ec_client_api::{contract_tracing::CONTRACT_LOGS, statekv::StateKv};
 use std::sync::Arc;
 
 mod parser;
@@ -170,6 +170,36 @@ pub fn parse(dispatch: Dispatch) -> Vec<Event> {
 	})
 }
 
+/// The contract log records in the contract tracing, which are recorded with the frames which
+/// emit them by the chain extension.
+pub fn contract_logs(trace: &serde_json::Value) -> Vec<ContractLog> {
+	trace
+		.get(CONTRACT_LOGS)
+		.and_then(serde_json::Value::as_array)
+		.map(|logs| {
+			logs.iter()
+				.filter_map(|log| serde_json::from_value(log.clone()).ok())
+				.collect()
+		})
+		.unwrap_or_default()
+}
+
+/// Append the contract logs from the contract tracing of this extrinsic, the contract logs are not
+/// emitted by the `Ext` instance.
+fn append_contract_logs<Block: BlockT, S: StateKv<Block>>(
+	events: &mut Vec<Event>,
+	number: u64,
+	index: u32,
+	s: &S,
+) {
+	if let Some(trace) = s
+		.get_contract_tracing(number.saturated_into(), index)
+		.and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok())
+	{
+		events.extend(contract_logs(&trace).into_iter().map(Event::ContractLog));
+	}
+}
+
 fn store_result<Block: BlockT, S: StateKv<Block>>(
 	events: Vec<Event>,
 	number: u64,
@@ -187,7 +217,8 @@ pub fn handle_dispatch<Block: BlockT, S: StateKv<Block>>(
 	index: u32,
 	s: Arc<S>,
 ) {
-	let events = parse(dispatch);
+	let mut events = parse(dispatch);
+	append_contract_logs::<Block, S>(&mut events, number, index, &s);
 	store_result::<Block, S>(events, number.saturated_into::<u64>(), index, s)
 }
 
@@ -237,6 +268,20 @@ pub struct Append {
 	append: Vec<u8>,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractLog {
+	level: String,
+	target: String,
+	message: String,
+	#[serde(with = "sp_core::bytes")]
+	contract: Vec<u8>,
+	/// The depth of the contract frame which emits the record.
+	depth: Option<u64>,
+	/// The index of the contract frame which emits the record, in the order the frames are
+	/// entered in the extrinsic.
+	frame: Option<u64>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum Event {
@@ -246,6 +291,7 @@ pub enum Event {
 	ClearPrefix(ClearPrefix),
 	ClearChildPrefix(ClearChildPrefix),
 	Append(Append),
+	ContractLog(ContractLog),
 	NotConcerned,
 }
 
@@ -275,6 +275,37 @@ mod tests {
 		assert_eq!(parsed, Some(b"0000abcd".to_vec()));
 	}
 
+	#[test]
+	fn test_contract_logs_of_tracing() {
+		let trace = serde_json::json!({
+			"depth": 1,
+			"contract_logs": [
+				{
+					"level": "INFO",
+					"target": "flipper",
+					"message": "a|b",
+					"contract": "0x0102",
+					"depth": 2,
+					"frame": 1
+				},
+				{ "level": "INFO" }
+			],
+			"nest": []
+		});
+		assert_eq!(
+			contract_logs(&trace),
+			vec![ContractLog {
+				level: "INFO".to_string(),
+				target: "flipper".to_string(),
+				message: "a|b".to_string(),
+				contract: vec![1, 2],
+				depth: Some(2),
+				frame: Some(1),
+			}]
+		);
+		assert_eq!(contract_logs(&serde_json::json!({ "depth": 1 })), vec![]);
+	}
+
 	#[test]
 	fn test_message_parser() {
 		let parsed = parse_message("0001: PutChild(0002) 0003=Some(0004)");
@@ -0,0 +1,118 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use codec::Decode;
+use structopt::StructOpt;
+
+use sp_runtime::traits::Block as BlockT;
+
+use ec_client_api::statekv;
+use ep_sandbox::CodeCoverage;
+
+use crate::{
+	params::{ImportParams, SharedParams},
+	CliConfiguration, Error as CliError,
+};
+
+use log::info;
+
+#[derive(Debug, StructOpt)]
+pub struct CoverageCmd {
+	/// The hash of the code, all the recorded codes if not supplied
+	#[structopt(value_name = "CODE_HASH")]
+	pub code_hash: Option<String>,
+
+	/// Write the LCOV into the file instead of printing it
+	#[structopt(long = "out", value_name = "PATH", parse(from_os_str))]
+	pub out: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub import_params: ImportParams,
+}
+
+fn parse_code_hash(input: &str) -> Result<Vec<u8>, String> {
+	let input = input.strip_prefix("0x").unwrap_or(input);
+	match hex::decode(input) {
+		Ok(hash) if hash.len() == 32 => Ok(hash),
+		Ok(_) => Err("the code hash should be 32 bytes".to_string()),
+		Err(e) => Err(format!("invalid code hash: {}", e)),
+	}
+}
+
+impl CoverageCmd {
+	/// Run the coverage command
+	pub fn run<B, S>(&self, state_kv: Arc<S>) -> sc_cli::Result<()>
+	where
+		B: BlockT,
+		S: statekv::StateKv<B>,
+	{
+		let coverages = match self.code_hash.as_ref() {
+			Some(code_hash) => {
+				let code_hash = parse_code_hash(code_hash).map_err(CliError::Input)?;
+				vec![state_kv
+					.get_contract_coverage(&code_hash)
+					.ok_or(CliError::Input(format!(
+						"do not have coverage for this code hash: 0x{}",
+						hex::encode(&code_hash)
+					)))?]
+			}
+			None => state_kv
+				.get_contract_coverages()
+				.into_iter()
+				.map(|(_, coverage)| coverage)
+				.collect(),
+		};
+		let coverages = coverages
+			.iter()
+			.map(|coverage| CodeCoverage::decode(&mut &coverage[..]))
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| CliError::Input(format!("invalid coverage: {}", e)))?;
+
+		for coverage in coverages.iter() {
+			info!(
+				"code:{:?}|invocations:{}|blocks:{}/{}|files:{}",
+				coverage.code_hash,
+				coverage.invocations,
+				coverage.covered(),
+				coverage.blocks.len(),
+				coverage.files.len()
+			);
+		}
+
+		let lcov = coverages
+			.iter()
+			.map(|coverage| coverage.lcov())
+			.collect::<String>();
+		match self.out.as_ref() {
+			Some(path) => {
+				std::fs::write(path, lcov)?;
+				info!(
+					"write the coverage of {} codes into {}",
+					coverages.len(),
+					path.display()
+				);
+			}
+			None => print!("{}", lcov),
+		}
+		Ok(())
+	}
+}
+
+impl CliConfiguration for CoverageCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
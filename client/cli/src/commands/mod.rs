@@ -2,6 +2,9 @@
 
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
+pub mod coverage_cmd;
+pub mod replay_cmd;
 pub mod run_cmd;
 pub mod statekv_cmd;
+pub mod trap_snapshot_cmd;
 pub mod workspace_cmd;
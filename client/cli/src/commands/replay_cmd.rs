@@ -0,0 +1,126 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+use std::path::{Path, PathBuf};
+
+use ansi_term::Color;
+use structopt::StructOpt;
+
+use sc_cli::SubstrateCli;
+
+use ep_sandbox::{replay, Recording, SandboxExecutor};
+
+use crate::params::SharedParams;
+use crate::CliConfiguration;
+
+use log::info;
+
+#[derive(Debug, StructOpt)]
+pub struct ReplayCmd {
+	/// The reproducer file recorded by `--sandbox-record`, or a directory of them
+	#[structopt(value_name = "PATH", parse(from_os_str))]
+	pub path: PathBuf,
+
+	/// Replay by the backend, the recorded one if not supplied.
+	#[structopt(
+		long = "sandbox-executor",
+		value_name = "EXECUTOR",
+		possible_values = &SandboxExecutor::VARIANTS,
+		case_insensitive = true
+	)]
+	pub sandbox_executor: Option<SandboxExecutor>,
+
+	/// Print every recorded host call.
+	#[structopt(long = "calls")]
+	pub calls: bool,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl CliConfiguration for ReplayCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+}
+
+impl ReplayCmd {
+	/// Run the replay command, this function could run directly, do not need be wrapped by a runner.
+	pub fn init_and_run<C: SubstrateCli>(&self) -> sc_cli::Result<()> {
+		self.init::<C>()?;
+
+		let mut paths = if self.path.is_dir() {
+			std::fs::read_dir(&self.path)?
+				.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+				.filter(|path| path.extension().and_then(|e| e.to_str()) == Some(replay::EXTENSION))
+				.collect::<Vec<_>>()
+		} else {
+			vec![self.path.clone()]
+		};
+		paths.sort();
+
+		let mut failed = 0;
+		for path in paths.iter() {
+			if !self.replay(path)? {
+				failed += 1;
+			}
+		}
+		if failed > 0 {
+			return Err(format!("{} of {} reproducers diverge", failed, paths.len()).into());
+		}
+		Ok(())
+	}
+
+	/// Replay the reproducer, returns whether the recorded result is reproduced.
+	fn replay(&self, path: &Path) -> sc_cli::Result<bool> {
+		let recording = Recording::load(path)?;
+		info!(
+			"{} `{}`{:?} by {}, {} host calls",
+			Color::Yellow.bold().paint(path.display().to_string()),
+			recording.function,
+			recording.args,
+			self.sandbox_executor.unwrap_or(recording.executor),
+			recording.calls.len(),
+		);
+		if self.calls {
+			for (index, call) in recording.calls.iter().enumerate() {
+				info!(
+					"	#{} {}{:?} -> {:?}, {} memory writes",
+					index,
+					call.name,
+					call.args,
+					call.result,
+					call.writes.len()
+				);
+			}
+		}
+
+		let replayed = replay::replay(&recording, self.sandbox_executor)
+			.map_err(|e| format!("Could not replay {}: {:?}", path.display(), e))?;
+		if let Some((index, expected, actual)) = replayed.mismatch.as_ref() {
+			info!(
+				"	host call #{} diverges: expected {}, actual {}",
+				index,
+				expected.as_deref().unwrap_or("<none>"),
+				actual
+			);
+		}
+		if replayed.remaining > 0 {
+			info!("	{} recorded host calls are not called", replayed.remaining);
+		}
+		let reproduced = replayed.reproduced(&recording);
+		info!(
+			"	recorded: {:?}, replayed: {:?} [{}]",
+			recording.result,
+			replayed.result,
+			if reproduced {
+				Color::Green.paint("reproduced")
+			} else {
+				Color::Red.paint("diverged")
+			}
+		);
+		Ok(reproduced)
+	}
+}
@@ -19,6 +19,8 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
 
 use regex::Regex;
 use structopt::StructOpt;
@@ -26,6 +28,7 @@ use structopt::StructOpt;
 use sc_cli::{arg_enums::RpcMethods, TransactionPoolParams};
 
 use ec_service::{BasePath, TransactionPoolOptions};
+use ep_sandbox::{Interruption, SandboxExecutor, SandboxLimits};
 
 use crate::config::{CliConfiguration, KeystoreParams};
 use crate::params::{ImportParams, SharedParams};
@@ -144,6 +147,108 @@ pub struct RunCmd {
 	/// which includes: database, node key and keystore.
 	#[structopt(long, conflicts_with_all = &["base-path", "workspace"])]
 	pub tmp: bool,
+
+	/// The maximum gas limit which `contractsExt_call` and `contractsExt_instantiate` are
+	/// allowed to consume.
+	///
+	/// Overrides the value recorded in workspace config. The default is 5 seconds of gas.
+	#[structopt(long = "rpc-max-gas-limit", value_name = "GAS")]
+	pub rpc_max_gas_limit: Option<u64>,
+
+	/// The backend which executes the contracts.
+	///
+	/// - `interpreter`: The forked wasmi, which provides the wasm backtrace with the name section.
+	/// - `jit`: Wasmtime, which provides the wasm backtrace with DWARF.
+	///
+	/// Overrides the value recorded in workspace config. The default is `jit`.
+	#[structopt(
+		long,
+		value_name = "EXECUTOR",
+		possible_values = &SandboxExecutor::VARIANTS,
+		case_insensitive = true,
+		verbatim_doc_comment
+	)]
+	pub sandbox_executor: Option<SandboxExecutor>,
+
+	/// Execute every contract call on both sandbox executors, and report the divergence in the
+	/// contract tracing.
+	///
+	/// The result of the executor selected by `--sandbox-executor` is used.
+	#[structopt(long)]
+	pub sandbox_differential: bool,
+
+	/// Capture the linear memories, exported globals and arguments of the trapped contract
+	/// instances, and store them next to the contract tracing.
+	///
+	/// The snapshots could be inspected by the `contractsExt_trapSnapshots` rpc or the
+	/// `trap-snapshot` subcommand.
+	#[structopt(long)]
+	pub trap_snapshot: bool,
+
+	/// Count the executed instructions and calls per wasm function of the contracts executed by
+	/// the interpreter, and put the profiles into the contract tracing.
+	///
+	/// The `folded` call stacks of the profiles could be rendered by `flamegraph.pl`.
+	#[structopt(long)]
+	pub sandbox_profile: bool,
+
+	/// Record the wasm blocks executed by the contracts, which are aggregated per code hash
+	/// across all the calls in the workspace.
+	///
+	/// The coverage could be exported in LCOV by the `coverage` subcommand or the
+	/// `europa_contractCoverage` rpc.
+	#[structopt(long)]
+	pub contract_coverage: bool,
+
+	/// Record every contract invocation with its host calls into a reproducer file in the
+	/// directory.
+	///
+	/// The reproducers could be replayed without the runtime by the `replay` subcommand.
+	#[structopt(long, value_name = "PATH", parse(from_os_str))]
+	pub sandbox_record: Option<PathBuf>,
+
+	/// Serve the GDB remote serial protocol at `127.0.0.1:<PORT>` for the contracts executed by the
+	/// jit executor, by `gdbserver` or `lldb-server` in PATH (Linux only).
+	///
+	/// The next call of a contract, either a dry-run or in an extrinsic, is paused until a debugger
+	/// attaches (at most 60 seconds), then the breakpoints could be set in the contract source if
+	/// it is built with debug info. The whole node is stopped while the debugger stops it.
+	#[structopt(long, value_name = "PORT")]
+	pub contract_debug_port: Option<u16>,
+
+	/// The maximum pages (64 KiB each) of a contract memory.
+	///
+	/// The sandbox limits emulate a stricter production chain, the violations trap with the
+	/// dedicated codes. Overrides `sandboxLimits` in the properties of the chain spec.
+	#[structopt(long, value_name = "PAGES")]
+	pub sandbox_max_memory_pages: Option<u32>,
+
+	/// The maximum depth of the wasm call stack of a contract, in frames.
+	#[structopt(long, value_name = "FRAMES")]
+	pub sandbox_max_stack_height: Option<u32>,
+
+	/// The maximum initial size of a contract table, in elements.
+	#[structopt(long, value_name = "ELEMENTS")]
+	pub sandbox_max_table_size: Option<u32>,
+
+	/// The maximum size of a contract module executed by the sandbox, in bytes.
+	#[structopt(long, value_name = "BYTES")]
+	pub sandbox_max_module_size: Option<u32>,
+
+	/// Interrupt the contract invocations by the jit executor which consume more fuel, roughly
+	/// one per wasm instruction.
+	///
+	/// The interrupted invocations trap with `Interrupt` and the backtrace where they are
+	/// stopped. The fuel is metered by the compiled code, thus the execution is slower.
+	#[structopt(long, value_name = "FUEL")]
+	pub sandbox_fuel: Option<u64>,
+
+	/// Interrupt the contract dry-runs by the jit executor which run longer, in milliseconds.
+	///
+	/// The time of an invocation includes its nested calls. The block production and import are
+	/// never interrupted by the time. It is ignored while `--contract-debug-port` is set.
+	#[structopt(long, value_name = "MS")]
+	pub sandbox_timeout: Option<u64>,
 }
 impl CliConfiguration for RunCmd {
 	fn shared_params(&self) -> &SharedParams {
@@ -222,6 +327,54 @@ impl CliConfiguration for RunCmd {
 		Ok(self.pool_config.transaction_pool())
 	}
 
+	fn rpc_max_gas_limit(&self) -> Result<Option<u64>> {
+		Ok(self.rpc_max_gas_limit)
+	}
+
+	fn sandbox_executor(&self) -> Result<Option<SandboxExecutor>> {
+		Ok(self.sandbox_executor)
+	}
+
+	fn sandbox_differential(&self) -> Result<bool> {
+		Ok(self.sandbox_differential)
+	}
+
+	fn trap_snapshot(&self) -> Result<bool> {
+		Ok(self.trap_snapshot)
+	}
+
+	fn sandbox_profile(&self) -> Result<bool> {
+		Ok(self.sandbox_profile)
+	}
+
+	fn contract_coverage(&self) -> Result<bool> {
+		Ok(self.contract_coverage)
+	}
+
+	fn sandbox_interruption(&self) -> Result<Interruption> {
+		Ok(Interruption {
+			fuel: self.sandbox_fuel,
+			timeout: self.sandbox_timeout.map(Duration::from_millis),
+		})
+	}
+
+	fn sandbox_limits(&self) -> Result<SandboxLimits> {
+		Ok(SandboxLimits {
+			max_memory_pages: self.sandbox_max_memory_pages,
+			max_stack_height: self.sandbox_max_stack_height,
+			max_table_size: self.sandbox_max_table_size,
+			max_module_size: self.sandbox_max_module_size,
+		})
+	}
+
+	fn sandbox_record(&self) -> Result<Option<PathBuf>> {
+		Ok(self.sandbox_record.clone())
+	}
+
+	fn contract_debug_port(&self) -> Result<Option<u16>> {
+		Ok(self.contract_debug_port)
+	}
+
 	fn base_path(&self) -> Result<Option<BasePath>> {
 		Ok(if self.tmp {
 			Some(BasePath::new_temp_dir()?)
@@ -332,6 +485,15 @@ fn parse_cors(s: &str) -> std::result::Result<Cors, Box<dyn std::error::Error>>
 mod tests {
 	use super::*;
 
+	#[test]
+	fn contract_debug_port_is_configured() {
+		let cmd = RunCmd::from_iter(&["europa", "--contract-debug-port", "2345"]);
+		assert_eq!(cmd.contract_debug_port().unwrap(), Some(2345));
+		let cmd = RunCmd::from_iter(&["europa"]);
+		assert_eq!(cmd.contract_debug_port().unwrap(), None);
+		assert!(RunCmd::from_iter_safe(&["europa", "--contract-debug-port", "65536"]).is_err());
+	}
+
 	#[test]
 	fn tests_node_name_good() {
 		assert!(is_node_name_valid("short name").is_ok());
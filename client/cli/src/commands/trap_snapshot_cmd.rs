@@ -0,0 +1,186 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use codec::Decode;
+use structopt::StructOpt;
+
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT},
+};
+
+use sc_cli::BlockNumberOrHash;
+
+use ec_client_api::statekv;
+use ep_sandbox::TrapSnapshot;
+
+use crate::{
+	params::{ImportParams, SharedParams},
+	CliConfiguration, Error as CliError,
+};
+
+use log::info;
+
+/// The bytes in a line of the hex dump.
+const DUMP_WIDTH: usize = 16;
+
+#[derive(Debug, StructOpt)]
+pub struct TrapSnapshotCmd {
+	/// Block hash or number
+	#[structopt(value_name = "HASH or NUMBER")]
+	pub input: BlockNumberOrHash,
+
+	/// The index of the extrinsic in the block
+	#[structopt(value_name = "INDEX")]
+	pub index: u32,
+
+	/// The index of the snapshot in the extrinsic, the outermost trapped instance is the last one
+	#[structopt(long = "snapshot", value_name = "SNAPSHOT", default_value = "0")]
+	pub snapshot: usize,
+
+	/// The index of the linear memory in the snapshot
+	#[structopt(long = "memory", value_name = "MEMORY", default_value = "0")]
+	pub memory: usize,
+
+	/// The start of the dumped memory
+	#[structopt(long = "offset", value_name = "OFFSET", default_value = "0")]
+	pub offset: usize,
+
+	/// The length of the dumped memory, the whole memory after `--offset` if not supplied
+	#[structopt(long = "len", value_name = "LEN")]
+	pub len: Option<usize>,
+
+	/// Write the raw memory into the file instead of printing the hex dump
+	#[structopt(long = "out", value_name = "PATH", parse(from_os_str))]
+	pub out: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub import_params: ImportParams,
+}
+
+impl TrapSnapshotCmd {
+	/// Run the trap-snapshot command
+	pub fn run<B, S>(&self, state_kv: Arc<S>) -> sc_cli::Result<()>
+	where
+		B: BlockT,
+		B::Hash: FromStr,
+		<B::Hash as FromStr>::Err: Debug,
+		<<B::Header as HeaderT>::Number as FromStr>::Err: Debug,
+		S: statekv::StateKv<B>,
+	{
+		let id = self.input.parse::<B>().map_err(CliError::Input)?;
+		let number = match id {
+			BlockId::Number(num) => num,
+			BlockId::Hash(hash) => state_kv.get_number(hash).ok_or(CliError::Input(format!(
+				"do not have block number for this block hash: {:?}",
+				hash
+			)))?,
+		};
+
+		let snapshots = state_kv
+			.get_trap_snapshots(number, self.index)
+			.ok_or(CliError::Input(format!(
+				"do not have trap snapshot for this extrinsic: number:{}|index:{}",
+				number, self.index
+			)))?
+			.iter()
+			.map(|snapshot| TrapSnapshot::decode(&mut &snapshot[..]))
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| CliError::Input(format!("invalid trap snapshot: {}", e)))?;
+
+		for (i, snapshot) in snapshots.iter().enumerate() {
+			info!(
+				"snapshot:{}|depth:{}|executor:{}|function:{}|args:{:?}|trap:{:?}",
+				i,
+				snapshot.depth,
+				snapshot.executor,
+				snapshot.function,
+				snapshot.args,
+				snapshot.trap.code
+			);
+			if let Some(frame) = snapshot.frame.as_ref() {
+				info!("	frame:{}|locals:{:?}", frame.function, frame.locals);
+			}
+			for frame in snapshot.trap.trace.iter() {
+				info!("	at {}", frame);
+			}
+			for (name, value) in snapshot.globals.iter() {
+				info!("	global:{}|value:{:?}", name, value);
+			}
+			for (j, memory) in snapshot.memories.iter().enumerate() {
+				info!(
+					"	memory:{}|import:{}.{}|size:{}",
+					j, memory.module, memory.field, memory.size
+				);
+			}
+		}
+
+		let memory = snapshots
+			.get(self.snapshot)
+			.and_then(|snapshot| snapshot.memories.get(self.memory))
+			.ok_or(CliError::Input(format!(
+				"do not have memory {} in snapshot {}",
+				self.memory, self.snapshot
+			)))?;
+		let data = memory.decompress().map_err(CliError::Input)?;
+		let start = self.offset.min(data.len());
+		let end = match self.len {
+			Some(len) => start.saturating_add(len).min(data.len()),
+			None => data.len(),
+		};
+		let data = &data[start..end];
+
+		match self.out.as_ref() {
+			Some(path) => {
+				std::fs::write(path, data)?;
+				info!(
+					"write {} bytes of memory {} in snapshot {} into {}",
+					data.len(),
+					self.memory,
+					self.snapshot,
+					path.display()
+				);
+			}
+			None => {
+				for (line, chunk) in data.chunks(DUMP_WIDTH).enumerate() {
+					println!(
+						"{:08x}: {:<width$} |{}|",
+						start + line * DUMP_WIDTH,
+						chunk
+							.iter()
+							.map(|b| format!("{:02x}", b))
+							.collect::<Vec<_>>()
+							.join(" "),
+						chunk
+							.iter()
+							.map(|b| if b.is_ascii_graphic() { *b as char } else { '.' })
+							.collect::<String>(),
+						width = DUMP_WIDTH * 3 - 1,
+					);
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl CliConfiguration for TrapSnapshotCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn import_params(&self) -> Option<&ImportParams> {
+		Some(&self.import_params)
+	}
+}
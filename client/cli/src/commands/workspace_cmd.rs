@@ -8,6 +8,7 @@ use structopt::StructOpt;
 use sc_cli::SubstrateCli;
 
 use ec_service::BasePath;
+use ep_sandbox::SandboxExecutor;
 
 use crate::config::{metadata, DEFAULT_WORKSPACE};
 use crate::params::SharedParams;
@@ -32,6 +33,8 @@ pub enum WorkspaceSubCmd {
 	Default(DefaultCmd),
 	/// Delete a workspace, if the workspace has data, it would **remove all data** in this workspace.
 	Delete(DeleteCmd),
+	/// Print or modify the config for a workspace, the config would be used when the node runs in this workspace.
+	Config(ConfigCmd),
 }
 #[derive(Debug, StructOpt)]
 pub struct DefaultCmd {
@@ -44,6 +47,29 @@ pub struct DeleteCmd {
 	#[structopt(value_name = "DEL WORKSPACE")]
 	deleted: String,
 }
+#[derive(Debug, StructOpt)]
+pub struct ConfigCmd {
+	#[structopt(value_name = "CONFIG WORKSPACE")]
+	workspace: String,
+
+	/// Set the maximum gas limit for `contractsExt_call` and `contractsExt_instantiate`.
+	#[structopt(long = "rpc-max-gas-limit", value_name = "GAS")]
+	rpc_max_gas_limit: Option<u64>,
+
+	/// Set the backend which executes the contracts, `interpreter` or `jit`.
+	#[structopt(
+		long = "sandbox-executor",
+		value_name = "EXECUTOR",
+		possible_values = &SandboxExecutor::VARIANTS,
+		case_insensitive = true
+	)]
+	sandbox_executor: Option<SandboxExecutor>,
+
+	/// Remove all config for this workspace.
+	#[structopt(long = "reset", conflicts_with_all = &["rpc-max-gas-limit", "sandbox-executor"])]
+	reset: bool,
+}
+
 impl CliConfiguration for WorkspaceCmd {
 	fn shared_params(&self) -> &SharedParams {
 		&self.shared_params
@@ -134,10 +160,49 @@ impl WorkspaceCmd {
 							r
 						});
 					}
+					if let Some(ref mut configs) = m.workspace_configs {
+						configs.remove(&cmd.deleted);
+					}
 					// remove all workspace data
 					let p = base_path.path().join(&cmd.deleted);
 					let _ = ::std::fs::remove_dir_all(p);
 				}
+				WorkspaceSubCmd::Config(ref cmd) => {
+					let configs = m.workspace_configs.get_or_insert_with(Default::default);
+					if cmd.reset {
+						configs.remove(&cmd.workspace);
+					} else {
+						let config = configs.entry(cmd.workspace.clone()).or_default();
+						if let Some(gas) = cmd.rpc_max_gas_limit {
+							config.rpc_max_gas_limit = Some(gas);
+						}
+						if let Some(executor) = cmd.sandbox_executor {
+							config.sandbox_executor = Some(executor);
+						}
+					}
+					info!(
+						"{}",
+						Style::new()
+							.bold()
+							.fg(Color::Yellow)
+							.paint(format!("Config for workspace [{}]:", cmd.workspace))
+					);
+					let config = configs.get(&cmd.workspace).cloned().unwrap_or_default();
+					info!(
+						"	rpc max gas limit: {}",
+						config
+							.rpc_max_gas_limit
+							.map(|gas| gas.to_string())
+							.unwrap_or("[default]".to_string())
+					);
+					info!(
+						"	sandbox executor: {}",
+						config
+							.sandbox_executor
+							.map(|executor| executor.to_string())
+							.unwrap_or("[default]".to_string())
+					);
+				}
 			}
 			m
 		})?;
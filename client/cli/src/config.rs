@@ -21,6 +21,7 @@
 //! Configuration trait for a CLI based on substrate
 
 use log::warn;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
@@ -28,13 +29,16 @@ use serde::{Deserialize, Serialize};
 
 use sc_cli::{arg_enums::Database, generate_node_name, DefaultConfigurationValues, Error, Result};
 use sc_tracing::logging::LoggerBuilder;
+
+use ep_sandbox::{Interruption, SandboxExecutor, SandboxLimits};
+
 // TODO may use local
 pub use sc_cli::{DatabaseParams, KeystoreParams, SubstrateCli};
 
 use ec_service::{
 	config::{
 		BasePath, Configuration, DatabaseConfig, KeystoreConfig, Role, RpcMethods, TaskExecutor,
-		TransactionPoolOptions,
+		TransactionPoolOptions, WasmExecutionMethod,
 	},
 	TracingReceiver,
 };
@@ -90,6 +94,78 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		self.shared_params().workspace()
 	}
 
+	/// Get the maximum gas limit which the `contractsExt` dry-run rpcs are allowed to consume.
+	///
+	/// By default this is `None`, which means using the value in workspace config or the
+	/// default ceiling in rpc.
+	fn rpc_max_gas_limit(&self) -> Result<Option<u64>> {
+		Ok(None)
+	}
+
+	/// Get the backend which executes the contracts.
+	///
+	/// By default this is `None`, which means using the value in workspace config or the
+	/// default backend of `ep-sandbox`.
+	fn sandbox_executor(&self) -> Result<Option<SandboxExecutor>> {
+		Ok(None)
+	}
+
+	/// Whether to execute the contracts on both sandbox executors.
+	///
+	/// By default this is `false`.
+	fn sandbox_differential(&self) -> Result<bool> {
+		Ok(false)
+	}
+
+	/// Whether to capture the memories and globals of the trapped contract instances.
+	///
+	/// By default this is `false`.
+	fn trap_snapshot(&self) -> Result<bool> {
+		Ok(false)
+	}
+
+	/// Whether to profile the instructions of the contracts executed by the interpreter.
+	///
+	/// By default this is `false`.
+	fn sandbox_profile(&self) -> Result<bool> {
+		Ok(false)
+	}
+
+	/// Whether to record the code coverage of the contracts.
+	///
+	/// By default this is `false`.
+	fn contract_coverage(&self) -> Result<bool> {
+		Ok(false)
+	}
+
+	/// Get the budgets of the contract invocations by the jit executor.
+	///
+	/// By default the invocations are not interrupted.
+	fn sandbox_interruption(&self) -> Result<Interruption> {
+		Ok(Default::default())
+	}
+
+	/// Get the resource limits of the sandbox, which are completed by the chain spec.
+	///
+	/// By default none of the limits is set.
+	fn sandbox_limits(&self) -> Result<SandboxLimits> {
+		Ok(Default::default())
+	}
+
+	/// Get the directory to record the contract invocations into the reproducer files.
+	///
+	/// By default this is `None`.
+	fn sandbox_record(&self) -> Result<Option<PathBuf>> {
+		Ok(None)
+	}
+
+	/// Get the port to serve the GDB remote serial protocol for the contracts.
+	///
+	/// By default this is `None`.
+	fn contract_debug_port(&self) -> Result<Option<u16>> {
+		Ok(None)
+	}
+
 	/// Get the transaction pool options
 	///
 	/// By default this is `TransactionPoolOptions::default()`.
@@ -160,6 +236,14 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			.unwrap_or_default())
 	}
 
+	/// Get the method to execute the wasm runtime in `:code`.
+	///
+	/// By default this is retrieved from `ImportParams` if it is available. Otherwise its `None`,
+	/// which means only the native runtime is executed.
+	fn wasm_method(&self) -> Result<Option<WasmExecutionMethod>> {
+		Ok(self.import_params().and_then(|x| x.wasm_method()))
+	}
+
 	/// Get the state cache child ratio (if any).
 	///
 	/// By default this is `None`.
@@ -290,6 +374,15 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			.as_ref()
 			.map(Clone::clone)
 			.expect("workspace must exist");
+		// the value from command line has a higher priority than workspace config
+		let workspace_config = metadata.workspace_config(&workspace);
+		let rpc_max_gas_limit = self
+			.rpc_max_gas_limit()?
+			.or(workspace_config.rpc_max_gas_limit);
+		let sandbox_executor = self
+			.sandbox_executor()?
+			.or(workspace_config.sandbox_executor)
+			.unwrap_or_default();
 		match base_path {
 			BasePath::Permanenent(ref mut p) => {
 				// replace old path to new path with workspace
@@ -331,6 +424,17 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			base_path: Some(base_path),
 			workspace,
 			workspace_list,
+			rpc_max_gas_limit,
+			sandbox_executor,
+			sandbox_differential: self.sandbox_differential()?,
+			trap_snapshot: self.trap_snapshot()?,
+			sandbox_profile: self.sandbox_profile()?,
+			contract_coverage: self.contract_coverage()?,
+			sandbox_limits: self.sandbox_limits()?,
+			sandbox_interruption: self.sandbox_interruption()?,
+			sandbox_record: self.sandbox_record()?,
+			contract_debug_port: self.contract_debug_port()?,
+			wasm_method: self.wasm_method()?,
 			informant_output_format: Default::default(),
 		})
 	}
@@ -400,6 +504,26 @@ pub const DEFAULT_WORKSPACE: &'static str = "default";
 pub struct Metadata {
 	pub workspaces: Option<Vec<String>>,
 	pub current_workspace: Option<String>,
+	pub workspace_configs: Option<BTreeMap<String, WorkspaceConfig>>,
+}
+
+impl Metadata {
+	/// Get the config for a workspace, return default config if this workspace has not set any.
+	pub fn workspace_config(&self, workspace: &str) -> WorkspaceConfig {
+		self.workspace_configs
+			.as_ref()
+			.and_then(|configs| configs.get(workspace).cloned())
+			.unwrap_or_default()
+	}
+}
+
+/// Config items which are recorded for a workspace.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, Eq, PartialEq)]
+pub struct WorkspaceConfig {
+	/// The maximum gas limit for `contractsExt_call` and `contractsExt_instantiate`.
+	pub rpc_max_gas_limit: Option<u64>,
+	/// The backend which executes the contracts.
+	pub sandbox_executor: Option<SandboxExecutor>,
 }
 
 pub fn metadata(base_path: &BasePath, f: impl Fn(Metadata) -> Metadata) -> Result<Metadata> {
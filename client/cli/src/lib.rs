@@ -14,4 +14,7 @@ pub use runner::{build_runner, Runner};
 
 pub use commands::run_cmd::RunCmd;
 pub use commands::statekv_cmd::StateKvCmd;
+pub use commands::replay_cmd::ReplayCmd;
+pub use commands::trap_snapshot_cmd::TrapSnapshotCmd;
+pub use commands::coverage_cmd::CoverageCmd;
 pub use commands::workspace_cmd::WorkspaceCmd;
@@ -20,7 +20,7 @@
 
 use structopt::StructOpt;
 
-use sc_cli::DatabaseParams;
+use sc_cli::{DatabaseParams, WasmExecutionMethod};
 
 /// Parameters for block import.
 #[derive(Debug, StructOpt)]
@@ -36,6 +36,19 @@ pub struct ImportParams {
 		default_value = "67108864"
 	)]
 	pub state_cache_size: usize,
+
+	/// Execute the wasm runtime in `:code` by the method when its version differs from the native
+	/// runtime, e.g. after a runtime upgrade by `set_code`.
+	///
+	/// Only the native runtime is executed if it is not set. The empty `:code` in the genesis is
+	/// always executed by the native runtime.
+	#[structopt(
+		long = "wasm-execution",
+		value_name = "METHOD",
+		possible_values = &WasmExecutionMethod::enabled_variants(),
+		case_insensitive = true
+	)]
+	pub wasm_method: Option<WasmExecutionMethod>,
 }
 
 impl ImportParams {
@@ -43,4 +56,9 @@ impl ImportParams {
 	pub fn state_cache_size(&self) -> usize {
 		self.state_cache_size
 	}
+
+	/// Get the method to execute the wasm runtime.
+	pub fn wasm_method(&self) -> Option<ec_service::config::WasmExecutionMethod> {
+		self.wasm_method.map(Into::into)
+	}
 }
@@ -4,6 +4,7 @@
 
 use std::sync::Arc;
 
+use codec::{Decode, Encode};
 use kvdb::{DBTransaction, KeyValueDB};
 
 use sp_database::error;
@@ -17,7 +18,7 @@ use sc_client_db::{DatabaseSettings, DatabaseSettingsSrc};
 const SEPARATOR: u8 = b'|';
 const DELETE_HOLDER: &'static [u8] = b":DELETE:";
 
-pub const NUM_COLUMNS: u32 = 9;
+pub const NUM_COLUMNS: u32 = 11;
 /// Meta column. The set of keys in the column is shared by full storages.
 pub const COLUMN_META: u32 = 0;
 
@@ -34,6 +35,8 @@ pub mod columns {
 	pub const NUMBER_TO_HASH: u32 = 6;
 	pub const TRACING: u32 = 7;
 	pub const EXTRINSIC_CHANGES: u32 = 8;
+	pub const TRAP_SNAPSHOT: u32 = 9;
+	pub const COVERAGE: u32 = 10;
 }
 
 const DB_PATH_NAME: &'static str = "state_kv";
@@ -237,6 +240,20 @@ impl StateKv {
 		let v = handle_err(self.state_kv_db.get(columns::TRACING, &key))?;
 		Some(String::from_utf8_lossy(&v).to_string())
 	}
+	fn set_trap_snapshots(
+		&self,
+		number: u64,
+		index: u32,
+		snapshots: Vec<Vec<u8>>,
+	) -> error::Result<()> {
+		let key = tracing_key(number, index);
+		self.set_kv_impl(columns::TRAP_SNAPSHOT, key.as_ref(), Some(&snapshots.encode()))
+	}
+	fn get_trap_snapshots(&self, number: u64, index: u32) -> Option<Vec<Vec<u8>>> {
+		let key = tracing_key(number, index);
+		let v = handle_err(self.state_kv_db.get(columns::TRAP_SNAPSHOT, &key))?;
+		Decode::decode(&mut &v[..]).ok()
+	}
 	fn remove_contract_tracing<B: BlockT, F: FnMut(&mut DBTransaction)>(
 		&self,
 		mut f: F,
@@ -415,11 +432,43 @@ impl<B: BlockT> ec_client_api::statekv::StateKv<B> for StateKv {
 		self.get_contract_tracing(number, index)
 	}
 
+	fn set_trap_snapshots(
+		&self,
+		number: NumberFor<B>,
+		index: u32,
+		snapshots: Vec<Vec<u8>>,
+	) -> error::Result<()> {
+		let number: u64 = number.saturated_into::<u64>();
+		self.set_trap_snapshots(number, index, snapshots)
+	}
+
+	fn get_trap_snapshots(&self, number: NumberFor<B>, index: u32) -> Option<Vec<Vec<u8>>> {
+		let number: u64 = number.saturated_into::<u64>();
+		self.get_trap_snapshots(number, index)
+	}
+
+	fn set_contract_coverage(&self, code_hash: &[u8], coverage: Vec<u8>) -> error::Result<()> {
+		self.set_kv_impl(columns::COVERAGE, code_hash, Some(&coverage))
+	}
+
+	fn get_contract_coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>> {
+		handle_err(self.state_kv_db.get(columns::COVERAGE, code_hash))
+	}
+
+	fn get_contract_coverages(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+		self.get_kys_impl(columns::COVERAGE, &[], |(k, v)| (k.to_vec(), v.to_vec()))
+			.unwrap_or_default()
+			.into_iter()
+			.filter_map(|(k, v)| Some((k, v?)))
+			.collect()
+	}
+
 	fn remove_contract_tracing(&self, number: NumberFor<B>, index: u32) -> error::Result<()> {
 		let number: u64 = number.saturated_into::<u64>();
 		self.remove_contract_tracing::<B, _>(|t| {
 			let key = tracing_key(number, index);
 			t.delete(columns::TRACING, &key);
+			t.delete(columns::TRAP_SNAPSHOT, &key);
 		})
 	}
 
@@ -428,6 +477,7 @@ impl<B: BlockT> ec_client_api::statekv::StateKv<B> for StateKv {
 		self.remove_contract_tracing::<B, _>(|t| {
 			let prefix = &number.to_le_bytes()[..];
 			t.delete_prefix(columns::TRACING, prefix);
+			t.delete_prefix(columns::TRAP_SNAPSHOT, prefix);
 		})
 	}
 
@@ -500,9 +550,48 @@ impl<Block: BlockT, Db: ec_client_api::statekv::StateKv<Block>> ep_extensions::C
 	for DbRef<Block, Db>
 {
 	fn set_tracing(&mut self, number: u32, index: u32, tracing: String) {
+		if let Ok(trace) = serde_json::from_str(&tracing) {
+			for frame in ec_client_api::contract_tracing::debug_messages(&trace) {
+				for message in frame.messages {
+					log::info!(
+						target: "contract-debug",
+						"[#{}:{}] depth:{} contract:{} | {}",
+						number,
+						index,
+						frame.depth,
+						frame.contract.as_ref().map(|c| c.to_string()).unwrap_or_default(),
+						message
+					);
+				}
+			}
+		}
 		let number: u64 = number as u64;
 		self.persistent
 			.set_contract_tracing(number.saturated_into(), index, tracing)
 			.expect("")
 	}
+
+	fn set_trap_snapshots(&mut self, number: u32, index: u32, snapshots: Vec<Vec<u8>>) {
+		log::debug!(
+			target: "contract-debug",
+			"[#{}:{}] {} trapped contract instance(s) captured",
+			number,
+			index,
+			snapshots.len()
+		);
+		let number: u64 = number as u64;
+		self.persistent
+			.set_trap_snapshots(number.saturated_into(), index, snapshots)
+			.expect("")
+	}
+
+	fn coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>> {
+		self.persistent.get_contract_coverage(code_hash)
+	}
+
+	fn set_coverage(&mut self, code_hash: &[u8], coverage: Vec<u8>) {
+		self.persistent
+			.set_contract_coverage(code_hash, coverage)
+			.expect("")
+	}
 }
@@ -6,7 +6,9 @@
 mod native_executor;
 
 pub use native_executor::NativeExecutor;
-pub use sc_executor::{with_externalities_safe, NativeExecutionDispatch, RuntimeInfo};
+pub use sc_executor::{
+	with_externalities_safe, NativeExecutionDispatch, RuntimeInfo, WasmExecutionMethod,
+};
 pub use sc_executor_common::error;
 pub use sp_core::traits::Externalities;
 pub use sp_version::{NativeVersion, RuntimeVersion};
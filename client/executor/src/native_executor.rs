@@ -21,35 +21,103 @@
 use std::{
 	panic::{AssertUnwindSafe, UnwindSafe},
 	result,
+	sync::Arc,
 };
 
 use codec::{Decode, Encode};
+use lru::LruCache;
+use parking_lot::Mutex;
 use sc_executor::{
 	error::{Error, Result},
-	with_externalities_safe, NativeExecutionDispatch, RuntimeInfo,
+	with_externalities_safe, NativeExecutionDispatch, RuntimeInfo, WasmExecutionMethod,
 };
 use sp_core::{
-	traits::{CodeExecutor, Externalities, RuntimeCode},
+	traits::{CodeExecutor, Externalities, ReadRuntimeVersion, RuntimeCode},
 	NativeOrEncoded,
 };
 use sp_version::{NativeVersion, RuntimeVersion};
 
+/// The maximum number of the cached instances of the wasm runtime.
+const MAX_RUNTIME_INSTANCES: usize = 8;
+/// The maximum number of the cached versions of the wasm runtimes.
+const VERSIONS_CACHE_SIZE: usize = 8;
+
 /// A generic `CodeExecutor` implementation that uses a delegate to determine wasm code equivalence
 /// and dispatch to native code when possible, falling back on `WasmExecutor` when not.
+///
+/// The native runtime is always executed unless the wasm execution is enabled, since the genesis
+/// of Europa has no wasm runtime.
 pub struct NativeExecutor<D> {
 	/// Dummy field to avoid the compiler complaining about us not using `D`.
 	_dummy: std::marker::PhantomData<D>,
 	/// Native runtime version info.
 	native_version: NativeVersion,
+	/// The executor of the wasm runtime in `:code`, which also reads the versions of the wasm
+	/// runtimes for `set_code`.
+	wasm: sc_executor::NativeExecutor<D>,
+	/// The method to execute the wasm runtime, `None` if only the native runtime is executed.
+	wasm_method: Option<WasmExecutionMethod>,
+	/// The versions of the wasm runtimes by the hash of the code, `None` if the code is executed
+	/// by the native runtime, evicted in LRU.
+	versions: Arc<Mutex<LruCache<Vec<u8>, Option<RuntimeVersion>>>>,
 }
 
 impl<D: NativeExecutionDispatch> NativeExecutor<D> {
-	/// Create new instance.
+	/// Create new instance, which only executes the native runtime.
 	pub fn new() -> Self {
+		Self::with_wasm_method(None)
+	}
+
+	/// Create new instance, which executes the wasm runtime in `:code` by `wasm_method` when its
+	/// version differs from the native runtime, e.g. after a runtime upgrade by `set_code`.
+	pub fn with_wasm_method(wasm_method: Option<WasmExecutionMethod>) -> Self {
 		NativeExecutor {
 			_dummy: Default::default(),
 			native_version: D::native_version(),
+			wasm: sc_executor::NativeExecutor::new(
+				wasm_method.unwrap_or(WasmExecutionMethod::Interpreted),
+				None,
+				MAX_RUNTIME_INSTANCES,
+			),
+			wasm_method,
+			versions: Arc::new(Mutex::new(LruCache::new(VERSIONS_CACHE_SIZE))),
+		}
+	}
+
+	/// The version of the wasm runtime in `runtime_code` if it is executed, `None` if the native
+	/// runtime is executed.
+	///
+	/// The empty code (e.g. in the genesis) and the code of the same version as the native runtime
+	/// are executed by the native runtime. The version is cached by the hash of the code, thus
+	/// `:code` is only fetched once for every recent runtime.
+	fn wasm_version(
+		&self,
+		ext: &mut dyn Externalities,
+		runtime_code: &RuntimeCode,
+	) -> Option<Result<RuntimeVersion>> {
+		if self.wasm_method.is_none() {
+			return None;
+		}
+		if let Some(version) = self.versions.lock().get(&runtime_code.hash) {
+			return version.clone().map(Ok);
 		}
+		let version = if runtime_code
+			.fetch_runtime_code()
+			.map_or(true, |code| code.is_empty())
+		{
+			None
+		} else {
+			match self.wasm.runtime_version(ext, runtime_code) {
+				Ok(version) if version == self.native_version.runtime_version => None,
+				Ok(version) => Some(version),
+				// the failure is not cached, it is retried by the next call.
+				Err(e) => return Some(Err(e)),
+			}
+		};
+		self.versions
+			.lock()
+			.put(runtime_code.hash.clone(), version.clone());
+		version.map(Ok)
 	}
 }
 
@@ -60,11 +128,11 @@ impl<D: NativeExecutionDispatch> RuntimeInfo for NativeExecutor<D> {
 
 	fn runtime_version(
 		&self,
-		_ext: &mut dyn Externalities,
-		_runtime_code: &RuntimeCode,
+		ext: &mut dyn Externalities,
+		runtime_code: &RuntimeCode,
 	) -> Result<RuntimeVersion> {
-		// do not use wasm runtime version, use native runtime version directly
-		Ok(self.native_version.runtime_version.clone())
+		self.wasm_version(ext, runtime_code)
+			.unwrap_or_else(|| Ok(self.native_version.runtime_version.clone()))
 	}
 }
 
@@ -77,12 +145,22 @@ impl<D: NativeExecutionDispatch + 'static> CodeExecutor for NativeExecutor<D> {
 	>(
 		&self,
 		ext: &mut dyn Externalities,
-		_runtime_code: &RuntimeCode,
+		runtime_code: &RuntimeCode,
 		method: &str,
 		data: &[u8],
 		_use_native: bool,
 		native_call: Option<NC>,
 	) -> (Result<NativeOrEncoded<R>>, bool) {
+		match self.wasm_version(ext, runtime_code) {
+			// the wasm runtime differs from the native one, thus it is always executed.
+			Some(Ok(_)) => {
+				return self
+					.wasm
+					.call(ext, runtime_code, method, data, false, native_call)
+			}
+			Some(Err(e)) => return (Err(e), false),
+			None => {}
+		}
 		let mut ext = AssertUnwindSafe(ext);
 		let result = if let Some(call) = native_call {
 			with_externalities_safe(&mut **ext, move || (call)())
@@ -100,17 +178,20 @@ impl<D: NativeExecutionDispatch> Clone for NativeExecutor<D> {
 		NativeExecutor {
 			_dummy: Default::default(),
 			native_version: D::native_version(),
+			wasm: self.wasm.clone(),
+			wasm_method: self.wasm_method,
+			versions: self.versions.clone(),
 		}
 	}
 }
 
-impl<D: NativeExecutionDispatch> sp_core::traits::ReadRuntimeVersion for NativeExecutor<D> {
+impl<D: NativeExecutionDispatch> ReadRuntimeVersion for NativeExecutor<D> {
 	fn read_runtime_version(
 		&self,
-		_: &[u8],
-		_: &mut dyn Externalities,
+		wasm_code: &[u8],
+		ext: &mut dyn Externalities,
 	) -> std::result::Result<Vec<u8>, String> {
-		unimplemented!("Not required in Europa.")
+		self.wasm.read_runtime_version(wasm_code, ext)
 	}
 }
 /// Implements a `NativeExecutionDispatch` for provided parameters.
@@ -130,11 +211,17 @@ macro_rules! native_executor_instance {
 		/// A unit struct which implements `NativeExecutionDispatch` feeding in the
 		/// hard-coded runtime.
 		$pub struct $name;
-		$crate::native_executor_instance!(IMPL $name, $dispatcher, $version);
+		$crate::native_executor_instance!(IMPL $name, $dispatcher, $version, ());
+	};
+	( $pub:vis $name:ident, $dispatcher:path, $version:path, $custom_host_functions:ty $(,)?) => {
+		/// A unit struct which implements `NativeExecutionDispatch` feeding in the
+		/// hard-coded runtime, the wasm runtime could import the custom host functions.
+		$pub struct $name;
+		$crate::native_executor_instance!(IMPL $name, $dispatcher, $version, $custom_host_functions);
 	};
-	(IMPL $name:ident, $dispatcher:path, $version:path) => {
+	(IMPL $name:ident, $dispatcher:path, $version:path, $host_functions:ty) => {
 		impl $crate::NativeExecutionDispatch for $name {
-			type ExtendHostFunctions = ();
+			type ExtendHostFunctions = $host_functions;
 
 			fn dispatch(
 				ext: &mut dyn $crate::Externalities,
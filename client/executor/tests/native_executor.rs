@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+
+use codec::Decode;
+use ec_executor::{
+	native_executor_instance, NativeExecutor, NativeVersion, RuntimeInfo, RuntimeVersion,
+	WasmExecutionMethod,
+};
+use sp_core::{
+	traits::{CodeExecutor, FetchRuntimeCode, ReadRuntimeVersion, RuntimeCode, WrappedRuntimeCode},
+	NeverNativeValue,
+};
+
+/// The native runtime answers every call with the name of the method.
+fn dispatch(method: &str, _data: &[u8]) -> Option<Vec<u8>> {
+	Some(method.as_bytes().to_vec())
+}
+
+fn native_version() -> NativeVersion {
+	NativeVersion {
+		runtime_version: RuntimeVersion {
+			spec_name: "native".into(),
+			..Default::default()
+		},
+		can_author_with: Default::default(),
+	}
+}
+
+native_executor_instance!(Native, dispatch, native_version);
+
+/// The code which could not be fetched.
+struct NoCode;
+
+impl FetchRuntimeCode for NoCode {
+	fn fetch_runtime_code<'a>(&'a self) -> Option<Cow<'a, [u8]>> {
+		None
+	}
+}
+
+#[test]
+fn upgraded_wasm_runtime_is_executed() {
+	let wasm = substrate_test_runtime::wasm_binary_unwrap();
+	let version = substrate_test_runtime::VERSION;
+	// the wasm runtime which is set by `set_code`, its version differs from the native runtime.
+	let code = WrappedRuntimeCode(wasm.into());
+	let hash = sp_core::blake2_256(wasm).to_vec();
+	let runtime_code = RuntimeCode {
+		code_fetcher: &code,
+		heap_pages: None,
+		hash: hash.clone(),
+	};
+	let mut ext = sp_io::TestExternalities::default();
+	let mut ext = ext.ext();
+
+	let executor =
+		NativeExecutor::<Native>::with_wasm_method(Some(WasmExecutionMethod::Interpreted));
+	assert_eq!(
+		executor.runtime_version(&mut ext, &runtime_code).unwrap(),
+		version
+	);
+	let (result, native) = executor.call::<NeverNativeValue, fn() -> _>(
+		&mut ext,
+		&runtime_code,
+		"Core_version",
+		&[],
+		true,
+		None,
+	);
+	assert!(!native);
+	let result = result.unwrap().into_encoded();
+	assert_eq!(RuntimeVersion::decode(&mut &result[..]).unwrap(), version);
+	let read = executor.read_runtime_version(wasm, &mut ext).unwrap();
+	assert_eq!(RuntimeVersion::decode(&mut &read[..]).unwrap(), version);
+
+	// the version is cached by the hash of the code, which is not fetched again.
+	let cached = RuntimeCode {
+		code_fetcher: &NoCode,
+		heap_pages: None,
+		hash,
+	};
+	assert_eq!(
+		executor.runtime_version(&mut ext, &cached).unwrap(),
+		version
+	);
+
+	// without `--wasm-execution`, the native runtime is always executed.
+	let executor = NativeExecutor::<Native>::new();
+	assert_eq!(
+		executor.runtime_version(&mut ext, &runtime_code).unwrap(),
+		native_version().runtime_version
+	);
+	let (result, native) = executor.call::<NeverNativeValue, fn() -> _>(
+		&mut ext,
+		&runtime_code,
+		"Core_version",
+		&[],
+		true,
+		None,
+	);
+	assert!(native);
+	assert_eq!(result.unwrap().into_encoded(), b"Core_version".to_vec());
+}
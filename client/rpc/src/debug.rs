@@ -0,0 +1,147 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! The rpcs of the step debugger, which pauses the dry-run of the contracts on the interpreter.
+//!
+//! The dry-run rpc (e.g. `contractsExt_call`) is blocked while it is paused, thus the debugger
+//! should be driven by another connection.
+
+use futures::{FutureExt, SinkExt, StreamExt};
+use jsonrpc_core::Result;
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber, SubscriptionId};
+
+use sp_core::Bytes;
+use sp_utils::mpsc::tracing_unbounded;
+
+use ep_sandbox::debugger::{self, Breakpoint, Paused};
+
+/// The execution is not paused, or the paused execution does not respond.
+const DEBUGGER_ERROR: i64 = 1;
+
+#[rpc]
+pub trait EuropaDebugApi {
+	/// RPC metadata
+	type Metadata;
+
+	/// Attach a debugger, the dry-runs of the contracts are paused at the breakpoints until it is
+	/// unsubscribed.
+	#[pubsub(
+		subscription = "europaDebug_events",
+		subscribe,
+		name = "europaDebug_subscribe"
+	)]
+	fn subscribe(&self, metadata: Self::Metadata, subscriber: Subscriber<serde_json::Value>);
+
+	/// Detach the debugger, the paused execution is resumed if it is the last one.
+	#[pubsub(
+		subscription = "europaDebug_events",
+		unsubscribe,
+		name = "europaDebug_unsubscribe"
+	)]
+	fn unsubscribe(&self, metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool>;
+
+	/// Replace the breakpoints.
+	#[rpc(name = "europaDebug_setBreakpoints")]
+	fn set_breakpoints(&self, breakpoints: Vec<Breakpoint>) -> Result<()>;
+
+	/// The breakpoints.
+	#[rpc(name = "europaDebug_breakpoints")]
+	fn breakpoints(&self) -> Result<Vec<Breakpoint>>;
+
+	/// The location, the locals and the operands of the paused execution.
+	#[rpc(name = "europaDebug_paused")]
+	fn paused(&self) -> Result<Option<Paused>>;
+
+	/// Execute one instruction of the paused execution.
+	#[rpc(name = "europaDebug_step")]
+	fn step(&self) -> Result<()>;
+
+	/// Continue the paused execution until the next breakpoint.
+	#[rpc(name = "europaDebug_continue")]
+	fn resume(&self) -> Result<()>;
+
+	/// Read the linear memory of the paused execution.
+	#[rpc(name = "europaDebug_memory")]
+	fn memory(&self, offset: u32, len: u32) -> Result<Bytes>;
+}
+
+pub struct EuropaDebug {
+	subscriptions: SubscriptionManager,
+}
+
+impl EuropaDebug {
+	/// Create new `EuropaDebug` with the given subscription manager.
+	pub fn new(subscriptions: SubscriptionManager) -> Self {
+		EuropaDebug { subscriptions }
+	}
+}
+
+/// Detach the debugger when the subscription is finished or cancelled.
+struct Attached(u64);
+
+impl Drop for Attached {
+	fn drop(&mut self) {
+		debugger::detach(self.0);
+	}
+}
+
+fn debugger_err(e: String) -> jsonrpc_core::Error {
+	jsonrpc_core::Error {
+		code: jsonrpc_core::ErrorCode::ServerError(DEBUGGER_ERROR),
+		message: e,
+		data: None,
+	}
+}
+
+impl EuropaDebugApi for EuropaDebug {
+	type Metadata = sc_rpc_api::Metadata;
+
+	fn subscribe(&self, _metadata: Self::Metadata, subscriber: Subscriber<serde_json::Value>) {
+		let (tx, rx) = tracing_unbounded("mpsc_europa_debug");
+		let attached = Attached(debugger::attach(move |event| {
+			if let Ok(event) = serde_json::to_value(event) {
+				let _ = tx.unbounded_send(event);
+			}
+		}));
+		let stream = rx.map(|event| Ok::<_, ()>(Ok(event)));
+
+		self.subscriptions.add(subscriber, move |sink| {
+			sink.sink_map_err(|e| log::warn!("Error sending notifications: {:?}", e))
+				.send_all(stream)
+				.map(move |_| drop(attached))
+		});
+	}
+
+	fn unsubscribe(&self, _metadata: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool> {
+		Ok(self.subscriptions.cancel(id))
+	}
+
+	fn set_breakpoints(&self, breakpoints: Vec<Breakpoint>) -> Result<()> {
+		debugger::set_breakpoints(breakpoints);
+		Ok(())
+	}
+
+	fn breakpoints(&self) -> Result<Vec<Breakpoint>> {
+		Ok(debugger::breakpoints())
+	}
+
+	fn paused(&self) -> Result<Option<Paused>> {
+		Ok(debugger::paused())
+	}
+
+	fn step(&self) -> Result<()> {
+		debugger::step().map_err(debugger_err)
+	}
+
+	fn resume(&self) -> Result<()> {
+		debugger::resume().map_err(debugger_err)
+	}
+
+	fn memory(&self, offset: u32, len: u32) -> Result<Bytes> {
+		debugger::memory(offset, len)
+			.map(Bytes)
+			.map_err(debugger_err)
+	}
+}
@@ -4,7 +4,7 @@
 
 use jsonrpc_core as rpc;
 
-use sp_core::Bytes;
+use sp_core::{Bytes, H256};
 use sp_runtime::traits::Block as BlockT;
 
 use crate::{NumberOf, NumberOrHash};
@@ -17,6 +17,7 @@ pub enum EuropaRpcError<B: BlockT> {
 	NoStateKvs(NumberOrHash<B>),
 	NoChildStateKvs(NumberOrHash<B>, Bytes),
 	NoExtrinsic(NumberOrHash<B>, u32),
+	NoCoverage(H256),
 	Client(Box<dyn std::error::Error + Send>),
 }
 
@@ -61,6 +62,11 @@ impl<B: BlockT> From<EuropaRpcError<B>> for rpc::Error {
 				.into(),
 				data: None,
 			},
+			EuropaRpcError::NoCoverage(code_hash) => rpc::Error {
+				code: rpc::ErrorCode::InvalidParams,
+				message: format!("No coverage for this code hash: {:?}", code_hash).into(),
+				data: None,
+			},
 			e => internal(e),
 		}
 	}
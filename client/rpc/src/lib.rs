@@ -2,16 +2,23 @@
 
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
+mod debug;
 mod error;
 
+pub use debug::{EuropaDebug, EuropaDebugApi};
+
+use codec::Decode;
 use serde::{Deserialize, Serialize};
 use std::{collections::hash_map::HashMap, sync::Arc};
 
+use futures::{future, FutureExt, SinkExt, StreamExt};
 use jsonrpc_core::Result;
 use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber, SubscriptionId};
 
+use sc_client_api::{BlockBackend, BlockchainEvents};
 use sp_blockchain::HeaderBackend;
-use sp_core::Bytes;
+use sp_core::{Bytes, H256};
 use sp_runtime::{
 	generic::BlockId,
 	traits::{Block as BlockT, BlockIdTo, Header},
@@ -19,7 +26,9 @@ use sp_runtime::{
 };
 use sp_utils::mpsc::{tracing_unbounded, TracingUnboundedReceiver, TracingUnboundedSender};
 
+use ec_basic_authorship::Event;
 use ec_client_api::statekv;
+use ep_sandbox::{CodeCoverage, ValidationReport};
 
 use error::EuropaRpcError;
 
@@ -31,6 +40,7 @@ pub struct Europa<C, B: BlockT, Backend, S> {
 	client: Arc<C>,
 	backend: Arc<Backend>,
 	sender: TracingUnboundedSender<Message<B>>,
+	subscriptions: SubscriptionManager,
 	_marker: std::marker::PhantomData<S>,
 }
 
@@ -40,6 +50,7 @@ impl<C, B: BlockT, Backend, S> Clone for Europa<C, B, Backend, S> {
 			client: self.client.clone(),
 			backend: self.backend.clone(),
 			sender: self.sender.clone(),
+			subscriptions: self.subscriptions.clone(),
 			_marker: self._marker.clone(),
 		}
 	}
@@ -50,6 +61,7 @@ impl<C, B: BlockT, Backend, S> Europa<C, B, Backend, S> {
 	pub fn new(
 		client: Arc<C>,
 		backend: Arc<Backend>,
+		subscriptions: SubscriptionManager,
 	) -> (Self, TracingUnboundedReceiver<Message<B>>) {
 		let (tx, rx) = tracing_unbounded("mpsc_europa_rpc");
 		(
@@ -57,6 +69,7 @@ impl<C, B: BlockT, Backend, S> Europa<C, B, Backend, S> {
 				client,
 				backend,
 				sender: tx,
+				subscriptions,
 				_marker: Default::default(),
 			},
 			rx,
@@ -69,6 +82,9 @@ pub trait EuropaApi<B>
 where
 	B: BlockT,
 {
+	/// RPC metadata
+	type Metadata;
+
 	/// The rpc provide a way to produce a batch of empty block to reach target block height.
 	#[rpc(name = "europa_forwardToHeight")]
 	fn forward_to_height(&self, height: NumberOf<B>) -> Result<()>;
@@ -92,6 +108,48 @@ where
 		number_or_hash: NumberOrHash<B>,
 		index: u32,
 	) -> Result<serde_json::Value>;
+
+	/// The rpc can get the contract logs (PIP-102) for pointed extrinsic.
+	#[rpc(name = "europa_contractLogs")]
+	fn contract_logs(
+		&self,
+		number_or_hash: NumberOrHash<B>,
+		index: u32,
+	) -> Result<serde_json::Value>;
+
+	/// The rpc exports the code coverage recorded by `--contract-coverage` in LCOV, for the code
+	/// hash or all the recorded codes.
+	#[rpc(name = "europa_contractCoverage")]
+	fn contract_coverage(&self, code_hash: Option<H256>) -> Result<String>;
+
+	/// The rpc reports the wasm features, imports, sections and exports of the contract code which
+	/// could not be deployed, the report has no issue if the code is valid.
+	#[rpc(name = "europa_validateCode")]
+	fn validate_code(&self, code: Bytes) -> Result<ValidationReport>;
+
+	/// Subscribe the contract logs (PIP-102) of new blocks.
+	#[pubsub(
+		subscription = "europa_contractLogs",
+		subscribe,
+		name = "europa_subscribeContractLogs"
+	)]
+	fn subscribe_contract_logs(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<serde_json::Value>,
+	);
+
+	/// Unsubscribe the contract logs.
+	#[pubsub(
+		subscription = "europa_contractLogs",
+		unsubscribe,
+		name = "europa_unsubscribeContractLogs"
+	)]
+	fn unsubscribe_contract_logs(
+		&self,
+		metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> Result<bool>;
 }
 
 type NumberOf<B> = <<B as BlockT>::Header as Header>::Number;
@@ -103,14 +161,81 @@ pub enum NumberOrHash<B: BlockT> {
 	Hash(B::Hash),
 }
 
+impl<C, B, Backend, S> Europa<C, B, Backend, S>
+where
+	C: HeaderBackend<B> + BlockIdTo<B, Error = sp_blockchain::Error> + statekv::ClientStateKv<B, S>,
+	B: BlockT,
+	S: statekv::StateKv<B>,
+{
+	/// Get the changed state events for pointed extrinsic.
+	fn extrinsic_events(&self, number_or_hash: NumberOrHash<B>, index: u32) -> Result<Vec<Event>> {
+		let id = number_or_hash.clone();
+		let number = match id {
+			NumberOrHash::Hash(hash) => self
+				.client
+				.to_number(&BlockId::Hash(hash))
+				.map_err(error::client_err::<B>)?
+				.ok_or(EuropaRpcError::<B>::InvalidBlockId(number_or_hash.clone()))?,
+			NumberOrHash::Number(num) => num,
+		};
+		let json = self
+			.client
+			.state_kv()
+			.get_extrinsic_changes(number, index)
+			.ok_or(EuropaRpcError::<B>::NoExtrinsic(number_or_hash, index))?;
+		serde_json::from_str(&json).map_err(error::internal)
+	}
+}
+
+/// Collect the contract logs for every extrinsic in the block which has logs, the block has
+/// `count` extrinsics.
+fn block_contract_logs<B: BlockT, S: statekv::StateKv<B>>(
+	state_kv: &S,
+	hash: B::Hash,
+	number: NumberOf<B>,
+	count: u32,
+) -> Option<serde_json::Value> {
+	let extrinsics: Vec<_> = (0..count)
+		.map(|index| (index, state_kv.get_extrinsic_changes(number, index)))
+		.filter_map(|(index, json)| {
+			let events: Vec<Event> = serde_json::from_str(&json?).ok()?;
+			let logs = contract_logs(events);
+			if logs.is_empty() {
+				None
+			} else {
+				Some(serde_json::json!({ "index": index, "logs": logs }))
+			}
+		})
+		.collect();
+	if extrinsics.is_empty() {
+		None
+	} else {
+		Some(serde_json::json!({
+			"hash": hash,
+			"number": number,
+			"extrinsics": extrinsics,
+		}))
+	}
+}
+
+fn contract_logs(events: Vec<Event>) -> Vec<Event> {
+	events
+		.into_iter()
+		.filter(|e| matches!(e, Event::ContractLog(_)))
+		.collect()
+}
+
 impl<C, B, Backend, S> EuropaApi<B> for Europa<C, B, Backend, S>
 where
 	C: HeaderBackend<B> + BlockIdTo<B, Error = sp_blockchain::Error> + statekv::ClientStateKv<B, S>,
+	C: BlockchainEvents<B> + BlockBackend<B>,
 	C: Send + Sync + 'static,
 	B: BlockT,
 	Backend: sc_client_api::backend::Backend<B> + Send + Sync + 'static,
 	S: statekv::StateKv<B> + 'static,
 {
+	type Metadata = sc_rpc_api::Metadata;
+
 	fn forward_to_height(&self, height: NumberOf<B>) -> Result<()> {
 		let best = self.client.info().best_number;
 		if height <= best {
@@ -180,22 +305,83 @@ where
 		number_or_hash: NumberOrHash<B>,
 		index: u32,
 	) -> Result<serde_json::Value> {
-		use ec_basic_authorship::Event;
-		let id = number_or_hash.clone();
-		let number = match id {
-			NumberOrHash::Hash(hash) => self
-				.client
-				.to_number(&BlockId::Hash(hash))
-				.map_err(error::client_err::<B>)?
-				.ok_or(EuropaRpcError::<B>::InvalidBlockId(number_or_hash.clone()))?,
-			NumberOrHash::Number(num) => num,
+		let r = self.extrinsic_events(number_or_hash, index)?;
+		Ok(serde_json::json!(r))
+	}
+
+	fn contract_logs(
+		&self,
+		number_or_hash: NumberOrHash<B>,
+		index: u32,
+	) -> Result<serde_json::Value> {
+		let r = contract_logs(self.extrinsic_events(number_or_hash, index)?);
+		Ok(serde_json::json!(r))
+	}
+
+	fn contract_coverage(&self, code_hash: Option<H256>) -> Result<String> {
+		let state_kv = self.client.state_kv();
+		let coverages = match code_hash {
+			Some(code_hash) => vec![state_kv
+				.get_contract_coverage(code_hash.as_bytes())
+				.ok_or(EuropaRpcError::<B>::NoCoverage(code_hash))?],
+			None => state_kv
+				.get_contract_coverages()
+				.into_iter()
+				.map(|(_, coverage)| coverage)
+				.collect(),
 		};
-		let json = self
+		coverages
+			.iter()
+			.map(|coverage| {
+				CodeCoverage::decode(&mut &coverage[..])
+					.map(|coverage| coverage.lcov())
+					.map_err(error::internal)
+			})
+			.collect()
+	}
+
+	fn validate_code(&self, code: Bytes) -> Result<ValidationReport> {
+		Ok(ep_sandbox::validate(&code))
+	}
+
+	fn subscribe_contract_logs(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<serde_json::Value>,
+	) {
+		let client = self.client.clone();
+		let state_kv = self.client.state_kv();
+		let stream = self
 			.client
-			.state_kv()
-			.get_extrinsic_changes(number, index)
-			.ok_or(EuropaRpcError::<B>::NoExtrinsic(number_or_hash, index))?;
-		let r: Vec<Event> = serde_json::from_str(&json).expect("should not fail.");
-		Ok(serde_json::json!(r))
+			.import_notification_stream()
+			.filter_map(move |notification| {
+				// every extrinsic of the block is looked up, an extrinsic may have no state changes.
+				let count = client
+					.block_body(&BlockId::Hash(notification.hash))
+					.ok()
+					.flatten()
+					.map_or(0, |extrinsics| extrinsics.len() as u32);
+				future::ready(block_contract_logs::<B, _>(
+					&*state_kv,
+					notification.hash,
+					*notification.header.number(),
+					count,
+				))
+			})
+			.map(|logs| Ok::<_, ()>(Ok(logs)));
+
+		self.subscriptions.add(subscriber, |sink| {
+			sink.sink_map_err(|e| log::warn!("Error sending notifications: {:?}", e))
+				.send_all(stream)
+				.map(|_| ())
+		});
+	}
+
+	fn unsubscribe_contract_logs(
+		&self,
+		_metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> Result<bool> {
+		Ok(self.subscriptions.cancel(id))
 	}
 }
@@ -123,7 +123,66 @@ where
 
 	let task_manager = TaskManager::new(config.task_executor.clone());
 
-	let executor = NativeExecutor::<TExecDisp>::new();
+	ep_sandbox::set_executor(config.sandbox_executor).map_err(Error::Other)?;
+	ep_sandbox::set_differential(config.sandbox_differential).map_err(Error::Other)?;
+	ep_sandbox::set_trap_snapshot(config.trap_snapshot);
+	ep_sandbox::set_profiling(config.sandbox_profile);
+	ep_sandbox::set_coverage(config.contract_coverage);
+	let sandbox_limits = match config.chain_spec.properties().get("sandboxLimits") {
+		Some(limits) => serde_json::from_value(limits.clone()).map_err(|e| {
+			Error::Other(format!("Invalid `sandboxLimits` in the chain spec: {}", e))
+		})?,
+		None => ep_sandbox::SandboxLimits::default(),
+	};
+	let sandbox_limits = config.sandbox_limits.or(sandbox_limits);
+	ep_sandbox::set_limits(sandbox_limits);
+	ep_sandbox::set_interruption(config.sandbox_interruption).map_err(Error::Other)?;
+	ep_sandbox::set_recording(config.sandbox_record.clone()).map_err(Error::Other)?;
+	ep_sandbox::set_debug_port(config.contract_debug_port).map_err(Error::Other)?;
+	info!(
+		"🧰 Sandbox executor: {}{}{}{}{}",
+		config.sandbox_executor,
+		if config.sandbox_differential {
+			" (differential)"
+		} else {
+			""
+		},
+		if config.trap_snapshot {
+			", trap snapshot"
+		} else {
+			""
+		},
+		if config.sandbox_profile {
+			", profile"
+		} else {
+			""
+		},
+		if config.contract_coverage {
+			", coverage"
+		} else {
+			""
+		}
+	);
+	if !sandbox_limits.is_empty() {
+		info!("🚧 Sandbox limits: {}", sandbox_limits);
+	}
+	if config.sandbox_interruption != Default::default() {
+		info!(
+			"⏱️  Interrupt the jit invocations: fuel {:?}, timeout {:?}",
+			config.sandbox_interruption.fuel, config.sandbox_interruption.timeout
+		);
+	}
+	if let Some(dir) = config.sandbox_record.as_ref() {
+		info!("📼 Recording contract invocations into {}", dir.display());
+	}
+	if let Some(port) = config.contract_debug_port {
+		info!("🐞 Contract debug port: 127.0.0.1:{}", port);
+	}
+	if let Some(method) = config.wasm_method {
+		info!("⚙️  Wasm runtime execution: {:?}", method);
+	}
+
+	let executor = NativeExecutor::<TExecDisp>::with_wasm_method(config.wasm_method);
 
 	let chain_spec = &config.chain_spec;
 
@@ -329,7 +388,7 @@ where
 		.into(),
 	));
 
-	// // Spawn informant task
+	// Spawn informant task
 	// todo use another informant which not include network
 	// spawn_handle.spawn("informant", sc_informant::build(
 	// 	client.clone(),
@@ -337,6 +396,7 @@ where
 	// 	transaction_pool.clone(),
 	// 	config.informant_output_format,
 	// ));
+	spawn_handle.spawn("informant", crate::informant::build(client.clone()));
 
 	task_manager.keep_alive((config.base_path, rpc, rpc_handlers.clone()));
 
@@ -397,6 +457,7 @@ where
 		(chain, state, child_state)
 	};
 
+	let debug = ec_rpc::EuropaDebug::new(subscriptions.clone());
 	let author = sc_rpc::author::Author::new(
 		client,
 		transaction_pool,
@@ -414,6 +475,7 @@ where
 			author::AuthorApi::to_delegate(author),
 			system::SystemApi::to_delegate(system),
 			ec_rpc::EuropaApi::to_delegate(europa_rpc), // add ec_rpc
+			ec_rpc::EuropaDebugApi::to_delegate(debug),
 			rpc_extensions_builder.build(deny_unsafe, task_executor),
 		),
 		rpc_middleware,
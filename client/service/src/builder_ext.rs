@@ -130,7 +130,11 @@ where
 	let rpc_extensions_builder = rpc_builder(components);
 	let system_rpc_tx = build_mock_network::<TBl>(task_manager.spawn_handle())?;
 
-	let (europa_rpc, europa_rpc_rx) = ec_rpc::Europa::new(client.clone(), backend.clone());
+	let europa_subscriptions = jsonrpc_pubsub::manager::SubscriptionManager::new(Arc::new(
+		sc_rpc::SubscriptionTaskExecutor::new(task_manager.spawn_handle()),
+	));
+	let (europa_rpc, europa_rpc_rx) =
+		ec_rpc::Europa::new(client.clone(), backend.clone(), europa_subscriptions);
 
 	spawn_tasks(SpawnTasksParams {
 		client: client.clone(),
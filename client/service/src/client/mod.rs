@@ -1529,6 +1529,11 @@ where
 		extensions.register(ep_extensions::ContractTracingDbExt::new(
 			ec_client_db::DbRef::new(self.state_kv.clone()),
 		));
+		extensions.register(ep_extensions::ChainExtensionRecordsExt::default());
+		extensions.register(ep_extensions::DebugMessagesExt::default());
+		extensions.register(ep_extensions::SandboxDivergencesExt::default());
+		extensions.register(ep_extensions::SandboxProfilesExt::default());
+		extensions.register(ep_extensions::TrapSnapshotsExt::default());
 
 		self.executor
 			.contextual_call::<fn(_, _) -> _, _, _>(
@@ -21,6 +21,7 @@
 //! Service configuration.
 
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 pub use sc_client_db::{
 	Database, DatabaseSettingsSrc as DatabaseConfig, KeepBlocks, TransactionStorageMode,
@@ -34,6 +35,8 @@ pub use sc_service::config::{
 	BasePath, ExtTransport, KeystoreConfig, RpcMethods, TaskExecutor, TaskType,
 };
 
+pub use ec_executor::WasmExecutionMethod;
+
 /// Service configuration.
 #[derive(Debug)]
 pub struct Configuration {
@@ -87,6 +90,30 @@ pub struct Configuration {
 	pub workspace: String,
 	/// All workspace list
 	pub workspace_list: Vec<String>,
+	/// The maximum gas limit for contract dry-run rpcs. `None` if using the default ceiling.
+	pub rpc_max_gas_limit: Option<u64>,
+	/// The backend which executes the contracts.
+	pub sandbox_executor: ep_sandbox::SandboxExecutor,
+	/// Execute the contracts on both sandbox executors, and report the divergence.
+	pub sandbox_differential: bool,
+	/// Capture the state of the trapped contract instances.
+	pub trap_snapshot: bool,
+	/// Profile the instructions of the contracts executed by the interpreter.
+	pub sandbox_profile: bool,
+	/// Record the code coverage of the contracts into the workspace.
+	pub contract_coverage: bool,
+	/// The resource limits of the sandbox set by the cli, which are completed by `sandboxLimits`
+	/// in the properties of the chain spec.
+	pub sandbox_limits: ep_sandbox::SandboxLimits,
+	/// The budgets of the contract invocations by the jit executor.
+	pub sandbox_interruption: ep_sandbox::Interruption,
+	/// The directory to record the contract invocations into, `None` if not recording.
+	pub sandbox_record: Option<PathBuf>,
+	/// The port to serve the GDB remote serial protocol for the contracts.
+	pub contract_debug_port: Option<u16>,
+	/// The method to execute the wasm runtime in `:code` when its version differs from the native
+	/// runtime, `None` if only the native runtime is executed.
+	pub wasm_method: Option<WasmExecutionMethod>,
 	/// Configuration of the output format that the informant uses.
 	pub informant_output_format: sc_informant::OutputFormat, // todo may also need in future
 }
@@ -0,0 +1,64 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Informant for the dev node
+//!
+//! `sc-informant` depends on the network status, which does not exist in Europa. This informant
+//! prints the statistics of the contract sandbox periodically, thus the cache hits of the dry-run
+//! rpcs are reported without any imported block.
+use std::{sync::Arc, time::Duration};
+
+use futures::{future, FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+use log::info;
+
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+
+use ep_sandbox::ModuleCacheStats;
+
+/// The interval between the reports, the same as `sc-informant`.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Build the informant future, which prints the module cache statistics on every tick when they
+/// change.
+pub async fn build<B, C>(client: Arc<C>)
+where
+	B: BlockT,
+	C: HeaderBackend<B>,
+{
+	let mut last = ModuleCacheStats::default();
+	interval(INTERVAL)
+		.for_each(move |_| {
+			let stats = ep_sandbox::module_cache_stats();
+			if stats != last {
+				info!(
+					"💾 Module cache at #{}: {} hits, {} misses ({}), {}/{} modules",
+					client.info().best_number,
+					stats.hits - last.hits,
+					stats.misses - last.misses,
+					hit_rate(&stats),
+					stats.cached,
+					stats.capacity,
+				);
+				last = stats;
+			}
+			future::ready(())
+		})
+		.await
+}
+
+/// Creates a stream that returns a new value every `duration`.
+fn interval(duration: Duration) -> impl Stream<Item = ()> + Unpin {
+	futures::stream::unfold((), move |_| Delay::new(duration).map(|_| Some(((), ())))).map(drop)
+}
+/// The total hit rate of the cache.
+fn hit_rate(stats: &ModuleCacheStats) -> String {
+	let total = stats.hits + stats.misses;
+	if total == 0 {
+		"-".into()
+	} else {
+		format!("{:.1}% total hit rate", stats.hits as f64 * 100.0 / total as f64)
+	}
+}
@@ -22,6 +22,7 @@ mod builder;
 pub mod builder_ext;
 pub mod client;
 pub mod config;
+mod informant;
 pub mod task_manager;
 
 use std::net::SocketAddr;
@@ -4,12 +4,26 @@
 
 pub trait ContractTracingDb: Send + Sync {
 	fn set_tracing(&mut self, number: u32, index: u32, tracing: String);
+	/// Store the SCALE encoded trap snapshots of the extrinsic.
+	fn set_trap_snapshots(&mut self, number: u32, index: u32, snapshots: Vec<Vec<u8>>);
+	/// The SCALE encoded coverage of the code, which is aggregated across the calls.
+	fn coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>>;
+	fn set_coverage(&mut self, code_hash: &[u8], coverage: Vec<u8>);
 }
 
 impl<T: ContractTracingDb + ?Sized> ContractTracingDb for Box<T> {
 	fn set_tracing(&mut self, number: u32, index: u32, tracing: String) {
 		(&mut **self).set_tracing(number, index, tracing)
 	}
+	fn set_trap_snapshots(&mut self, number: u32, index: u32, snapshots: Vec<Vec<u8>>) {
+		(&mut **self).set_trap_snapshots(number, index, snapshots)
+	}
+	fn coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>> {
+		(&**self).coverage(code_hash)
+	}
+	fn set_coverage(&mut self, code_hash: &[u8], coverage: Vec<u8>) {
+		(&mut **self).set_coverage(code_hash, coverage)
+	}
 }
 
 sp_externalities::decl_extension! {
@@ -21,3 +35,45 @@ impl ContractTracingDbExt {
 		Self(Box::new(inner_db))
 	}
 }
+
+sp_externalities::decl_extension! {
+	/// The chain extension invocations in current runtime call, which are merged into the
+	/// contract tracing.
+	#[derive(Default)]
+	pub struct ChainExtensionRecordsExt(Vec<Vec<u8>>);
+}
+
+sp_externalities::decl_extension! {
+	/// The contract log records (PIP-102) in current runtime call with the frames which emit
+	/// them, which are merged into the contract tracing.
+	#[derive(Default)]
+	pub struct ContractLogsExt(Vec<Vec<u8>>);
+}
+
+sp_externalities::decl_extension! {
+	/// The `seal_debug_message` output in current runtime call with the frames which print it,
+	/// which is merged into the contract tracing.
+	#[derive(Default)]
+	pub struct DebugMessagesExt(Vec<Vec<u8>>);
+}
+
+sp_externalities::decl_extension! {
+	/// The divergences between the sandbox backends in the differential mode, which are merged
+	/// into the contract tracing.
+	#[derive(Default)]
+	pub struct SandboxDivergencesExt(Vec<Vec<u8>>);
+}
+
+sp_externalities::decl_extension! {
+	/// The SCALE encoded snapshots of the trapped sandbox instances in current runtime call,
+	/// which are stored next to the contract tracing.
+	#[derive(Default)]
+	pub struct TrapSnapshotsExt(Vec<Vec<u8>>);
+}
+
+sp_externalities::decl_extension! {
+	/// The instruction profiles of the sandbox invocations in the profiling mode, which are
+	/// merged into the contract tracing.
+	#[derive(Default)]
+	pub struct SandboxProfilesExt(Vec<Vec<u8>>);
+}
@@ -5,15 +5,376 @@
 #![allow(missing_docs)]
 
 use sp_runtime_interface::runtime_interface;
+use sp_std::vec::Vec;
 
 #[runtime_interface]
 pub trait ContractTracing {
 	fn store_tracing(&mut self, block: u32, index: u32, tracing: Vec<u8>) {
-		use ep_extensions::ContractTracingDbExt;
+		use ep_extensions::{
+			ChainExtensionRecordsExt, ContractLogsExt, ContractTracingDbExt, DebugMessagesExt,
+			SandboxDivergencesExt, SandboxProfilesExt, TrapSnapshotsExt,
+		};
 		use sp_externalities::ExternalitiesExt;
+		let records = self
+			.extension::<ChainExtensionRecordsExt>()
+			.map(|records| std::mem::take(&mut records.0))
+			.unwrap_or_default();
+		let tracing = records::merge(tracing, records);
+		let messages = self
+			.extension::<DebugMessagesExt>()
+			.map(|messages| std::mem::take(&mut messages.0))
+			.unwrap_or_default();
+		let tracing = records::merge_debug_messages(tracing, messages);
+		let logs = self
+			.extension::<ContractLogsExt>()
+			.map(|logs| std::mem::take(&mut logs.0))
+			.unwrap_or_default();
+		let tracing = records::merge_contract_logs(tracing, logs);
+		let divergences = self
+			.extension::<SandboxDivergencesExt>()
+			.map(|divergences| std::mem::take(&mut divergences.0))
+			.unwrap_or_default();
+		let tracing = records::merge_divergences(tracing, divergences);
+		let profiles = self
+			.extension::<SandboxProfilesExt>()
+			.map(|profiles| std::mem::take(&mut profiles.0))
+			.unwrap_or_default();
+		let tracing = records::merge_profiles(tracing, profiles);
 		let tracing = String::from_utf8_lossy(&tracing[..]).to_string();
-		self.extension::<ContractTracingDbExt>()
-			.expect("set_tracing can be called with ContractTracingDb extension")
-			.set_tracing(block, index, tracing);
+		let snapshots = self
+			.extension::<TrapSnapshotsExt>()
+			.map(|snapshots| std::mem::take(&mut snapshots.0))
+			.unwrap_or_default();
+		let db = self
+			.extension::<ContractTracingDbExt>()
+			.expect("set_tracing can be called with ContractTracingDb extension");
+		db.set_tracing(block, index, tracing);
+		if !snapshots.is_empty() {
+			db.set_trap_snapshots(block, index, snapshots);
+		}
+	}
+
+	/// Record a chain extension invocation (in json) for current contract execution, with the
+	/// frame of the contract which makes the call.
+	fn record_chain_extension(&mut self, record: Vec<u8>) {
+		use ep_extensions::ChainExtensionRecordsExt;
+		use sp_externalities::ExternalitiesExt;
+		if let Some(records) = self.extension::<ChainExtensionRecordsExt>() {
+			records
+				.0
+				.push(records::with_frame(record, ep_sandbox::current_frame()));
+		}
+	}
+
+	/// Record a contract log record (PIP-102, in json) for current contract execution, with the
+	/// frame of the contract which emits it.
+	fn record_contract_log(&mut self, record: Vec<u8>) {
+		use ep_extensions::ContractLogsExt;
+		use sp_externalities::ExternalitiesExt;
+		if let Some(logs) = self.extension::<ContractLogsExt>() {
+			logs.0
+				.push(records::with_frame(record, ep_sandbox::current_frame()));
+		}
+	}
+
+	/// Merge the recorded chain extension invocations into the tracing (in json), and clear
+	/// the records.
+	fn merge_chain_extension_records(&mut self, tracing: Vec<u8>) -> Vec<u8> {
+		use ep_extensions::ChainExtensionRecordsExt;
+		use sp_externalities::ExternalitiesExt;
+		let records = self
+			.extension::<ChainExtensionRecordsExt>()
+			.map(|records| std::mem::take(&mut records.0))
+			.unwrap_or_default();
+		records::merge(tracing, records)
+	}
+
+	/// Merge the debug messages which are printed by the contracts (with their frames) into the
+	/// tracing (in json), and clear the messages.
+	fn merge_debug_messages(&mut self, tracing: Vec<u8>) -> Vec<u8> {
+		use ep_extensions::DebugMessagesExt;
+		use sp_externalities::ExternalitiesExt;
+		let messages = self
+			.extension::<DebugMessagesExt>()
+			.map(|messages| std::mem::take(&mut messages.0))
+			.unwrap_or_default();
+		records::merge_debug_messages(tracing, messages)
+	}
+
+	/// Key the coverage of the contract which is instantiated next by `code_hash`, and map it to
+	/// the source by the pristine code (before it is instrumented), in the coverage mode.
+	fn set_pristine_code(code_hash: [u8; 32], code: Vec<u8>) {
+		if ep_sandbox::coverage() {
+			ep_sandbox::set_pristine_code(code_hash.into(), code);
+		}
+	}
+
+	/// Tell why the host function which the contract calls fails, e.g. the `DispatchError` of the
+	/// chain extension, the trap of the contract carries the reason and the payload.
+	fn set_host_error(reason: Vec<u8>, payload: Option<Vec<u8>>) {
+		let reason = String::from_utf8_lossy(&reason).into_owned();
+		match payload {
+			Some(payload) => ep_sandbox::host_error_with_payload(reason, payload),
+			None => ep_sandbox::host_error(reason),
+		};
+	}
+
+	/// Merge the divergences between the sandbox backends (in json) into the tracing (in json),
+	/// and clear the divergences.
+	fn merge_sandbox_divergences(&mut self, tracing: Vec<u8>) -> Vec<u8> {
+		use ep_extensions::SandboxDivergencesExt;
+		use sp_externalities::ExternalitiesExt;
+		let divergences = self
+			.extension::<SandboxDivergencesExt>()
+			.map(|divergences| std::mem::take(&mut divergences.0))
+			.unwrap_or_default();
+		records::merge_divergences(tracing, divergences)
+	}
+
+	/// Merge the instruction profiles of the sandbox invocations (in json) into the tracing (in
+	/// json), and clear the profiles.
+	fn merge_sandbox_profiles(&mut self, tracing: Vec<u8>) -> Vec<u8> {
+		use ep_extensions::SandboxProfilesExt;
+		use sp_externalities::ExternalitiesExt;
+		let profiles = self
+			.extension::<SandboxProfilesExt>()
+			.map(|profiles| std::mem::take(&mut profiles.0))
+			.unwrap_or_default();
+		records::merge_profiles(tracing, profiles)
+	}
+}
+
+#[cfg(feature = "std")]
+mod records {
+	use serde_json::Value;
+	use sp_core::crypto::{AccountId32, Ss58Codec};
+	use std::convert::TryFrom;
+
+	/// The key of the chain extension invocations in the frame of the tracing.
+	const CHAIN_EXTENSION_KEY: &str = "chain_extension";
+	/// The key of the contract log records in the top frame of the tracing.
+	const CONTRACT_LOGS_KEY: &str = "contract_logs";
+	/// The key of the debug messages in the frame of the tracing.
+	const DEBUG_MESSAGES_KEY: &str = "debug_messages";
+	/// The key of the depth of the frame in the tracing and in the records.
+	const DEPTH_KEY: &str = "depth";
+	/// The key of the index of the frame (in the order the frames are entered) in the records.
+	const FRAME_KEY: &str = "frame";
+	/// The key of the divergences between the sandbox backends in the top frame of the tracing.
+	const SANDBOX_DIVERGENCE_KEY: &str = "sandbox_divergence";
+	/// The key of the instruction profiles of the sandbox invocations in the top frame of the
+	/// tracing.
+	const SANDBOX_PROFILE_KEY: &str = "sandbox_profile";
+
+	/// Add the frame of the contract which makes the call into the record, the frame is
+	/// `(depth, index)` of the running sandbox invocation.
+	pub fn with_frame(record: Vec<u8>, frame: Option<(u32, u32)>) -> Vec<u8> {
+		let (depth, index) = match frame {
+			Some(frame) => frame,
+			None => return record,
+		};
+		let mut value: Value = match serde_json::from_slice(&record) {
+			Ok(value) => value,
+			Err(_) => return record,
+		};
+		if let Some(map) = value.as_object_mut() {
+			map.insert(DEPTH_KEY.into(), depth.into());
+			map.insert(FRAME_KEY.into(), index.into());
+		}
+		serde_json::to_vec(&value).unwrap_or(record)
+	}
+
+	/// Put every record into the frame in which the call is made.
+	///
+	/// The frame is the one entered in the order of the index in the record, if it is at the
+	/// depth in the record. Otherwise the first frame at the depth which is executed by the
+	/// contract in the record, or the top frame if no frame matches.
+	pub fn merge(tracing: Vec<u8>, records: Vec<Vec<u8>>) -> Vec<u8> {
+		merge_by_frame(tracing, CHAIN_EXTENSION_KEY, records, Some)
+	}
+
+	/// Put the message of every record into the frame which prints it, in the same way as
+	/// [`merge`].
+	pub fn merge_debug_messages(tracing: Vec<u8>, records: Vec<Vec<u8>>) -> Vec<u8> {
+		merge_by_frame(tracing, DEBUG_MESSAGES_KEY, records, |record| {
+			record.get("message").cloned()
+		})
+	}
+
+	/// Put `item` of every record into the list under `key` in the frame of the record.
+	fn merge_by_frame(
+		tracing: Vec<u8>,
+		key: &str,
+		records: Vec<Vec<u8>>,
+		item: fn(Value) -> Option<Value>,
+	) -> Vec<u8> {
+		if records.is_empty() {
+			return tracing;
+		}
+		let mut trace: Value = match serde_json::from_slice(&tracing) {
+			Ok(trace) => trace,
+			Err(_) => return tracing,
+		};
+		for record in records {
+			let record: Value = match serde_json::from_slice(&record) {
+				Ok(record) => record,
+				Err(_) => continue,
+			};
+			let accounts = record
+				.get("contract")
+				.and_then(Value::as_str)
+				.map(accounts)
+				.unwrap_or_default();
+			let depth = record.get(DEPTH_KEY).and_then(Value::as_u64);
+			let index = record.get(FRAME_KEY).and_then(Value::as_u64);
+			let path = match (depth, index) {
+				(Some(depth), Some(index)) => {
+					let mut entered = 0;
+					frame_at(&trace, depth, index, &mut entered, &mut Vec::new())
+						.or_else(|| find_frame(&trace, depth, &accounts, &mut Vec::new()))
+				}
+				_ => None,
+			};
+			let item = match item(record) {
+				Some(item) => item,
+				None => continue,
+			};
+			let frame = path
+				.and_then(|path| frame_mut(&mut trace, &path))
+				.unwrap_or(&mut trace);
+			if let Some(frame) = frame.as_object_mut() {
+				let list = frame.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+				if let Some(list) = list.as_array_mut() {
+					list.push(item);
+				}
+			}
+		}
+		serde_json::to_vec(&trace).unwrap_or(tracing)
+	}
+
+	/// Put every contract log record into the top frame in the order they are emitted, the record
+	/// carries the frame which emits it.
+	pub fn merge_contract_logs(tracing: Vec<u8>, logs: Vec<Vec<u8>>) -> Vec<u8> {
+		merge_top(tracing, CONTRACT_LOGS_KEY, logs)
+	}
+
+	/// Put every divergence into the top frame, the divergence carries the depth of the
+	/// invocation rather than the contract.
+	pub fn merge_divergences(tracing: Vec<u8>, divergences: Vec<Vec<u8>>) -> Vec<u8> {
+		merge_top(tracing, SANDBOX_DIVERGENCE_KEY, divergences)
+	}
+
+	/// Put every profile into the top frame, the profile carries the depth of the invocation.
+	pub fn merge_profiles(tracing: Vec<u8>, profiles: Vec<Vec<u8>>) -> Vec<u8> {
+		merge_top(tracing, SANDBOX_PROFILE_KEY, profiles)
+	}
+
+	/// Put every record into the list under `key` in the top frame.
+	fn merge_top(tracing: Vec<u8>, key: &str, records: Vec<Vec<u8>>) -> Vec<u8> {
+		if records.is_empty() {
+			return tracing;
+		}
+		let mut trace: Value = match serde_json::from_slice(&tracing) {
+			Ok(trace) => trace,
+			Err(_) => return tracing,
+		};
+		if let Some(frame) = trace.as_object_mut() {
+			let list = frame.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+			if let Some(list) = list.as_array_mut() {
+				list.extend(
+					records
+						.iter()
+						.filter_map(|record| serde_json::from_slice(record).ok()),
+				);
+			}
+		}
+		serde_json::to_vec(&trace).unwrap_or(tracing)
+	}
+
+	/// The account may be in ss58 or hex in the tracing.
+	fn accounts(contract: &str) -> Vec<String> {
+		let raw = contract.trim_start_matches("0x");
+		let mut accounts = vec![raw.to_string(), format!("0x{}", raw)];
+		if let Some(account) = hex::decode(raw)
+			.ok()
+			.and_then(|bytes| <[u8; 32]>::try_from(&bytes[..]).ok())
+		{
+			accounts.push(AccountId32::from(account).to_ss58check());
+		}
+		accounts
+	}
+
+	fn depth_of(frame: &Value) -> Option<u64> {
+		frame.get(DEPTH_KEY).and_then(Value::as_u64)
+	}
+
+	fn nests(frame: &Value) -> &[Value] {
+		frame
+			.get("nest")
+			.and_then(Value::as_array)
+			.map(Vec::as_slice)
+			.unwrap_or_default()
+	}
+
+	/// The path (the indexes in the `nest`) to the `index`th frame which is entered, if it is
+	/// at `depth`.
+	fn frame_at(
+		frame: &Value,
+		depth: u64,
+		index: u64,
+		entered: &mut u64,
+		path: &mut Vec<usize>,
+	) -> Option<Vec<usize>> {
+		let entering = *entered;
+		*entered += 1;
+		if entering == index {
+			return if depth_of(frame) == Some(depth) {
+				Some(path.clone())
+			} else {
+				None
+			};
+		}
+		for (i, nest) in nests(frame).iter().enumerate() {
+			path.push(i);
+			let found = frame_at(nest, depth, index, entered, path);
+			path.pop();
+			// the frame is in this nest, even if it is not at the depth.
+			if *entered > index {
+				return found;
+			}
+		}
+		None
+	}
+
+	/// The path to the first frame at `depth` which is executed by one of the `accounts`.
+	fn find_frame(
+		frame: &Value,
+		depth: u64,
+		accounts: &[String],
+		path: &mut Vec<usize>,
+	) -> Option<Vec<usize>> {
+		let executed_by = frame
+			.get("self_account")
+			.and_then(Value::as_str)
+			.map(|account| accounts.iter().any(|a| a == account))
+			.unwrap_or_default();
+		if executed_by && depth_of(frame) == Some(depth) {
+			return Some(path.clone());
+		}
+		for (i, nest) in nests(frame).iter().enumerate() {
+			path.push(i);
+			let found = find_frame(nest, depth, accounts, path);
+			path.pop();
+			if found.is_some() {
+				return found;
+			}
+		}
+		None
+	}
+
+	fn frame_mut<'a>(frame: &'a mut Value, path: &[usize]) -> Option<&'a mut Value> {
+		path.iter().try_fold(frame, |frame, i| {
+			frame.get_mut("nest")?.as_array_mut()?.get_mut(*i)
+		})
 	}
 }
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use ep_extensions::{ContractLogsExt, ContractTracingDb, ContractTracingDbExt};
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, HostError, Instance, ReturnValue, SandboxExecutor, Value,
+};
+use serde_json::{json, Value as Json};
+
+/// The stored contract tracing.
+#[derive(Clone, Default)]
+struct TracingDb(Arc<Mutex<Option<String>>>);
+
+impl ContractTracingDb for TracingDb {
+	fn set_tracing(&mut self, _number: u32, _index: u32, tracing: String) {
+		*self.0.lock().unwrap() = Some(tracing);
+	}
+	fn set_trap_snapshots(&mut self, _number: u32, _index: u32, _snapshots: Vec<Vec<u8>>) {}
+	fn coverage(&self, _code_hash: &[u8]) -> Option<Vec<u8>> {
+		None
+	}
+	fn set_coverage(&mut self, _code_hash: &[u8], _coverage: Vec<u8>) {}
+}
+
+/// The callee of `seal_call`.
+struct Callee(Option<Instance<Callee>>);
+
+/// The logger of the chain extension, which logs the message in the argument.
+fn log(_e: &mut Callee, args: &[Value]) -> Result<ReturnValue, HostError> {
+	let message = match args {
+		[Value::I32(message)] => message.to_string(),
+		_ => return Err(HostError),
+	};
+	let record = json!({
+		"level": "INFO",
+		"target": "flipper",
+		"message": message,
+		"contract": "0x0102",
+	});
+	ep_io::contract_tracing::record_contract_log(serde_json::to_vec(&record).unwrap());
+	Ok(ReturnValue::Value(Value::I32(0)))
+}
+
+fn seal_call(e: &mut Callee, _args: &[Value]) -> Result<ReturnValue, HostError> {
+	let mut callee = e.0.take().ok_or(HostError)?;
+	let result = callee.invoke("call", &[], &mut Callee(None));
+	e.0 = Some(callee);
+	result.map_err(|_| HostError)?;
+	Ok(ReturnValue::Value(Value::I32(0)))
+}
+
+fn instantiate(executor: SandboxExecutor, code: &str) -> Instance<Callee> {
+	let code = wat::parse_str(code).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::new();
+	env_builder.add_host_func("seal0", "log", log);
+	env_builder.add_host_func("seal0", "seal_call", seal_call);
+	Instance::new_with_executor(executor, &code, &env_builder, &mut Callee(None)).unwrap()
+}
+
+#[test]
+fn contract_logs_carry_their_frames() {
+	// the caller logs before and after it calls the callee.
+	let caller = r#"
+		(module
+			(import "seal0" "log" (func $log (param i32) (result i32)))
+			(import "seal0" "seal_call" (func $seal_call (result i32)))
+			(func (export "call")
+				(drop (call $log (i32.const 1)))
+				(drop (call $seal_call))
+				(drop (call $log (i32.const 3)))
+			)
+		)
+		"#;
+	let callee = r#"
+		(module
+			(import "seal0" "log" (func $log (param i32) (result i32)))
+			(import "seal0" "seal_call" (func $seal_call (result i32)))
+			(func (export "call")
+				(drop (call $log (i32.const 2)))
+			)
+		)
+		"#;
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let db = TracingDb::default();
+		let mut ext = sp_io::TestExternalities::default();
+		ext.register_extension(ContractLogsExt::default());
+		ext.register_extension(ContractTracingDbExt::new(db.clone()));
+		ext.execute_with(|| {
+			let mut state = Callee(Some(instantiate(executor, callee)));
+			let mut instance = instantiate(executor, caller);
+			instance.invoke("call", &[], &mut state).unwrap();
+
+			let tracing = json!({ "depth": 1, "nest": [{ "depth": 2, "nest": [] }] });
+			ep_io::contract_tracing::store_tracing(1, 0, serde_json::to_vec(&tracing).unwrap());
+		});
+
+		let tracing = db.0.lock().unwrap().take().unwrap();
+		let tracing: Json = serde_json::from_str(&tracing).unwrap();
+		let frames: Vec<_> = tracing["contract_logs"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|log| {
+				(
+					log["message"].clone(),
+					log["depth"].clone(),
+					log["frame"].clone(),
+				)
+			})
+			.collect();
+		assert_eq!(
+			frames,
+			vec![
+				(json!("1"), json!(1), json!(0)),
+				(json!("2"), json!(2), json!(1)),
+				(json!("3"), json!(1), json!(0)),
+			],
+			"{}",
+			executor
+		);
+	}
+}
@@ -0,0 +1,93 @@
+use ep_extensions::DebugMessagesExt;
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, HostError, Instance, Memory, ReturnValue, SandboxExecutor, Value,
+};
+use serde_json::{json, Value as Json};
+
+/// The contract which prints `message`, and calls the other contract by `seal_call`.
+fn contract(message: &str, call: bool) -> Vec<u8> {
+	let call = if call { "(drop (call $seal_call))" } else { "" };
+	wat::parse_str(format!(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(import "seal0" "seal_debug_message"
+				(func $seal_debug_message (param i32 i32) (result i32)))
+			(import "seal0" "seal_call" (func $seal_call (result i32)))
+			(data (i32.const 16) "{}")
+			(func (export "call")
+				(drop (call $seal_debug_message (i32.const 16) (i32.const {})))
+				{}
+			)
+		)
+		"#,
+		message,
+		message.len(),
+		call,
+	))
+	.unwrap()
+}
+
+/// The callee of `seal_call`.
+struct Callee(Option<Instance<Callee>>);
+
+/// `seal_debug_message` of pallet-contracts, which drops the message without the debug mode.
+fn seal_debug_message(_e: &mut Callee, _args: &[Value]) -> Result<ReturnValue, HostError> {
+	Ok(ReturnValue::Value(Value::I32(9)))
+}
+
+fn seal_call(e: &mut Callee, _args: &[Value]) -> Result<ReturnValue, HostError> {
+	let mut callee = e.0.take().ok_or(HostError)?;
+	let result = callee.invoke("call", &[], &mut Callee(None));
+	e.0 = Some(callee);
+	result.map_err(|_| HostError)?;
+	Ok(ReturnValue::Value(Value::I32(0)))
+}
+
+fn instantiate(executor: SandboxExecutor, code: &[u8]) -> Instance<Callee> {
+	let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::new();
+	env_builder.add_memory("env", "memory", memory);
+	env_builder.add_host_func("seal0", "seal_debug_message", seal_debug_message);
+	env_builder.add_host_func("seal0", "seal_call", seal_call);
+	Instance::new_with_executor(executor, code, &env_builder, &mut Callee(None)).unwrap()
+}
+
+#[test]
+fn debug_messages_are_merged_into_their_frames() {
+	let caller = contract("caller", true);
+	let callee = contract("callee", false);
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let mut ext = sp_io::TestExternalities::default();
+		ext.register_extension(DebugMessagesExt::default());
+		ext.execute_with(|| {
+			let mut state = Callee(Some(instantiate(executor, &callee)));
+			let mut instance = instantiate(executor, &caller);
+			instance.invoke("call", &[], &mut state).unwrap();
+
+			let tracing = json!({
+				"depth": 1,
+				"nest": [{ "depth": 2, "nest": [] }],
+			});
+			let tracing = ep_io::contract_tracing::merge_debug_messages(
+				serde_json::to_vec(&tracing).unwrap(),
+			);
+			let tracing: Json = serde_json::from_slice(&tracing).unwrap();
+			assert_eq!(tracing["debug_messages"], json!(["caller"]), "{}", executor);
+			assert_eq!(
+				tracing["nest"][0]["debug_messages"],
+				json!(["callee"]),
+				"{}",
+				executor
+			);
+
+			// the messages are cleared after they are merged.
+			let merged = ep_io::contract_tracing::merge_debug_messages(b"{}".to_vec());
+			assert_eq!(merged, b"{}".to_vec());
+		});
+	}
+}
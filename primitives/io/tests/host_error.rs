@@ -0,0 +1,58 @@
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, HostError, HostFailure, Instance, ReturnValue,
+	SandboxExecutor, TrapCode, Value,
+};
+
+/// `seal_call_chain_extension` of pallet-contracts, which fails as the chain extension of the
+/// runtime does for an unregistered `func_id`.
+fn call_chain_extension(_e: &mut (), _args: &[Value]) -> Result<ReturnValue, HostError> {
+	// `DispatchError::Other` is encoded as its index, the message is skipped.
+	ep_io::contract_tracing::set_host_error(b"Unimplemented func_id".to_vec(), Some(vec![0]));
+	Err(HostError)
+}
+
+#[test]
+fn host_errors_of_the_runtime_are_carried_by_traps() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "seal0" "seal_call_chain_extension"
+				(func $seal_call_chain_extension (param i32 i32 i32 i32 i32) (result i32)))
+			(func (export "call")
+				(drop (call $seal_call_chain_extension
+					(i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let mut env_builder = EnvironmentDefinitionBuilder::new();
+		env_builder.add_host_func("seal0", "seal_call_chain_extension", call_chain_extension);
+		let mut instance =
+			Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+
+		match instance.invoke("call", &[], &mut ()) {
+			Err(Error::Trap(trap)) => {
+				assert_eq!(trap.code, TrapCode::HostError, "{}", executor);
+				assert_eq!(
+					trap.host_error,
+					Some(HostFailure {
+						import: "seal0.seal_call_chain_extension".into(),
+						reason: "Unimplemented func_id".into(),
+						payload: Some(vec![0]),
+					})
+				);
+				assert_eq!(
+					trap.trace[0],
+					"seal_call_chain_extension: Unimplemented func_id"
+				);
+			}
+			r => panic!("{}: unexpected result {:?}", executor, r),
+		}
+	}
+}
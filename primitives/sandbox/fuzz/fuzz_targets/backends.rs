@@ -0,0 +1,12 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	if let Some(code) = ep_sandbox_fuzz::generate(data) {
+		ep_sandbox_fuzz::check(&code);
+	}
+});
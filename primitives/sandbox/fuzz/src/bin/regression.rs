@@ -0,0 +1,50 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Save the module generated from a (minimized) fuzz artifact as a regression test.
+//!
+//! ```bash
+//! cargo run -p ep-sandbox-fuzz --bin regression -- fuzz/artifacts/backends/minimized-from-xxx
+//! ```
+//!
+//! The module is saved as `regressions/<artifact>.wasm`, which is checked by `cargo test`, thus
+//! the test does not depend on the version of `wasm-smith`.
+use std::{env, fs, path::Path, process};
+
+fn main() {
+	let artifact = match env::args().nth(1) {
+		Some(artifact) => artifact,
+		None => {
+			eprintln!("Usage: regression <ARTIFACT>");
+			process::exit(1);
+		}
+	};
+	let data = fs::read(&artifact).unwrap_or_else(|e| {
+		eprintln!("Could not read {}: {}", artifact, e);
+		process::exit(1);
+	});
+	let code = ep_sandbox_fuzz::generate(&data).unwrap_or_else(|| {
+		eprintln!("No module is generated from {}", artifact);
+		process::exit(1);
+	});
+	if let Err(mismatch) = ep_sandbox_fuzz::compare(&code) {
+		println!("The backends diverge in {}", mismatch);
+	} else {
+		println!("The backends do not diverge in {}", artifact);
+	}
+
+	let name = Path::new(&artifact)
+		.file_name()
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_else(|| "regression".into());
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("regressions")
+		.join(name)
+		.with_extension("wasm");
+	if let Err(e) = fs::write(&path, code) {
+		eprintln!("Could not write {}: {}", path.display(), e);
+		process::exit(1);
+	}
+	println!("Saved {}", path.display());
+}
@@ -0,0 +1,279 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Fuzzing of the sandbox backends
+//!
+//! The modules generated by `wasm-smith` from the fuzz input are instantiated by both the
+//! interpreter and the jit executor through `EnvironmentDefinitionBuilder` and `Instance`. Every
+//! exported function is invoked with the same arguments, then the results (or the trap codes), the
+//! exported globals and the exported memories of the backends must be the same.
+//!
+//! The generated modules import nothing, use only the features supported by both backends, and
+//! are instrumented to terminate by the fuel of [`FUEL`]. The modules with floats are skipped,
+//! since the bits of a NaN are not deterministic, and a NaN which is stored to the memory or
+//! reinterpreted as an integer would make the backends diverge falsely. The contracts could not
+//! use the floats anyway.
+use std::fmt;
+
+use arbitrary::{Arbitrary, Unstructured};
+use wasm_smith::{Config, ConfiguredModule};
+
+use ep_sandbox::{
+	validation::IssueKind, EnvironmentDefinitionBuilder, Error, ExternType, Instance, ReturnValue,
+	SandboxExecutor, TrapCode, ValType, Value,
+};
+
+/// The fuel of the loops and the calls in a generated module, the module traps with
+/// `UnreachableCodeReached` if it is consumed.
+pub const FUEL: u32 = 1_000;
+
+/// The maximum pages of the memories in a generated module.
+pub const MAX_MEMORY_PAGES: u32 = 16;
+
+/// The size of a wasm page.
+const PAGE_SIZE: usize = 65536;
+
+/// The name of the instantiation in [`Execution::calls`].
+pub const INSTANTIATE: &str = "<instantiate>";
+
+/// The arguments of the exported functions, the parameter `i` of the export `n` is the
+/// `(n + i)`th one.
+const ARGUMENTS: [i64; 7] = [
+	0,
+	1,
+	-1,
+	i32::MAX as i64,
+	i32::MIN as i64,
+	i64::MAX,
+	i64::MIN,
+];
+
+/// The configuration of `wasm-smith` for the modules which could be executed by both backends.
+#[derive(Clone, Debug, Default)]
+pub struct SandboxConfig;
+
+impl<'a> Arbitrary<'a> for SandboxConfig {
+	fn arbitrary(_u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+		Ok(SandboxConfig)
+	}
+}
+
+impl Config for SandboxConfig {
+	fn max_imports(&self) -> usize {
+		0
+	}
+
+	fn max_memory_pages(&self) -> u32 {
+		MAX_MEMORY_PAGES
+	}
+
+	fn bulk_memory_enabled(&self) -> bool {
+		false
+	}
+
+	fn reference_types_enabled(&self) -> bool {
+		false
+	}
+}
+
+/// Generate a module from the fuzz input.
+///
+/// Returns `None` if the input is too short, or the module uses a feature which is not supported
+/// by the interpreter, or the floats.
+pub fn generate(data: &[u8]) -> Option<Vec<u8>> {
+	let mut u = Unstructured::new(data);
+	let mut module = ConfiguredModule::<SandboxConfig>::arbitrary(&mut u).ok()?;
+	module.ensure_termination(FUEL);
+	let code = module.to_bytes();
+	let report = ep_sandbox::validate(&code);
+	let unsupported = report
+		.issues
+		.iter()
+		.any(|issue| matches!(issue.kind, IssueKind::Malformed | IssueKind::Feature));
+	if unsupported {
+		None
+	} else {
+		Some(code)
+	}
+}
+
+/// The outcome of the instantiation or an invocation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+	/// Returns the value.
+	Returned(Option<Value>),
+	/// Traps with the code, the backtraces are not compared.
+	Trapped(TrapCode),
+	/// Fails without a trap, with the debug output of the error.
+	Failed(String),
+}
+
+impl From<Result<ReturnValue, Error>> for Outcome {
+	fn from(result: Result<ReturnValue, Error>) -> Outcome {
+		match result {
+			Ok(ReturnValue::Unit) => Outcome::Returned(None),
+			Ok(ReturnValue::Value(value)) => Outcome::Returned(Some(value)),
+			Err(Error::Trap(trap)) => Outcome::Trapped(trap.code),
+			Err(e) => Outcome::Failed(format!("{:?}", e)),
+		}
+	}
+}
+
+/// The execution of a module by a backend.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Execution {
+	/// The outcome of the instantiation and then the invoked exports, in the order of the exports.
+	pub calls: Vec<(String, Outcome)>,
+	/// The values of the exported globals after the invocations.
+	pub globals: Vec<(String, Option<Value>)>,
+	/// The exported memories after the invocations, at most [`MAX_MEMORY_PAGES`] are kept with the
+	/// size in pages, since a memory without maximum could be grown to 4 GiB.
+	pub memories: Vec<(String, u32, Vec<u8>)>,
+}
+
+fn argument(ty: ValType, index: usize) -> Option<Value> {
+	let n = ARGUMENTS[index % ARGUMENTS.len()];
+	Some(match ty {
+		ValType::I32 => Value::I32(n as i32),
+		ValType::I64 => Value::I64(n),
+		_ => return None,
+	})
+}
+
+/// Execute `code` by `executor`.
+pub fn execute(executor: SandboxExecutor, code: &[u8]) -> Execution {
+	let mut execution = Execution::default();
+	let env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	let mut instance = match Instance::new_with_executor(executor, code, &env_builder, &mut ()) {
+		Ok(instance) => instance,
+		Err(e) => {
+			execution
+				.calls
+				.push((INSTANTIATE.into(), Outcome::from(Err::<ReturnValue, _>(e))));
+			return execution;
+		}
+	};
+	execution
+		.calls
+		.push((INSTANTIATE.into(), Outcome::Returned(None)));
+
+	let exports = instance.exports();
+	for (index, export) in exports.iter().enumerate() {
+		if let ExternType::Func(ty) = &export.ty {
+			let args = ty
+				.params
+				.iter()
+				.enumerate()
+				.map(|(i, ty)| argument(*ty, index + i))
+				.collect::<Option<Vec<_>>>();
+			if let Some(args) = args {
+				let outcome = instance.invoke(&export.name, &args, &mut ()).into();
+				execution.calls.push((export.name.clone(), outcome));
+			}
+		}
+	}
+
+	for export in exports {
+		match export.ty {
+			ExternType::Global { .. } => {
+				let value = instance.get_global_val(&export.name);
+				execution.globals.push((export.name, value));
+			}
+			ExternType::Memory { .. } => {
+				let pages = instance.memory_size(&export.name).unwrap_or(0);
+				let mut memory = vec![0; pages.min(MAX_MEMORY_PAGES) as usize * PAGE_SIZE];
+				if instance.read_memory(&export.name, 0, &mut memory).is_err() {
+					memory.clear();
+				}
+				execution.memories.push((export.name, pages, memory));
+			}
+			_ => {}
+		}
+	}
+	execution
+}
+
+/// The first difference between the executions of the interpreter and the jit executor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+	/// What is different, e.g. the name of the export.
+	pub subject: String,
+	/// The state in the interpreter.
+	pub interpreter: String,
+	/// The state in the jit executor.
+	pub jit: String,
+}
+
+impl fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{}: interpreter {}, jit {}",
+			self.subject, self.interpreter, self.jit
+		)
+	}
+}
+
+fn mismatch(
+	subject: impl Into<String>,
+	interpreter: impl fmt::Debug,
+	jit: impl fmt::Debug,
+) -> Mismatch {
+	Mismatch {
+		subject: subject.into(),
+		interpreter: format!("{:?}", interpreter),
+		jit: format!("{:?}", jit),
+	}
+}
+
+/// Execute `code` by both backends and find the first difference.
+pub fn compare(code: &[u8]) -> Result<(), Mismatch> {
+	let interpreter = execute(SandboxExecutor::Interpreter, code);
+	let jit = execute(SandboxExecutor::Jit, code);
+
+	for (i, (name, outcome)) in interpreter.calls.iter().enumerate() {
+		match jit.calls.get(i) {
+			Some((_, other)) if other == outcome => {}
+			other => return Err(mismatch(name.as_str(), outcome, other.map(|(_, o)| o))),
+		}
+	}
+	if jit.calls.len() > interpreter.calls.len() {
+		let (name, outcome) = &jit.calls[interpreter.calls.len()];
+		return Err(mismatch(name.as_str(), None::<Outcome>, outcome));
+	}
+	if interpreter.globals != jit.globals {
+		return Err(mismatch("globals", interpreter.globals, jit.globals));
+	}
+	if interpreter.memories.len() != jit.memories.len() {
+		return Err(mismatch(
+			"memories",
+			interpreter.memories.len(),
+			jit.memories.len(),
+		));
+	}
+	for ((name, pages, memory), (_, other_pages, other)) in
+		interpreter.memories.iter().zip(jit.memories.iter())
+	{
+		if pages != other_pages {
+			return Err(mismatch(format!("pages of {}", name), pages, other_pages));
+		}
+		if let Some(offset) = (0..memory.len().max(other.len()))
+			.find(|offset| memory.get(*offset) != other.get(*offset))
+		{
+			return Err(mismatch(
+				format!("{} at {:#x}", name, offset),
+				memory.get(offset),
+				other.get(offset),
+			));
+		}
+	}
+	Ok(())
+}
+
+/// Panic with the mismatch if the backends diverge in `code`.
+pub fn check(code: &[u8]) {
+	if let Err(mismatch) = compare(code) {
+		panic!("the backends diverge in {}", mismatch);
+	}
+}
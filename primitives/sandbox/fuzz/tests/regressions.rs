@@ -0,0 +1,40 @@
+use std::{fs, path::Path};
+
+#[test]
+fn regressions_do_not_diverge() {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("regressions");
+	let mut checked = 0;
+	for entry in fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		if path.extension().map_or(false, |ext| ext == "wasm") {
+			let code = fs::read(&path).unwrap();
+			if let Err(mismatch) = ep_sandbox_fuzz::compare(&code) {
+				panic!("{}: the backends diverge in {}", path.display(), mismatch);
+			}
+			checked += 1;
+		}
+	}
+	assert!(checked > 0);
+}
+
+#[test]
+fn generated_modules_do_not_diverge() {
+	// xorshift, so that the inputs are the same in every run.
+	let mut seed = 0x2545_f491_4f6c_dd1du64;
+	let mut generated = 0;
+	for _ in 0..256 {
+		let data = (0..1024)
+			.map(|_| {
+				seed ^= seed << 13;
+				seed ^= seed >> 7;
+				seed ^= seed << 17;
+				seed as u8
+			})
+			.collect::<Vec<_>>();
+		if let Some(code) = ep_sandbox_fuzz::generate(&data) {
+			ep_sandbox_fuzz::check(&code);
+			generated += 1;
+		}
+	}
+	assert!(generated > 0);
+}
@@ -0,0 +1,538 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Code coverage of the contracts
+//!
+//! In the coverage mode, the module is instrumented before it is instantiated by either backend:
+//! every basic block (the straight-line run of instructions after a control instruction) calls
+//! the `hit` function imported from [`MODULE`] with the index of the block. The hits of every
+//! invocation are aggregated per code hash into the contract tracing database by the
+//! `ContractTracingDbExt` externalities extension, across all the calls (the dry-runs included)
+//! in the workspace.
+//!
+//! The sandbox executes the code instrumented by pallet-contracts with the gas metering and the
+//! stack height limiter, thus pallet-contracts passes the code hash and the pristine code (the
+//! uploaded one) of the contract by [`set_pristine_code`] before it is instantiated. The coverage
+//! is keyed by that code hash, and the instructions executed by the sandbox are aligned to the
+//! ones of the pristine code, by which the blocks are mapped to the source lines in the DWARF
+//! sections if the contract is built with debug info. Otherwise every block is a line of a pseudo
+//! file named after the code hash. Without the pristine code, e.g. for the modules which are not
+//! contracts, the code hash is the blake2 256 hash of the executed code. The coverage is exported
+//! in LCOV.
+use std::{
+	borrow::Cow,
+	cell::RefCell,
+	collections::{BTreeMap, HashMap},
+	fmt::Write,
+	ops::Range,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use addr2line::gimli;
+use codec::{Decode, Encode};
+use ep_extensions::{ContractTracingDb, ContractTracingDbExt};
+use parity_wasm::elements::{ImportCountType, Instruction, Module, ValueType};
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_externalities::ExternalitiesExt;
+use wasmparser::{Parser, Payload};
+
+use crate::instrument::{self, is_control};
+
+/// The import module of the coverage function.
+pub const MODULE: &str = "__europa_coverage";
+/// Hit the block, the index of which is the argument.
+pub const HIT: &str = "hit";
+
+static COVERAGE: AtomicBool = AtomicBool::new(false);
+
+/// Enable the coverage mode, the instances created afterwards are instrumented.
+pub fn set_coverage(enable: bool) {
+	COVERAGE.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the coverage mode is enabled.
+pub fn coverage() -> bool {
+	COVERAGE.load(Ordering::Relaxed)
+}
+
+thread_local! {
+	/// The code hash and the pristine code of the contract which is instantiated next.
+	static PRISTINE_CODE: RefCell<Option<(H256, Vec<u8>)>> = RefCell::new(None);
+}
+
+/// Key the coverage of the contract which is instantiated next by `code_hash`, and map its blocks
+/// to the source by `pristine`, the code before pallet-contracts instruments it.
+///
+/// The pristine code is ignored if the instantiated code is not instrumented from it.
+pub fn set_pristine_code(code_hash: H256, pristine: Vec<u8>) {
+	PRISTINE_CODE.with(|code| *code.borrow_mut() = Some((code_hash, pristine)));
+}
+
+/// A basic block of a function.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Block {
+	/// The index of the function in the function index space of the executed code.
+	pub function: u32,
+	/// The offset of the first instruction of the pristine code in its code section, which is
+	/// the address in DWARF, `0` if the block only has the instructions injected by
+	/// pallet-contracts.
+	pub offset: u32,
+	/// The source lines of the instructions, as `(index in files, line)`.
+	pub lines: Vec<(u32, u32)>,
+	pub hits: u64,
+}
+
+/// The coverage of a code.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct CodeCoverage {
+	pub code_hash: H256,
+	/// The invocations which are aggregated.
+	pub invocations: u64,
+	/// The symbols of the defined functions, named by the name section or `func[index]`.
+	pub functions: Vec<(u32, String)>,
+	/// The source files in DWARF, empty if the code has no debug info.
+	pub files: Vec<String>,
+	/// The blocks in the order of the code.
+	pub blocks: Vec<Block>,
+}
+
+impl CodeCoverage {
+	/// Hit the block at `index`, which is called by the instrumented code.
+	pub(crate) fn hit(&mut self, index: u32) {
+		if let Some(block) = self.blocks.get_mut(index as usize) {
+			block.hits += 1;
+		}
+	}
+
+	/// Take the hits of the last invocation, the hits of this coverage are cleared.
+	pub(crate) fn take(&mut self) -> CodeCoverage {
+		let mut coverage = self.clone();
+		coverage.invocations = 1;
+		for block in self.blocks.iter_mut() {
+			block.hits = 0;
+		}
+		coverage
+	}
+
+	/// Add the hits of `other` of the same code.
+	pub fn merge(&mut self, other: &CodeCoverage) {
+		self.invocations += other.invocations;
+		for (block, other) in self.blocks.iter_mut().zip(other.blocks.iter()) {
+			block.hits += other.hits;
+		}
+	}
+
+	/// The number of the blocks which are hit.
+	pub fn covered(&self) -> usize {
+		self.blocks.iter().filter(|block| block.hits > 0).count()
+	}
+
+	/// The source locations of the block, or the index of the block in the pseudo file if the
+	/// code has no debug info.
+	fn locations(&self, index: usize, block: &Block) -> Vec<(String, u32)> {
+		if self.files.is_empty() {
+			return vec![(format!("{:?}.wasm", self.code_hash), index as u32 + 1)];
+		}
+		block
+			.lines
+			.iter()
+			.filter_map(|(file, line)| Some((self.files.get(*file as usize)?.clone(), *line)))
+			.collect()
+	}
+
+	/// Export the coverage in LCOV, one record per source file.
+	pub fn lcov(&self) -> String {
+		#[derive(Default)]
+		struct Record {
+			/// The (line, hits) of the functions.
+			functions: BTreeMap<String, (u32, u64)>,
+			/// The hits of the lines, the maximum of the blocks in the line.
+			lines: BTreeMap<u32, u64>,
+		}
+
+		let symbols = self.functions.iter().cloned().collect::<HashMap<_, _>>();
+		let mut records = BTreeMap::<String, Record>::new();
+		let mut last_function = None;
+		for (index, block) in self.blocks.iter().enumerate() {
+			let locations = self.locations(index, block);
+			// the first block with the location is the entry of the function.
+			if last_function != Some(block.function) {
+				if let Some((file, line)) = locations.first() {
+					let symbol = symbols
+						.get(&block.function)
+						.cloned()
+						.unwrap_or_else(|| format!("func[{}]", block.function));
+					records
+						.entry(file.clone())
+						.or_default()
+						.functions
+						.entry(symbol)
+						.or_insert((*line, block.hits));
+					last_function = Some(block.function);
+				}
+			}
+			for (file, line) in locations {
+				let hits = records
+					.entry(file)
+					.or_default()
+					.lines
+					.entry(line)
+					.or_default();
+				*hits = (*hits).max(block.hits);
+			}
+		}
+
+		let mut lcov = String::new();
+		for (file, record) in records {
+			let _ = writeln!(lcov, "TN:{:?}", self.code_hash);
+			let _ = writeln!(lcov, "SF:{}", file);
+			for (symbol, (line, _)) in record.functions.iter() {
+				let _ = writeln!(lcov, "FN:{},{}", line, symbol);
+			}
+			for (symbol, (_, hits)) in record.functions.iter() {
+				let _ = writeln!(lcov, "FNDA:{},{}", hits, symbol);
+			}
+			let _ = writeln!(lcov, "FNF:{}", record.functions.len());
+			let _ = writeln!(
+				lcov,
+				"FNH:{}",
+				record
+					.functions
+					.values()
+					.filter(|(_, hits)| *hits > 0)
+					.count()
+			);
+			for (line, hits) in record.lines.iter() {
+				let _ = writeln!(lcov, "DA:{},{}", line, hits);
+			}
+			let _ = writeln!(lcov, "LF:{}", record.lines.len());
+			let _ = writeln!(
+				lcov,
+				"LH:{}",
+				record.lines.values().filter(|hits| **hits > 0).count()
+			);
+			lcov.push_str("end_of_record\n");
+		}
+		lcov
+	}
+}
+
+/// Aggregate the coverage of an invocation into the contract tracing database.
+pub(crate) fn report(coverage: CodeCoverage) {
+	sp_externalities::with_externalities(|ext| {
+		let db = match ext.extension::<ContractTracingDbExt>() {
+			Some(db) => db,
+			None => return,
+		};
+		let code_hash = coverage.code_hash;
+		let aggregated = match db
+			.coverage(code_hash.as_bytes())
+			.and_then(|aggregated| CodeCoverage::decode(&mut &aggregated[..]).ok())
+		{
+			Some(mut aggregated) if aggregated.blocks.len() == coverage.blocks.len() => {
+				aggregated.merge(&coverage);
+				aggregated
+			}
+			_ => coverage,
+		};
+		db.set_coverage(code_hash.as_bytes(), aggregated.encode());
+	});
+}
+
+/// The offsets of the instructions in the code section, per defined function.
+pub(crate) fn offsets(code: &[u8]) -> Result<Vec<Vec<u32>>, String> {
+	let mut code_section = 0;
+	let mut functions = Vec::new();
+	for payload in Parser::new(0).parse_all(code) {
+		match payload.map_err(|e| e.to_string())? {
+			Payload::CodeSectionStart { range, .. } => code_section = range.start,
+			Payload::CodeSectionEntry(body) => {
+				let mut reader = body.get_operators_reader().map_err(|e| e.to_string())?;
+				let mut offsets = Vec::new();
+				while !reader.eof() {
+					let (_, offset) = reader.read_with_offset().map_err(|e| e.to_string())?;
+					offsets.push((offset - code_section) as u32);
+				}
+				functions.push(offsets);
+			}
+			_ => {}
+		}
+	}
+	Ok(functions)
+}
+
+pub(crate) type Dwarf<'a> = addr2line::Context<gimli::EndianSlice<'a, gimli::LittleEndian>>;
+
+/// The DWARF sections of the code, `None` if the code has no debug info.
+pub(crate) fn dwarf(code: &[u8]) -> Option<Dwarf<'_>> {
+	let mut sections = HashMap::new();
+	for payload in Parser::new(0).parse_all(code) {
+		if let Ok(Payload::CustomSection { name, data, .. }) = payload {
+			if name.starts_with(".debug_") {
+				sections.insert(name, data);
+			}
+		}
+	}
+	if !sections.contains_key(".debug_line") {
+		return None;
+	}
+	let load = |id: gimli::SectionId| -> Result<_, gimli::Error> {
+		let data = sections.get(id.name()).copied().unwrap_or(&[]);
+		Ok(gimli::EndianSlice::new(data, gimli::LittleEndian))
+	};
+	let dwarf = gimli::Dwarf::load(load).ok()?;
+	addr2line::Context::from_dwarf(dwarf).ok()
+}
+
+/// The defined functions of the code instrumented by pallet-contracts, aligned to the pristine
+/// code.
+struct Alignment {
+	/// The number of the instructions per defined function of the pristine code.
+	lengths: Vec<usize>,
+	/// The index of the pristine instruction per instruction, `None` for the injected ones.
+	pcs: Vec<Vec<Option<usize>>>,
+}
+
+/// The imported functions, the globals and the instructions of the defined functions.
+struct Parsed {
+	imported: u32,
+	globals: u32,
+	bodies: Vec<Vec<Instruction>>,
+}
+
+fn parse(code: &[u8]) -> Result<Parsed, String> {
+	let module = parity_wasm::deserialize_buffer::<Module>(code).map_err(|e| e.to_string())?;
+	Ok(Parsed {
+		imported: module.import_count(ImportCountType::Function) as u32,
+		globals: module.import_count(ImportCountType::Global) as u32
+			+ module
+				.global_section()
+				.map_or(0, |section| section.entries().len() as u32),
+		bodies: module
+			.code_section()
+			.map(|section| {
+				section
+					.bodies()
+					.iter()
+					.map(|body| body.code().elements().to_vec())
+					.collect()
+			})
+			.unwrap_or_default(),
+	})
+}
+
+/// Whether every instruction of `body` is injected by pallet-contracts: the charge of the gas
+/// (`i32.const; call $gas`, the function of which is in `imports`), and the accounting of the
+/// stack height around the calls, which accesses the global at or after `globals`.
+fn injected(body: &[Instruction], imports: &Range<u32>, globals: u32) -> Vec<bool> {
+	use Instruction::*;
+
+	let mut injected = vec![false; body.len()];
+	for (pc, instruction) in body.iter().enumerate() {
+		match instruction {
+			Call(index) if imports.contains(index) => {
+				injected[pc.saturating_sub(1)..=pc].fill(true);
+			}
+			// `global.get; i32.const; i32.add (or i32.sub); global.set`
+			SetGlobal(index) if *index >= globals => {
+				injected[pc.saturating_sub(3)..=pc].fill(true);
+			}
+			// `global.get; i32.const; i32.gt_u; if; unreachable; end`
+			GetGlobal(index)
+				if *index >= globals
+					&& matches!(
+						body.get(pc + 1..pc + 6),
+						Some([I32Const(_), I32GtU, If(_), Unreachable, End])
+					) =>
+			{
+				injected[pc..pc + 6].fill(true);
+			}
+			_ => {}
+		}
+	}
+	injected
+}
+
+/// Align the instructions of `code` to the ones of `pristine`. pallet-contracts only injects
+/// the instructions, the imports and the globals, thus the calls of the defined functions are
+/// shifted by the injected imports, and the grow of the memory is replaced by the call of the
+/// injected function which charges it.
+///
+/// Returns `Err` if `code` is not instrumented from `pristine`.
+fn align(pristine: &[u8], code: &[u8]) -> Result<Alignment, String> {
+	let pristine = parse(pristine)?;
+	let code = parse(code)?;
+	let added = code
+		.imported
+		.checked_sub(pristine.imported)
+		.ok_or("The imported functions of the pristine code are removed")?;
+	let imports = pristine.imported..pristine.imported + added;
+	// the functions appended after the shifted defined functions are injected.
+	let functions = pristine.imported + added + pristine.bodies.len() as u32;
+	let same = |pristine_instruction: &Instruction, instruction: &Instruction| match (
+		pristine_instruction,
+		instruction,
+	) {
+		(Instruction::Call(p), Instruction::Call(i)) => {
+			*i == if *p < pristine.imported {
+				*p
+			} else {
+				p + added
+			}
+		}
+		(Instruction::GrowMemory(_), Instruction::Call(i)) => *i >= functions,
+		(p, i) => p == i,
+	};
+
+	let mut pcs = Vec::new();
+	for (index, original) in pristine.bodies.iter().enumerate() {
+		let body = code
+			.bodies
+			.get(index)
+			.ok_or("The defined functions of the pristine code are removed")?;
+		let injected = injected(body, &imports, pristine.globals);
+		let mut next = 0;
+		let mut aligned = Vec::with_capacity(body.len());
+		for (instruction, injected) in body.iter().zip(injected) {
+			if injected {
+				aligned.push(None);
+			} else if original.get(next).map_or(false, |p| same(p, instruction)) {
+				aligned.push(Some(next));
+				next += 1;
+			} else {
+				break;
+			}
+		}
+		// every instruction is either injected or the next one of the pristine code.
+		if aligned.len() != body.len() || next != original.len() {
+			return Err(format!(
+				"The function {} is not instrumented from the pristine code",
+				pristine.imported + index as u32
+			));
+		}
+		pcs.push(aligned);
+	}
+	Ok(Alignment {
+		lengths: pristine.bodies.iter().map(Vec::len).collect(),
+		pcs,
+	})
+}
+
+/// The code hash, the pristine code and its alignment if the pristine code of `code` is set,
+/// which is taken.
+fn take_pristine_code(code: &[u8]) -> Option<(H256, Vec<u8>, Alignment)> {
+	let (code_hash, pristine) = PRISTINE_CODE.with(|code| code.borrow_mut().take())?;
+	match align(&pristine, code) {
+		Ok(alignment) => Some((code_hash, pristine, alignment)),
+		Err(e) => {
+			log::warn!(
+				target: "sandbox",
+				"The coverage is not mapped to the pristine code of {:?}: {}",
+				code_hash,
+				e
+			);
+			None
+		}
+	}
+}
+
+/// Instrument `code` with the hits of the blocks.
+///
+/// Returns the instrumented code and the coverage without hits.
+pub(crate) fn instrument(code: &[u8]) -> Result<(Vec<u8>, CodeCoverage), String> {
+	let (code_hash, source, alignment) = match take_pristine_code(code) {
+		Some((code_hash, pristine, alignment)) => {
+			(code_hash, Cow::Owned(pristine), Some(alignment))
+		}
+		None => (
+			sp_core::hashing::blake2_256(code).into(),
+			Cow::Borrowed(code),
+			None,
+		),
+	};
+	let offsets = offsets(&source)?;
+	let dwarf = dwarf(&source);
+	let mut files = Vec::<String>::new();
+	let mut blocks = Vec::<Block>::new();
+
+	let host_funcs = [instrument::HostFunc {
+		name: HIT,
+		params: vec![ValueType::I32],
+		results: vec![],
+	}];
+	let (instrumented, program) = instrument::instrument(code, MODULE, &host_funcs, |function| {
+		let hit = function.host_base;
+		let index = (function.index - function.host_base) as usize;
+		let (length, pcs) = match alignment.as_ref() {
+			Some(alignment) => (
+				alignment.lengths.get(index).copied().unwrap_or_default(),
+				alignment.pcs.get(index),
+			),
+			None => (function.code.len(), None),
+		};
+		// the instructions of parity-wasm and wasmparser are not aligned, skip the lines.
+		let offsets = offsets.get(index).filter(|offsets| offsets.len() == length);
+		// the offset of the instruction in the source, `None` if it is injected.
+		let offset = |pc: usize| {
+			let pc = match pcs {
+				Some(pcs) => (*pcs.get(pc)?)?,
+				None => pc,
+			};
+			offsets?.get(pc).copied()
+		};
+
+		let mut code = Vec::new();
+		for (pc, instruction) in function.code.iter().enumerate() {
+			if pc == 0 || is_control(&function.code[pc - 1]) {
+				code.push(Instruction::I32Const(blocks.len() as i32));
+				code.push(Instruction::Call(hit));
+				blocks.push(Block {
+					function: function.index,
+					offset: 0,
+					lines: Vec::new(),
+					hits: 0,
+				});
+			}
+			code.push(instruction.clone());
+
+			let offset = match offset(pc) {
+				Some(offset) => offset,
+				None => continue,
+			};
+			let location = dwarf
+				.as_ref()
+				.and_then(|dwarf| dwarf.find_location(offset as u64).ok().flatten());
+			let block = blocks.last_mut().expect("the block is pushed at pc 0; qed");
+			if block.offset == 0 {
+				block.offset = offset;
+			}
+			if let Some((file, line)) = location.and_then(|l| Some((l.file?, l.line?))) {
+				let file = match files.iter().position(|f| f == file) {
+					Some(index) => index,
+					None => {
+						files.push(file.to_string());
+						files.len() - 1
+					}
+				} as u32;
+				if !block.lines.contains(&(file, line)) {
+					block.lines.push((file, line));
+				}
+			}
+		}
+		code
+	})?;
+
+	let coverage = CodeCoverage {
+		code_hash,
+		invocations: 0,
+		functions: program
+			.functions()
+			.map(|function| (function, program.symbol(function)))
+			.collect(),
+		files,
+		blocks,
+	};
+	Ok((instrumented, coverage))
+}
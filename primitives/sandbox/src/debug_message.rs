@@ -0,0 +1,84 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! The debug messages of the contracts
+//!
+//! The buffers which are passed to `seal_debug_message` are read from the memory of the calling
+//! instance and reported with the frame (see [`crate::current_frame`]) which prints them, thus the
+//! messages are collected in the extrinsics too, even if the pallet drops them without the debug
+//! mode. The node merges the messages into the `debug_messages` of the frames in the contract
+//! tracing.
+use std::cell::RefCell;
+
+use ep_extensions::DebugMessagesExt;
+
+use crate::{snapshot, HostError, Memory, ReturnValue, Value};
+
+/// The name of the host function which prints the debug message.
+const DEBUG_MESSAGE: &str = "seal_debug_message";
+
+thread_local! {
+	/// The memories of the running invocations, the innermost one is the last.
+	static MEMORIES: RefCell<Vec<Option<Memory>>> = RefCell::new(Vec::new());
+}
+
+/// The memory of the running invocation, which is released when the invocation ends.
+pub(crate) struct MemoryGuard;
+
+impl MemoryGuard {
+	pub fn enter(memory: Option<Memory>) -> Self {
+		MEMORIES.with(|memories| memories.borrow_mut().push(memory));
+		MemoryGuard
+	}
+}
+
+impl Drop for MemoryGuard {
+	fn drop(&mut self) {
+		MEMORIES.with(|memories| memories.borrow_mut().pop());
+	}
+}
+
+/// Read the message if the host function `name` (in `module.field`) is `seal_debug_message`.
+pub(crate) fn read(name: &str, args: &[Value]) -> Option<String> {
+	if name.rsplit('.').next() != Some(DEBUG_MESSAGE) {
+		return None;
+	}
+	let (ptr, len) = match args {
+		[Value::I32(ptr), Value::I32(len)] => (*ptr as u32, *len as u32 as usize),
+		_ => return None,
+	};
+	MEMORIES.with(|memories| {
+		let memories = memories.borrow();
+		let memory = memories.last()?.as_ref()?;
+		if len > memory.size() {
+			return None;
+		}
+		let mut buf = vec![0; len];
+		memory.get(ptr, &mut buf).ok()?;
+		Some(String::from_utf8_lossy(&buf).into_owned())
+	})
+}
+
+/// Report the message which is printed by the host call with `result`.
+pub(crate) fn report(message: String, result: &Result<ReturnValue, HostError>) {
+	if result.is_err() {
+		return;
+	}
+	let (depth, frame) = match snapshot::current_frame() {
+		Some(frame) => frame,
+		None => return,
+	};
+	let record = serde_json::json!({
+		"depth": depth,
+		"frame": frame,
+		"message": message,
+	});
+	if let Ok(record) = serde_json::to_vec(&record) {
+		sp_externalities::with_externalities(|ext| {
+			if let Some(records) = ext.extension::<DebugMessagesExt>() {
+				records.0.push(record);
+			}
+		});
+	}
+}
@@ -0,0 +1,444 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Step debugger for the interpreter
+//!
+//! Neither backend exposes the execution of single instructions, thus the module is instrumented
+//! before it is instantiated by the interpreter: every instruction is prefixed by a call to the
+//! probe imported from [`MODULE`], which asks the debugger whether to pause at this location. If
+//! so, the locals of the frame and the operands are passed to the host one by one, and the
+//! execution is blocked until the debugger steps or continues.
+//!
+//! The instrumented code could not read the operand stack, thus the types of the operands are
+//! tracked while instrumenting, and the operands are spilled into the scratch locals before every
+//! probe and pushed back after it. Only the operands pushed in the innermost block are reachable
+//! by the code in the block, the operands of the outer blocks and the operands in the code after
+//! a post-MVP instruction are not inspectable.
+//!
+//! Only the instances which are created in [`debuggable`] (the dry-run rpcs) while a debugger is
+//! attached are instrumented, thus the block production is never paused. One execution is paused
+//! at a time; the memory of the paused execution is read by the paused thread on behalf of the
+//! debugger.
+use std::{
+	cell::Cell,
+	collections::{BTreeMap, VecDeque},
+	sync::mpsc,
+	time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use parity_wasm::elements::{BlockType, Instruction, ValueType};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	instrument::{self, Program},
+	serde_opt_wasm_returnvalue::SerdeValue,
+	Value,
+};
+
+/// The import module of the debugger functions.
+pub const MODULE: &str = "__europa_debug";
+/// Ask whether to pause at `(function, pc)`, returns non-zero to pause.
+const PROBE: &str = "probe";
+/// Pass a local of the paused frame.
+const LOCAL_I32: &str = "local_i32";
+const LOCAL_I64: &str = "local_i64";
+const LOCAL_F32: &str = "local_f32";
+const LOCAL_F64: &str = "local_f64";
+/// Pass an operand of the paused frame.
+const OPERAND_I32: &str = "operand_i32";
+const OPERAND_I64: &str = "operand_i64";
+const OPERAND_F32: &str = "operand_f32";
+const OPERAND_F64: &str = "operand_f64";
+/// Block until the debugger steps or continues.
+const PAUSE: &str = "pause";
+/// The debugger functions, in the order of the imports.
+const HOST_FUNCS: [&str; 10] = [
+	PROBE,
+	LOCAL_I32,
+	LOCAL_I64,
+	LOCAL_F32,
+	LOCAL_F64,
+	OPERAND_I32,
+	OPERAND_I64,
+	OPERAND_F32,
+	OPERAND_F64,
+	PAUSE,
+];
+/// How long the debugger waits for the paused thread to read the memory.
+const MEMORY_TIMEOUT: Duration = Duration::from_secs(5);
+
+static DEBUGGER: Lazy<Debugger> = Lazy::new(|| Debugger {
+	state: Mutex::new(State::default()),
+	commands: Condvar::new(),
+});
+
+thread_local! {
+	static DEBUGGABLE: Cell<bool> = Cell::new(false);
+}
+
+/// Run `f` with the instances created in it debuggable, e.g. the dry-run of a contract call.
+pub fn debuggable<R>(f: impl FnOnce() -> R) -> R {
+	with_debuggable(true, f)
+}
+
+/// Run `f` with the instances created in it not debuggable, e.g. the secondary instance in the
+/// differential mode.
+pub(crate) fn undebuggable<R>(f: impl FnOnce() -> R) -> R {
+	with_debuggable(false, f)
+}
+
+fn with_debuggable<R>(debuggable: bool, f: impl FnOnce() -> R) -> R {
+	let previous = DEBUGGABLE.with(|d| d.replace(debuggable));
+	let result = f();
+	DEBUGGABLE.with(|d| d.set(previous));
+	result
+}
+
+/// Whether the current thread runs in [`debuggable`], i.e. a dry-run.
+pub(crate) fn in_dry_run() -> bool {
+	DEBUGGABLE.with(|d| d.get())
+}
+
+/// Whether the instance created now should be instrumented.
+pub(crate) fn enabled() -> bool {
+	in_dry_run() && !DEBUGGER.state.lock().listeners.is_empty()
+}
+
+/// The function where to pause, by the index in the function index space (the imported
+/// functions included) or the name in the name section.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FunctionRef {
+	/// The index in the function index space.
+	Index(u32),
+	/// The name in the name section.
+	Name(String),
+}
+
+/// Pause before the instruction `pc` of `function`, or the first instruction if `pc` is `None`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Breakpoint {
+	/// The function where to pause.
+	pub function: FunctionRef,
+	/// The index of the instruction in the function body.
+	#[serde(default)]
+	pub pc: Option<u32>,
+}
+
+/// The location where the execution is paused.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Paused {
+	/// The index of the function in the function index space.
+	pub function: u32,
+	/// The name of the function in the name section.
+	pub name: Option<String>,
+	/// The index of the instruction in the function body.
+	pub pc: u32,
+	/// The instruction to be executed.
+	pub instruction: String,
+	/// The parameters and locals of the frame.
+	pub locals: Vec<SerdeValue>,
+	/// The operands pushed in the innermost block, the bottom first. Empty if they are not
+	/// inspectable.
+	pub operands: Vec<SerdeValue>,
+}
+
+/// The event sent to the attached debuggers.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum DebugEvent {
+	/// An execution is paused.
+	Paused(Paused),
+	/// The paused execution is resumed.
+	Resumed,
+}
+
+type Listener = Box<dyn Fn(&DebugEvent) + Send + Sync>;
+
+enum Command {
+	Step,
+	Continue,
+	Memory(u32, u32, mpsc::Sender<Result<Vec<u8>, String>>),
+}
+
+#[derive(Default)]
+struct State {
+	listeners: BTreeMap<u64, Listener>,
+	next_listener: u64,
+	breakpoints: Vec<Breakpoint>,
+	/// Pause at the next probe.
+	stepping: bool,
+	paused: Option<Paused>,
+	commands: VecDeque<Command>,
+}
+
+impl State {
+	fn notify(&self, event: DebugEvent) {
+		for listener in self.listeners.values() {
+			listener(&event);
+		}
+	}
+}
+
+struct Debugger {
+	state: Mutex<State>,
+	/// Notify the paused thread the new commands.
+	commands: Condvar,
+}
+
+/// Attach a debugger, the events are sent to `listener`. Returns the id to detach it.
+pub fn attach(listener: impl Fn(&DebugEvent) + Send + Sync + 'static) -> u64 {
+	let mut state = DEBUGGER.state.lock();
+	let id = state.next_listener;
+	state.next_listener += 1;
+	state.listeners.insert(id, Box::new(listener));
+	id
+}
+
+/// Detach the debugger. The breakpoints are cleared and the paused execution is resumed after the
+/// last debugger is detached.
+pub fn detach(id: u64) {
+	let mut state = DEBUGGER.state.lock();
+	state.listeners.remove(&id);
+	if state.listeners.is_empty() {
+		state.breakpoints.clear();
+		state.stepping = false;
+		state.commands.clear();
+		DEBUGGER.commands.notify_all();
+	}
+}
+
+/// Replace the breakpoints.
+pub fn set_breakpoints(breakpoints: Vec<Breakpoint>) {
+	DEBUGGER.state.lock().breakpoints = breakpoints;
+}
+
+/// The breakpoints.
+pub fn breakpoints() -> Vec<Breakpoint> {
+	DEBUGGER.state.lock().breakpoints.clone()
+}
+
+/// The location of the paused execution, `None` if no execution is paused.
+pub fn paused() -> Option<Paused> {
+	DEBUGGER.state.lock().paused.clone()
+}
+
+/// Execute one instruction of the paused execution.
+pub fn step() -> Result<(), String> {
+	command(Command::Step)
+}
+
+/// Continue the paused execution until the next breakpoint.
+pub fn resume() -> Result<(), String> {
+	command(Command::Continue)
+}
+
+/// Read the linear memory of the paused execution.
+pub fn memory(offset: u32, len: u32) -> Result<Vec<u8>, String> {
+	let (tx, rx) = mpsc::channel();
+	command(Command::Memory(offset, len, tx))?;
+	rx.recv_timeout(MEMORY_TIMEOUT)
+		.map_err(|e| format!("The paused execution does not respond: {}", e))?
+}
+
+fn command(command: Command) -> Result<(), String> {
+	let mut state = DEBUGGER.state.lock();
+	if state.paused.is_none() {
+		return Err("No execution is paused".into());
+	}
+	state.commands.push_back(command);
+	DEBUGGER.commands.notify_all();
+	Ok(())
+}
+
+/// Whether to pause before the instruction `pc` of `function`.
+pub(crate) fn probe(program: &Program, function: u32, pc: u32) -> bool {
+	let state = DEBUGGER.state.lock();
+	if state.listeners.is_empty() || state.paused.is_some() {
+		return false;
+	}
+	state.stepping
+		|| state.breakpoints.iter().any(|breakpoint| {
+			let matched = match &breakpoint.function {
+				FunctionRef::Index(index) => *index == function,
+				FunctionRef::Name(name) => program.name(function) == Some(name),
+			};
+			matched && breakpoint.pc.unwrap_or(0) == pc
+		})
+}
+
+/// Pause before the instruction `pc` of `function`, until the debugger steps, continues or is
+/// detached. The memory is read by `memory` on behalf of the debugger.
+pub(crate) fn pause(
+	program: &Program,
+	function: u32,
+	pc: u32,
+	locals: &[Value],
+	operands: &[Value],
+	memory: &dyn Fn(u32, u32) -> Result<Vec<u8>, String>,
+) {
+	let mut state = DEBUGGER.state.lock();
+	if state.listeners.is_empty() || state.paused.is_some() {
+		return;
+	}
+	let paused = Paused {
+		function,
+		name: program.name(function).cloned(),
+		pc,
+		instruction: program.instruction(function, pc),
+		locals: locals.iter().cloned().map(Into::into).collect(),
+		operands: operands.iter().cloned().map(Into::into).collect(),
+	};
+	log::info!(
+		target: "sandbox",
+		"Paused at {}:{} `{}`",
+		paused.name.clone().unwrap_or_else(|| function.to_string()),
+		pc,
+		paused.instruction
+	);
+	state.stepping = false;
+	state.commands.clear();
+	state.paused = Some(paused.clone());
+	state.notify(DebugEvent::Paused(paused));
+
+	loop {
+		match state.commands.pop_front() {
+			Some(Command::Step) => {
+				state.stepping = true;
+				break;
+			}
+			Some(Command::Continue) => break,
+			Some(Command::Memory(offset, len, reply)) => {
+				let _ = reply.send(memory(offset, len));
+			}
+			None if state.listeners.is_empty() => break,
+			None => DEBUGGER.commands.wait(&mut state),
+		}
+	}
+	state.paused = None;
+	state.notify(DebugEvent::Resumed);
+}
+
+/// The index of the debugger function `field` in [`HOST_FUNCS`].
+pub(crate) fn host_func(field: &str) -> Option<usize> {
+	HOST_FUNCS.iter().position(|f| *f == field)
+}
+
+/// The debugger function at `index` of [`HOST_FUNCS`].
+pub(crate) enum HostFunc {
+	Probe,
+	Local,
+	Operand,
+	Pause,
+}
+
+impl HostFunc {
+	pub fn from_index(index: usize) -> Option<HostFunc> {
+		match HOST_FUNCS.get(index)? {
+			&PROBE => Some(HostFunc::Probe),
+			&PAUSE => Some(HostFunc::Pause),
+			&OPERAND_I32 | &OPERAND_I64 | &OPERAND_F32 | &OPERAND_F64 => Some(HostFunc::Operand),
+			_ => Some(HostFunc::Local),
+		}
+	}
+}
+
+/// Instrument `code` with the probes before every instruction.
+///
+/// Returns the instrumented code and the original program.
+pub(crate) fn instrument(code: &[u8]) -> Result<(Vec<u8>, Program), String> {
+	let signatures: [(Vec<ValueType>, Vec<ValueType>); 10] = [
+		(vec![ValueType::I32, ValueType::I32], vec![ValueType::I32]),
+		(vec![ValueType::I32], vec![]),
+		(vec![ValueType::I64], vec![]),
+		(vec![ValueType::F32], vec![]),
+		(vec![ValueType::F64], vec![]),
+		(vec![ValueType::I32], vec![]),
+		(vec![ValueType::I64], vec![]),
+		(vec![ValueType::F32], vec![]),
+		(vec![ValueType::F64], vec![]),
+		(vec![], vec![]),
+	];
+	let host_funcs = HOST_FUNCS
+		.iter()
+		.zip(signatures.iter().cloned())
+		.map(|(name, (params, results))| instrument::HostFunc {
+			name: *name,
+			params,
+			results,
+		})
+		.collect::<Vec<_>>();
+
+	instrument::instrument(code, MODULE, &host_funcs, |function| {
+		let base = function.host_base;
+		let (probe, pause) = (base, base + HOST_FUNCS.len() as u32 - 1);
+		let offset = |ty: ValueType| match ty {
+			ValueType::I32 => 0,
+			ValueType::I64 => 1,
+			ValueType::F32 => 2,
+			ValueType::F64 => 3,
+		};
+		let local = |ty: ValueType| base + 1 + offset(ty);
+		let operand = |ty: ValueType| base + 5 + offset(ty);
+
+		// the scratch locals of the operands, by the position in the block and the type.
+		let mut scratch = BTreeMap::new();
+		let mut scratch_locals = Vec::new();
+		let mut code = Vec::new();
+		for (pc, instruction) in function.code.iter().enumerate() {
+			let operands = function
+				.stacks
+				.get(pc)
+				.cloned()
+				.flatten()
+				.unwrap_or_default()
+				.into_iter()
+				.enumerate()
+				.map(|(i, ty)| {
+					let next = (function.locals.len() + scratch_locals.len()) as u32;
+					let index = *scratch.entry((i, offset(ty))).or_insert_with(|| {
+						scratch_locals.push(ty);
+						next
+					});
+					(index, ty)
+				})
+				.collect::<Vec<_>>();
+
+			// spill the operands, the top first.
+			for (index, _) in operands.iter().rev() {
+				code.push(Instruction::SetLocal(*index));
+			}
+			code.extend_from_slice(&[
+				Instruction::I32Const(function.index as i32),
+				Instruction::I32Const(pc as i32),
+				Instruction::Call(probe),
+				Instruction::If(BlockType::NoResult),
+			]);
+			for (i, ty) in function.locals.iter().enumerate() {
+				code.push(Instruction::GetLocal(i as u32));
+				code.push(Instruction::Call(local(*ty)));
+			}
+			for (index, ty) in operands.iter() {
+				code.push(Instruction::GetLocal(*index));
+				code.push(Instruction::Call(operand(*ty)));
+			}
+			code.push(Instruction::Call(pause));
+			code.push(Instruction::End);
+			// push the operands back.
+			for (index, _) in operands.iter() {
+				code.push(Instruction::GetLocal(*index));
+			}
+			code.push(instruction.clone());
+		}
+		instrument::Rewritten {
+			code,
+			locals: scratch_locals,
+		}
+	})
+}
@@ -0,0 +1,260 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Differential execution
+//!
+//! In the differential mode, every `Instance::invoke` is executed by the selected backend (the
+//! primary one) and then by the other backend (the secondary one) with the host calls recorded
+//! from the primary execution. The return values, traps, host calls and memories are compared
+//! after the invocation, the divergence is reported as a warning, and recorded into the contract
+//! tracing by the `SandboxDivergencesExt` externalities extension.
+//!
+//! The result of the primary backend is always returned, the secondary backend never calls the
+//! host functions.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ep_extensions::SandboxDivergencesExt;
+use sp_externalities::ExternalitiesExt;
+
+use crate::{
+	imp::{self, recorder},
+	Error, HostFuncType, ReturnValue, SandboxExecutor, Value,
+};
+
+static DIFFERENTIAL: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable the differential mode for the instances which are created after this call.
+///
+/// Returns `Err` if both backends are not compiled in.
+pub fn set_differential(enable: bool) -> Result<(), String> {
+	if enable
+		&& !(SandboxExecutor::Interpreter.is_available() && SandboxExecutor::Jit.is_available())
+	{
+		return Err("The differential mode requires both sandbox executors".into());
+	}
+	DIFFERENTIAL.store(enable, Ordering::Relaxed);
+	Ok(())
+}
+
+/// Whether the differential mode is enabled.
+pub fn differential() -> bool {
+	DIFFERENTIAL.load(Ordering::Relaxed)
+}
+
+/// The backend which checks the execution of `primary`.
+pub(crate) fn secondary(primary: SandboxExecutor) -> SandboxExecutor {
+	match primary {
+		SandboxExecutor::Interpreter => SandboxExecutor::Jit,
+		SandboxExecutor::Jit => SandboxExecutor::Interpreter,
+	}
+}
+
+/// The divergence between the backends in an invocation.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Divergence {
+	/// The depth of the invocation, `1` for the outermost one.
+	pub depth: usize,
+	/// The invoked export.
+	pub function: String,
+	pub primary: SandboxExecutor,
+	pub secondary: SandboxExecutor,
+	pub reasons: Vec<DivergenceReason>,
+}
+
+/// The reason of the divergence.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DivergenceReason {
+	/// The secondary backend could not instantiate the module.
+	Instantiate { error: String },
+	/// The secondary backend calls another host function, or with other arguments.
+	HostCall {
+		index: usize,
+		expected: Option<String>,
+		actual: String,
+	},
+	/// The secondary backend finishes before calling all the host functions.
+	MissingHostCalls { remaining: usize },
+	/// The return values or traps are different.
+	Result { primary: String, secondary: String },
+	/// The memory is different after the invocation.
+	Memory {
+		index: usize,
+		/// The first different byte, `None` if only the sizes are different.
+		offset: Option<usize>,
+		primary_size: usize,
+		secondary_size: usize,
+	},
+}
+
+/// Report the divergence in log and the contract tracing.
+fn report(divergence: Divergence) {
+	log::warn!(
+		target: "sandbox",
+		"{} and {} diverge in `{}` at depth {}: {:?}",
+		divergence.primary,
+		divergence.secondary,
+		divergence.function,
+		divergence.depth,
+		divergence.reasons
+	);
+	if let Ok(record) = serde_json::to_vec(&divergence) {
+		sp_externalities::with_externalities(|ext| {
+			if let Some(records) = ext.extension::<SandboxDivergencesExt>() {
+				records.0.push(record);
+			}
+		});
+	}
+}
+
+/// The environment for the secondary backend.
+pub(crate) struct ShadowBuilder<T> {
+	builder: imp::EnvironmentDefinitionBuilder<T>,
+	/// The (primary, secondary) memories.
+	memories: Vec<(imp::Memory, imp::Memory)>,
+}
+
+impl<T> ShadowBuilder<T> {
+	pub fn new() -> Self {
+		ShadowBuilder {
+			builder: imp::EnvironmentDefinitionBuilder::new(),
+			memories: Vec::new(),
+		}
+	}
+
+	pub fn add_host_func(&mut self, module: Vec<u8>, field: Vec<u8>, f: HostFuncType<T>) {
+		self.builder.add_host_func(module, field, f);
+	}
+
+	pub fn add_memory(
+		&mut self,
+		module: Vec<u8>,
+		field: Vec<u8>,
+		primary: imp::Memory,
+		secondary: imp::Memory,
+	) {
+		self.builder.add_memory(module, field, secondary.clone());
+		self.memories.push((primary, secondary));
+	}
+
+	/// Instantiate the module by the secondary backend of `primary`.
+	pub fn instantiate(
+		&self,
+		primary: SandboxExecutor,
+		code: &[u8],
+		state: &mut T,
+	) -> Option<Shadow<T>> {
+		let executor = secondary(primary);
+		match imp::Instance::new(executor, code, &self.builder, state) {
+			Ok(instance) => Some(Shadow {
+				instance,
+				memories: self.memories.clone(),
+			}),
+			Err(e) => {
+				report(Divergence {
+					depth: recorder::depth(),
+					function: "<instantiate>".into(),
+					primary,
+					secondary: executor,
+					reasons: vec![DivergenceReason::Instantiate {
+						error: format!("{:?}", e),
+					}],
+				});
+				None
+			}
+		}
+	}
+}
+
+/// The instance of the secondary backend.
+pub(crate) struct Shadow<T> {
+	instance: imp::Instance<T>,
+	memories: Vec<(imp::Memory, imp::Memory)>,
+}
+
+impl<T> Shadow<T> {
+	/// Invoke `name` by both backends, and report the divergence.
+	pub fn invoke(
+		&mut self,
+		primary: &mut imp::Instance<T>,
+		name: &str,
+		args: &[Value],
+		state: &mut T,
+	) -> Result<ReturnValue, Error> {
+		let depth = recorder::depth();
+		recorder::begin_record();
+		let result = primary.invoke(name, args, state);
+		let calls = recorder::end_record();
+
+		recorder::begin_replay(calls);
+		let shadow_result = self.instance.invoke(name, args, state);
+		let replayed = recorder::end_replay();
+
+		let mut reasons = Vec::new();
+		if let Some(mismatch) = replayed.mismatch {
+			// the results and memories are meaningless after the host calls diverge.
+			reasons.push(DivergenceReason::HostCall {
+				index: mismatch.index,
+				expected: mismatch.expected.map(|(name, args)| describe_call(&name, &args)),
+				actual: describe_call(&mismatch.actual.0, &mismatch.actual.1),
+			});
+		} else {
+			if replayed.remaining > 0 {
+				reasons.push(DivergenceReason::MissingHostCalls {
+					remaining: replayed.remaining,
+				});
+			}
+			if !same_result(&result, &shadow_result) {
+				reasons.push(DivergenceReason::Result {
+					primary: describe_result(&result),
+					secondary: describe_result(&shadow_result),
+				});
+			}
+			for (index, (primary, secondary)) in self.memories.iter().enumerate() {
+				let (primary, secondary) = (primary.to_vec(), secondary.to_vec());
+				if primary != secondary {
+					reasons.push(DivergenceReason::Memory {
+						index,
+						offset: primary.iter().zip(secondary.iter()).position(|(a, b)| a != b),
+						primary_size: primary.len(),
+						secondary_size: secondary.len(),
+					});
+				}
+			}
+		}
+
+		if !reasons.is_empty() {
+			report(Divergence {
+				depth,
+				function: name.into(),
+				primary: primary.executor(),
+				secondary: self.instance.executor(),
+				reasons,
+			});
+		}
+		result
+	}
+}
+
+fn describe_call(name: &str, args: &[Value]) -> String {
+	format!("{}{:?}", name, args)
+}
+
+fn same_result(a: &Result<ReturnValue, Error>, b: &Result<ReturnValue, Error>) -> bool {
+	match (a, b) {
+		(Ok(a), Ok(b)) => a == b,
+		// the backtraces are different between the backends.
+		(Err(Error::Trap(a)), Err(Error::Trap(b))) => a.code == b.code,
+		(Err(a), Err(b)) => std::mem::discriminant(a) == std::mem::discriminant(b),
+		_ => false,
+	}
+}
+
+fn describe_result(result: &Result<ReturnValue, Error>) -> String {
+	match result {
+		Ok(value) => format!("{:?}", value),
+		Err(Error::Trap(trap)) => format!("Trap({:?})", trap.code),
+		Err(e) => format!("{:?}", e),
+	}
+}
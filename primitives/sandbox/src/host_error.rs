@@ -0,0 +1,95 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Typed host errors
+//!
+//! The host functions fail with the opaque [`HostError`], which is kept for the compatibility of
+//! [`crate::HostFuncType`]. A host function tells why it fails by returning the error built by
+//! [`host_error`] or [`host_error_with_payload`] instead, the reason is kept aside until the
+//! backend raises the trap, and the trap carries it as a [`HostFailure`] together with the import
+//! name of the function. The failure is the first line of the backtrace, e.g.
+//! `seal_set_storage: value too large`.
+use std::{cell::RefCell, fmt};
+
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, HostError};
+
+/// The reason of the host function which does not tell why it fails.
+const UNKNOWN_REASON: &str = "the host function failed";
+
+thread_local! {
+	/// The reason and the payload of the failing host function.
+	static REASON: RefCell<Option<(String, Option<Vec<u8>>)>> = RefCell::new(None);
+}
+
+/// The failure of a host function, which is carried by the trap.
+#[derive(Clone, PartialEq, Eq, Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct HostFailure {
+	/// The import name of the host function, in `module.field`.
+	pub import: String,
+	/// Why the host function fails.
+	pub reason: String,
+	/// The data attached by the host function, e.g. the value which is too large.
+	pub payload: Option<Vec<u8>>,
+}
+
+impl HostFailure {
+	/// The field of the import name, which is the name of the host function.
+	pub fn field(&self) -> &str {
+		self.import
+			.find('.')
+			.map_or(&self.import[..], |dot| &self.import[dot + 1..])
+	}
+}
+
+impl fmt::Display for HostFailure {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.field(), self.reason)
+	}
+}
+
+/// Fail the host function with `reason`.
+pub fn host_error(reason: impl Into<String>) -> HostError {
+	REASON.with(|r| *r.borrow_mut() = Some((reason.into(), None)));
+	HostError
+}
+
+/// Fail the host function with `reason` and the data of the failure.
+pub fn host_error_with_payload(reason: impl Into<String>, payload: Vec<u8>) -> HostError {
+	REASON.with(|r| *r.borrow_mut() = Some((reason.into(), Some(payload))));
+	HostError
+}
+
+/// Drop the reason which is not taken, before a host function is called.
+pub(crate) fn clear() {
+	REASON.with(|r| r.borrow_mut().take());
+}
+
+/// The failure of the host function `import` (in `module.field`), which returns `HostError`.
+pub(crate) fn failure(import: &str) -> HostFailure {
+	let (reason, payload) = REASON
+		.with(|r| r.borrow_mut().take())
+		.unwrap_or_else(|| (UNKNOWN_REASON.into(), None));
+	HostFailure {
+		import: import.into(),
+		reason,
+		payload,
+	}
+}
+
+impl From<Error> for HostError {
+	fn from(e: Error) -> HostError {
+		host_error(match e {
+			Error::Module => "the module could not be instantiated".into(),
+			Error::OutOfBounds => "the memory or table access is out of bounds".into(),
+			Error::Execution => "the execution failed".into(),
+			Error::Trap(trap) => match trap.host_error {
+				Some(failure) => format!("the nested instance failed at {}", failure),
+				None => format!("the nested instance trapped with {:?}", trap.code),
+			},
+		})
+	}
+}
@@ -2,15 +2,508 @@
 
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
+//! The backends of the sandbox, both of them could be compiled in, and the backend is selected
+//! by `SandboxExecutor` at runtime.
+use std::{
+	fmt,
+	str::FromStr,
+	sync::atomic::{AtomicU8, Ordering},
+	time::Duration,
+};
+
+use crate::{
+	introspection::{ExportType, FuncType},
+	limits, validation, Error, HostFailure, HostFuncType, ReturnValue, Value,
+};
+
+pub(crate) mod recorder;
+
 #[cfg(feature = "interpreter")]
 mod wasmi;
-#[cfg(feature = "interpreter")]
-pub use self::wasmi::*;
 
 #[cfg(feature = "jit")]
 mod wasmtime;
-#[cfg(feature = "jit")]
-pub use self::wasmtime::*;
+
+#[cfg(not(any(feature = "interpreter", feature = "jit")))]
+compile_error!("at least one of the features `interpreter` and `jit` must be enabled");
+
+/// The backend which executes the sandboxed modules.
+#[derive(
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	sp_core::RuntimeDebug,
+	codec::Encode,
+	codec::Decode,
+	serde::Serialize,
+	serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxExecutor {
+	/// The forked wasmi, which provides the wasm backtrace with the name section.
+	Interpreter,
+	/// Wasmtime, which provides the wasm backtrace with DWARF.
+	Jit,
+}
+
+impl SandboxExecutor {
+	/// All the variants, for the cli.
+	pub const VARIANTS: [&'static str; 2] = ["interpreter", "jit"];
+
+	/// Whether the backend is compiled in.
+	pub fn is_available(&self) -> bool {
+		match self {
+			SandboxExecutor::Interpreter => cfg!(feature = "interpreter"),
+			SandboxExecutor::Jit => cfg!(feature = "jit"),
+		}
+	}
+
+	fn from_u8(v: u8) -> Option<Self> {
+		match v {
+			1 => Some(SandboxExecutor::Interpreter),
+			2 => Some(SandboxExecutor::Jit),
+			_ => None,
+		}
+	}
+
+	fn to_u8(self) -> u8 {
+		match self {
+			SandboxExecutor::Interpreter => 1,
+			SandboxExecutor::Jit => 2,
+		}
+	}
+}
+
+impl Default for SandboxExecutor {
+	/// `Jit` if it is compiled in, as before the backend is selectable.
+	fn default() -> Self {
+		if cfg!(feature = "jit") {
+			SandboxExecutor::Jit
+		} else {
+			SandboxExecutor::Interpreter
+		}
+	}
+}
+
+impl FromStr for SandboxExecutor {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"interpreter" | "wasmi" => Ok(SandboxExecutor::Interpreter),
+			"jit" | "wasmtime" => Ok(SandboxExecutor::Jit),
+			other => Err(format!(
+				"Unknown sandbox executor `{}`, expected one of {:?}",
+				other,
+				Self::VARIANTS
+			)),
+		}
+	}
+}
+
+impl fmt::Display for SandboxExecutor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SandboxExecutor::Interpreter => write!(f, "interpreter"),
+			SandboxExecutor::Jit => write!(f, "jit"),
+		}
+	}
+}
+
+/// The executor selected for the process, `0` means the default one.
+static EXECUTOR: AtomicU8 = AtomicU8::new(0);
+
+/// Select the backend for the `Memory` and `Instance` which are created after this call.
+///
+/// Returns `Err` if the backend is not compiled in.
+pub fn set_executor(executor: SandboxExecutor) -> Result<(), String> {
+	if !executor.is_available() {
+		return Err(format!(
+			"The sandbox executor `{}` is not compiled in ep-sandbox",
+			executor
+		));
+	}
+	EXECUTOR.store(executor.to_u8(), Ordering::Relaxed);
+	Ok(())
+}
+
+/// The backend selected by `set_executor`, or the default one.
+pub fn executor() -> SandboxExecutor {
+	SandboxExecutor::from_u8(EXECUTOR.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// The statistics of the compiled module cache of the `jit` executor.
+#[derive(Clone, Copy, Default, PartialEq, Eq, sp_core::RuntimeDebug, serde::Serialize)]
+pub struct ModuleCacheStats {
+	/// The number of the instantiations which reuse a cached module.
+	pub hits: u64,
+	/// The number of the instantiations which compile the module.
+	pub misses: u64,
+	/// The number of the cached modules.
+	pub cached: usize,
+	/// The maximum number of the cached modules.
+	pub capacity: usize,
+}
+
+/// The statistics of the compiled module cache, all zero if the `jit` executor is not compiled in.
+pub fn module_cache_stats() -> ModuleCacheStats {
+	#[cfg(feature = "jit")]
+	{
+		self::wasmtime::cache::stats()
+	}
+	#[cfg(not(feature = "jit"))]
+	{
+		ModuleCacheStats::default()
+	}
+}
+
+/// Serve the GDB remote serial protocol at `127.0.0.1:port` for the contracts executed by the
+/// `jit` executor, and pause the next invocation until a debugger attaches. Stop serving if `port`
+/// is `None`.
+///
+/// Returns `Err` if the `jit` executor is not compiled in, the server is not supported, or the
+/// engine is already created without the native DWARF, i.e. the port is not set at startup.
+pub fn set_debug_port(port: Option<u16>) -> Result<(), String> {
+	#[cfg(feature = "jit")]
+	{
+		self::wasmtime::gdb::set_port(port)
+	}
+	#[cfg(not(feature = "jit"))]
+	{
+		match port {
+			Some(_) => Err("The contract debug port requires the `jit` executor".into()),
+			None => Ok(()),
+		}
+	}
+}
+
+/// The budgets of an invocation by the `jit` executor, `None` is unlimited.
+#[derive(Clone, Copy, Default, PartialEq, Eq, sp_core::RuntimeDebug)]
+pub struct Interruption {
+	/// The fuel, which is consumed by roughly one per instruction.
+	pub fuel: Option<u64>,
+	/// The wall-clock time, including the nested calls, of the dry-runs only.
+	pub timeout: Option<Duration>,
+}
+
+/// Interrupt the invocations by the `jit` executor which exceed the budgets, with
+/// `TrapCode::Interrupt` and the backtrace where they are stopped.
+///
+/// Returns `Err` if the `jit` executor is not compiled in, or the fuel budget is set after the
+/// `jit` executor is used.
+pub fn set_interruption(interruption: Interruption) -> Result<(), String> {
+	#[cfg(feature = "jit")]
+	{
+		self::wasmtime::interrupt::set(interruption)
+	}
+	#[cfg(not(feature = "jit"))]
+	{
+		if interruption == Interruption::default() {
+			Ok(())
+		} else {
+			Err("The interruption requires the `jit` executor".into())
+		}
+	}
+}
+
+/// The memory of the backend which creates it.
+#[derive(Clone)]
+pub enum Memory {
+	#[cfg(feature = "interpreter")]
+	Interpreter(self::wasmi::Memory),
+	#[cfg(feature = "jit")]
+	Jit(self::wasmtime::Memory),
+}
+
+impl Memory {
+	pub fn new(
+		executor: SandboxExecutor,
+		initial: u32,
+		maximum: Option<u32>,
+	) -> Result<Memory, Error> {
+		let maximum = limits::check_memory(initial, maximum)?;
+		match executor {
+			#[cfg(feature = "interpreter")]
+			SandboxExecutor::Interpreter => Ok(Memory::Interpreter(self::wasmi::Memory::new(
+				initial, maximum,
+			)?)),
+			#[cfg(feature = "jit")]
+			SandboxExecutor::Jit => Ok(Memory::Jit(self::wasmtime::Memory::new(
+				initial, maximum,
+			)?)),
+			// the backend is not compiled in.
+			#[allow(unreachable_patterns)]
+			_ => Err(Error::Module),
+		}
+	}
+
+	pub fn executor(&self) -> SandboxExecutor {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Memory::Interpreter(_) => SandboxExecutor::Interpreter,
+			#[cfg(feature = "jit")]
+			Memory::Jit(_) => SandboxExecutor::Jit,
+		}
+	}
+
+	pub fn get(&self, ptr: u32, buf: &mut [u8]) -> Result<(), Error> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Memory::Interpreter(memory) => memory.get(ptr, buf),
+			#[cfg(feature = "jit")]
+			Memory::Jit(memory) => memory.get(ptr, buf),
+		}
+	}
+
+	pub fn set(&self, ptr: u32, value: &[u8]) -> Result<(), Error> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Memory::Interpreter(memory) => memory.set(ptr, value),
+			#[cfg(feature = "jit")]
+			Memory::Jit(memory) => memory.set(ptr, value),
+		}
+	}
+
+	pub fn size(&self) -> usize {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Memory::Interpreter(memory) => memory.size(),
+			#[cfg(feature = "jit")]
+			Memory::Jit(memory) => memory.size(),
+		}
+	}
+
+	/// Read the whole memory.
+	pub fn to_vec(&self) -> Vec<u8> {
+		let mut buf = vec![0; self.size()];
+		// the range is in bounds.
+		let _ = self.get(0, &mut buf);
+		buf
+	}
+}
+
+enum Definition<T> {
+	HostFunc(HostFuncType<T>),
+	Memory(Memory),
+}
+
+/// The definitions are kept in order, and resolved by the backend of the instance.
+pub struct EnvironmentDefinitionBuilder<T> {
+	definitions: Vec<(Vec<u8>, Vec<u8>, Definition<T>)>,
+}
+
+impl<T> EnvironmentDefinitionBuilder<T> {
+	pub fn new() -> EnvironmentDefinitionBuilder<T> {
+		EnvironmentDefinitionBuilder {
+			definitions: Vec::new(),
+		}
+	}
+
+	pub fn add_host_func<N1, N2>(&mut self, module: N1, field: N2, f: HostFuncType<T>)
+	where
+		N1: Into<Vec<u8>>,
+		N2: Into<Vec<u8>>,
+	{
+		self.definitions
+			.push((module.into(), field.into(), Definition::HostFunc(f)));
+	}
+
+	pub fn add_memory<N1, N2>(&mut self, module: N1, field: N2, mem: Memory)
+	where
+		N1: Into<Vec<u8>>,
+		N2: Into<Vec<u8>>,
+	{
+		self.definitions
+			.push((module.into(), field.into(), Definition::Memory(mem)));
+	}
+
+	/// The backend of the memories, or the selected backend if there is no memory.
+	///
+	/// Returns `Err` if the memories are created by different backends.
+	pub fn executor(&self) -> Result<SandboxExecutor, Error> {
+		let mut executors = self.definitions.iter().filter_map(|(_, _, def)| match def {
+			Definition::Memory(mem) => Some(mem.executor()),
+			Definition::HostFunc(_) => None,
+		});
+		match executors.next() {
+			Some(first) if executors.all(|e| e == first) => Ok(first),
+			Some(_) => Err(Error::Module),
+			None => Ok(executor()),
+		}
+	}
+
+	#[cfg(feature = "interpreter")]
+	fn interpreter(&self) -> Result<self::wasmi::EnvironmentDefinitionBuilder<T>, Error> {
+		let mut builder = self::wasmi::EnvironmentDefinitionBuilder::new();
+		for (module, field, def) in self.definitions.iter() {
+			match def {
+				Definition::HostFunc(f) => builder.add_host_func(module.clone(), field.clone(), *f),
+				Definition::Memory(Memory::Interpreter(mem)) => {
+					builder.add_memory(module.clone(), field.clone(), mem.clone())
+				}
+				#[allow(unreachable_patterns)]
+				Definition::Memory(_) => return Err(Error::Module),
+			}
+		}
+		Ok(builder)
+	}
+
+	#[cfg(feature = "jit")]
+	fn jit(&self) -> Result<self::wasmtime::EnvironmentDefinitionBuilder<T>, Error> {
+		let mut builder = self::wasmtime::EnvironmentDefinitionBuilder::new();
+		for (module, field, def) in self.definitions.iter() {
+			match def {
+				Definition::HostFunc(f) => builder.add_host_func(module.clone(), field.clone(), *f),
+				Definition::Memory(Memory::Jit(mem)) => {
+					builder.add_memory(module.clone(), field.clone(), mem.clone())
+				}
+				#[allow(unreachable_patterns)]
+				Definition::Memory(_) => return Err(Error::Module),
+			}
+		}
+		Ok(builder)
+	}
+}
+
+/// The instance of the backend which instantiates it.
+pub enum Instance<T> {
+	#[cfg(feature = "interpreter")]
+	Interpreter(self::wasmi::Instance<T>),
+	#[cfg(feature = "jit")]
+	Jit(self::wasmtime::Instance<T>),
+}
+
+impl<T> Instance<T> {
+	/// Instantiate the module with `executor`, the memories in `env_def_builder` must be created
+	/// by the same backend.
+	pub fn new(
+		executor: SandboxExecutor,
+		code: &[u8],
+		env_def_builder: &EnvironmentDefinitionBuilder<T>,
+		state: &mut T,
+	) -> Result<Instance<T>, Error> {
+		limits::check_module(code)?;
+		let instance = match executor {
+			#[cfg(feature = "interpreter")]
+			SandboxExecutor::Interpreter => {
+				self::wasmi::Instance::new(code, &env_def_builder.interpreter()?, state)
+					.map(Instance::Interpreter)
+			}
+			#[cfg(feature = "jit")]
+			SandboxExecutor::Jit => self::wasmtime::Instance::new(code, &env_def_builder.jit()?, state)
+				.map(Instance::Jit),
+			// the backend is not compiled in.
+			#[allow(unreachable_patterns)]
+			_ => Err(Error::Module),
+		};
+		// the backends do not tell why the module could not be instantiated.
+		if let Err(Error::Module) = instance {
+			let report = validation::validate(code);
+			if !report.is_valid() {
+				log::warn!(target: "sandbox", "The module could not be instantiated:\n{}", report);
+			}
+		}
+		instance
+	}
+
+	pub fn executor(&self) -> SandboxExecutor {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(_) => SandboxExecutor::Interpreter,
+			#[cfg(feature = "jit")]
+			Instance::Jit(_) => SandboxExecutor::Jit,
+		}
+	}
+
+	pub fn invoke(
+		&mut self,
+		name: &str,
+		args: &[Value],
+		state: &mut T,
+	) -> Result<ReturnValue, Error> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.invoke(name, args, state),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.invoke(name, args, state),
+		}
+	}
+
+	/// The profile of the last invocation of `function`, if the instance is profiled.
+	pub fn profile(&self, function: &str, depth: u32) -> Option<crate::Profile> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.profile(function, depth),
+			#[cfg(feature = "jit")]
+			Instance::Jit(_) => None,
+		}
+	}
+
+	/// Take the coverage of the last invocation, if the instance is instrumented for coverage.
+	pub fn take_coverage(&mut self) -> Option<crate::CodeCoverage> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.take_coverage(),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.take_coverage(),
+		}
+	}
+
+	pub fn get_global_val(&self, name: &str) -> Option<Value> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.get_global_val(name),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.get_global_val(name),
+		}
+	}
+
+	pub fn exports(&self) -> Vec<ExportType> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.exports(),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.exports(),
+		}
+	}
+
+	pub fn memory_size(&self, name: &str) -> Option<u32> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.memory_size(name),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.memory_size(name),
+		}
+	}
+
+	pub fn read_memory(&self, name: &str, ptr: u32, buf: &mut [u8]) -> Result<(), Error> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.read_memory(name, ptr, buf),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.read_memory(name, ptr, buf),
+		}
+	}
+
+	pub fn table_size(&self, name: &str) -> Option<u32> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.table_size(name),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.table_size(name),
+		}
+	}
+
+	pub fn table_get(&self, name: &str, index: u32) -> Result<Option<FuncType>, Error> {
+		match self {
+			#[cfg(feature = "interpreter")]
+			Instance::Interpreter(instance) => instance.table_get(name, index),
+			#[cfg(feature = "jit")]
+			Instance::Jit(instance) => instance.table_get(name, index),
+		}
+	}
+}
 
 /// A trap code describing the reason for a trap.
 ///
@@ -65,15 +558,70 @@ pub enum TrapCode {
 
 	// Unknown Error
 	Unknown,
+
+	/// The memory exceeds the maximum pages of the sandbox limits.
+	MemoryLimitExceeded,
+
+	/// The wasm call stack exceeds the maximum height of the sandbox limits.
+	StackLimitExceeded,
+
+	/// The table exceeds the maximum size of the sandbox limits.
+	TableLimitExceeded,
+
+	/// The module exceeds the maximum size of the sandbox limits.
+	ModuleSizeExceeded,
 }
 
 /// Wasm Trap
 #[derive(
-	sp_core::RuntimeDebug, codec::Encode, codec::Decode, serde::Serialize, serde::Deserialize,
+	Clone,
+	PartialEq,
+	sp_core::RuntimeDebug,
+	codec::Encode,
+	codec::Decode,
+	serde::Serialize,
+	serde::Deserialize,
 )]
 pub struct Trap {
 	/// Trap code
 	pub code: TrapCode,
-	/// Wasm backtrace (in wasmtime, this includes native backtrace)
+	/// Wasm backtrace, the innermost frame first.
+	///
+	/// In wasmtime, the frames of the contracts built with debug info carry `file:line:column`.
 	pub trace: Vec<String>,
+	/// The failure of the host function if the trap is raised by the host, which is the first
+	/// line of the backtrace as well.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub host_error: Option<HostFailure>,
+}
+
+impl Trap {
+	/// The trap raised by the failing host function.
+	pub(crate) fn host(failure: HostFailure, mut trace: Vec<String>) -> Trap {
+		trace.insert(0, failure.to_string());
+		Trap {
+			code: TrapCode::HostError,
+			trace,
+			host_error: Some(failure),
+		}
+	}
+}
+
+impl fmt::Display for Trap {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.trace.is_empty() {
+			write!(f, "[]")?;
+		} else {
+			for (index, frame) in self.trace.iter().enumerate() {
+				if index == self.trace.len() - 1 {
+					write!(f, "\n\t╰─>")?;
+				} else {
+					write!(f, "\n\t|  ")?;
+				}
+				write!(f, "{}", frame)?;
+			}
+		}
+
+		Ok(())
+	}
 }
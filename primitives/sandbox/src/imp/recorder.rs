@@ -0,0 +1,236 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Host-call recording layer
+//!
+//! In the differential mode, the host calls (with the memory writes done by the host) are
+//! recorded while the primary backend executes, and replayed to the secondary backend, thus
+//! the secondary backend gets the identical host-function inputs without calling the host again.
+//!
+//! The frames are in a stack, for the host function (e.g. `seal_call`) may invoke another
+//! instance. The replay frames also re-execute the reproducers offline, see [`crate::replay`].
+use std::{cell::RefCell, collections::VecDeque};
+
+use super::Memory;
+use crate::{debug_message, host_error, replay, HostError, HostFuncType, ReturnValue, Value};
+
+/// A memory write done by the host, which is applied to the memory of the secondary backend.
+pub struct MemoryWrite {
+	pub memory: Memory,
+	pub ptr: u32,
+	pub data: Vec<u8>,
+}
+
+/// A recorded host call.
+pub struct HostCall {
+	/// The import name of the host function, in `module.field`.
+	pub name: String,
+	pub args: Vec<Value>,
+	pub result: Result<ReturnValue, ()>,
+	pub writes: Vec<MemoryWrite>,
+}
+
+/// The host call which the secondary backend does not agree with the recorded one.
+pub struct Mismatch {
+	/// The index of the host call.
+	pub index: usize,
+	pub expected: Option<(String, Vec<Value>)>,
+	pub actual: (String, Vec<Value>),
+}
+
+/// The result of the replay.
+pub struct Replayed {
+	pub mismatch: Option<Mismatch>,
+	/// The recorded calls which are not called by the secondary backend.
+	pub remaining: usize,
+}
+
+enum Frame {
+	Record {
+		calls: Vec<HostCall>,
+		/// The writes of current host call, `None` if no host call is in progress.
+		writes: Option<Vec<MemoryWrite>>,
+	},
+	Replay {
+		calls: VecDeque<HostCall>,
+		replayed: usize,
+		mismatch: Option<Mismatch>,
+	},
+}
+
+thread_local! {
+	static FRAMES: RefCell<Vec<Frame>> = RefCell::new(Vec::new());
+}
+
+/// The depth of the invocation which begins now, `1` for the outermost one.
+pub fn depth() -> usize {
+	FRAMES.with(|frames| frames.borrow().len() + 1)
+}
+
+/// Start recording the host calls of the primary backend.
+pub fn begin_record() {
+	FRAMES.with(|frames| {
+		frames.borrow_mut().push(Frame::Record {
+			calls: Vec::new(),
+			writes: None,
+		})
+	});
+}
+
+/// Stop recording, returns the recorded host calls.
+pub fn end_record() -> Vec<HostCall> {
+	FRAMES.with(|frames| match frames.borrow_mut().pop() {
+		Some(Frame::Record { calls, .. }) => calls,
+		_ => unreachable!("end_record must be paired with begin_record; qed"),
+	})
+}
+
+/// Start replaying the host calls to the secondary backend.
+pub fn begin_replay(calls: Vec<HostCall>) {
+	FRAMES.with(|frames| {
+		frames.borrow_mut().push(Frame::Replay {
+			calls: calls.into(),
+			replayed: 0,
+			mismatch: None,
+		})
+	});
+}
+
+/// Stop replaying.
+pub fn end_replay() -> Replayed {
+	FRAMES.with(|frames| match frames.borrow_mut().pop() {
+		Some(Frame::Replay {
+			calls, mismatch, ..
+		}) => Replayed {
+			mismatch,
+			remaining: calls.len(),
+		},
+		_ => unreachable!("end_replay must be paired with begin_replay; qed"),
+	})
+}
+
+/// Record the memory write done by the host in current host call.
+///
+/// Returns the write back if it is not recorded, which should be applied to `memory` directly.
+pub fn record_write(memory: Memory, ptr: u32, data: &[u8]) -> Option<Memory> {
+	FRAMES.with(|frames| match frames.borrow_mut().last_mut() {
+		Some(Frame::Record {
+			writes: Some(writes),
+			..
+		}) => {
+			writes.push(MemoryWrite {
+				memory,
+				ptr,
+				data: data.to_vec(),
+			});
+			None
+		}
+		_ => Some(memory),
+	})
+}
+
+/// The name of the host function in the recorded calls.
+pub fn host_name(module: &[u8], field: &[u8]) -> String {
+	format!(
+		"{}.{}",
+		String::from_utf8_lossy(module),
+		String::from_utf8_lossy(field)
+	)
+}
+
+/// Call the host function, and record the call into the reproducer in the recording mode.
+fn call<T>(
+	name: &str,
+	f: HostFuncType<T>,
+	state: &mut T,
+	args: &[Value],
+) -> Result<ReturnValue, HostError> {
+	let recorded = replay::begin_host_call();
+	let message = debug_message::read(name, args);
+	let result = f(state, args);
+	if recorded {
+		replay::end_host_call(name, args, &result);
+	}
+	if let Some(message) = message {
+		debug_message::report(message, &result);
+	}
+	result
+}
+
+/// Call the host function `name` (in `module.field`), all backends dispatch the host calls by
+/// this function.
+pub fn call_host<T>(
+	name: &str,
+	f: HostFuncType<T>,
+	state: &mut T,
+	args: &[Value],
+) -> Result<ReturnValue, HostError> {
+	host_error::clear();
+	let recording = FRAMES.with(|frames| match frames.borrow_mut().last_mut() {
+		Some(Frame::Record { writes, .. }) => {
+			*writes = Some(Vec::new());
+			Some(true)
+		}
+		Some(Frame::Replay { .. }) => Some(false),
+		None => None,
+	});
+
+	match recording {
+		None => call(name, f, state, args),
+		Some(true) => {
+			// NOTE: the frames must not be borrowed here, the host function may invoke another
+			// instance.
+			let result = call(name, f, state, args);
+			FRAMES.with(|frames| {
+				if let Some(Frame::Record { calls, writes }) = frames.borrow_mut().last_mut() {
+					calls.push(HostCall {
+						name: name.into(),
+						args: args.to_vec(),
+						result: result.as_ref().map(|r| *r).map_err(|_| ()),
+						writes: writes.take().unwrap_or_default(),
+					});
+				}
+			});
+			result
+		}
+		Some(false) => FRAMES.with(|frames| {
+			let mut frames = frames.borrow_mut();
+			let (calls, replayed, mismatch) = match frames.last_mut() {
+				Some(Frame::Replay {
+					calls,
+					replayed,
+					mismatch,
+				}) => (calls, replayed, mismatch),
+				_ => unreachable!("checked above; qed"),
+			};
+			if mismatch.is_some() {
+				return Err(host_error::host_error(
+					"the replay diverges from the recorded calls",
+				));
+			}
+			let index = *replayed;
+			*replayed += 1;
+			match calls.pop_front() {
+				Some(call) if call.name == name && call.args[..] == args[..] => {
+					for write in call.writes {
+						// the memories have the same size, the write does not fail if it does
+						// not fail in the primary backend.
+						let _ = write.memory.set(write.ptr, &write.data);
+					}
+					call.result.map_err(|_| HostError)
+				}
+				expected => {
+					*mismatch = Some(Mismatch {
+						index,
+						expected: expected.map(|call| (call.name, call.args)),
+						actual: (name.into(), args.to_vec()),
+					});
+					Err(host_error::host_error(
+						"the host call does not match the recorded one",
+					))
+				}
+			}
+		}),
+	}
+}
@@ -16,14 +16,26 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use sp_std::{collections::btree_map::BTreeMap, fmt, mem::transmute};
-
-use super::{Trap as OutterTrap, TrapCode};
-use crate::{Error, HostError, HostFuncType, ReturnValue, Value};
+use sp_std::{cell::RefCell, collections::btree_map::BTreeMap, mem::transmute};
+
+use super::{recorder, Trap as OutterTrap, TrapCode};
+use crate::{
+	coverage::{self, CodeCoverage},
+	debugger::{self, HostFunc as DebugHostFunc},
+	host_error,
+	instrument::Program,
+	introspection::{self, ExportType, ExternType, FuncType, ValType},
+	limits,
+	profiler::{self, HostFunc as ProfileHostFunc, Profiler},
+	snapshot::{self, HostFunc as SnapshotHostFunc},
+	Error, HostError, HostFailure, HostFuncType, Profile, ReturnValue, Value,
+};
 use patract_wasmi::{
-	memory_units::Pages, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef,
-	ImportResolver, MemoryDescriptor, MemoryInstance, MemoryRef, Module, ModuleInstance, ModuleRef,
-	RuntimeArgs, RuntimeValue, Signature, TableDescriptor, TableRef, Trap, TrapKind,
+	memory_units::{Bytes, Pages},
+	ExternVal, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef, ImportResolver,
+	MemoryDescriptor, MemoryInstance, MemoryRef, Module, ModuleInstance, ModuleRef, RuntimeArgs,
+	RuntimeValue, Signature, StackRecycler, TableDescriptor, TableRef, Trap, TrapKind, ValueType,
+	DEFAULT_VALUE_STACK_LIMIT,
 };
 
 #[derive(Clone)]
@@ -55,12 +67,40 @@ impl Memory {
 			.map_err(|_| Error::OutOfBounds)?;
 		Ok(())
 	}
+
+	/// The current size of the memory in bytes.
+	pub fn size(&self) -> usize {
+		Bytes::from(self.memref.current_size()).0
+	}
 }
 
 struct HostFuncIndex(usize);
 
+/// The indices of the debugger functions start from here, to be distinguished from the defined
+/// host functions.
+const DEBUG_FUNC_BASE: usize = usize::MAX / 2;
+/// The indices of the profiler functions start from here.
+const PROFILE_FUNC_BASE: usize = usize::MAX / 4 * 3;
+/// The index of the coverage function.
+const COVERAGE_FUNC_BASE: usize = usize::MAX / 8 * 7;
+/// The indices of the trap snapshot functions start from here.
+const SNAPSHOT_FUNC_BASE: usize = usize::MAX / 16 * 15;
+
+/// The instrumented instance, which is paused by the debugger.
+struct Debuggee {
+	program: Program,
+	memory: Option<MemoryRef>,
+	/// The location where to pause, which is set by the probe.
+	location: (u32, u32),
+	/// The locals of the paused frame.
+	locals: Vec<Value>,
+	/// The operands of the paused frame.
+	operands: Vec<Value>,
+}
+
 struct DefinedHostFunctions<T> {
-	funcs: Vec<HostFuncType<T>>,
+	/// The host functions with their import names.
+	funcs: Vec<(String, HostFuncType<T>)>,
 }
 
 impl<T> Clone for DefinedHostFunctions<T> {
@@ -76,27 +116,132 @@ impl<T> DefinedHostFunctions<T> {
 		DefinedHostFunctions { funcs: Vec::new() }
 	}
 
-	fn define(&mut self, f: HostFuncType<T>) -> HostFuncIndex {
+	fn define(&mut self, name: String, f: HostFuncType<T>) -> HostFuncIndex {
 		let idx = self.funcs.len();
-		self.funcs.push(f);
+		self.funcs.push((name, f));
 		HostFuncIndex(idx)
 	}
 }
 
-#[derive(Debug)]
-struct DummyHostError;
-
-impl fmt::Display for DummyHostError {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "DummyHostError")
-	}
-}
-
-impl patract_wasmi::HostError for DummyHostError {}
+impl patract_wasmi::HostError for HostFailure {}
 
 struct GuestExternals<'a, T> {
 	state: &'a mut T,
 	defined_host_functions: &'a DefinedHostFunctions<T>,
+	debuggee: Option<&'a mut Debuggee>,
+	profiler: Option<&'a mut Profiler>,
+	coverage: Option<&'a mut CodeCoverage>,
+}
+
+impl<'a, T> GuestExternals<'a, T> {
+	fn invoke_debug(&mut self, index: usize, args: &[Value]) -> Result<Option<RuntimeValue>, Trap> {
+		let debuggee = self
+			.debuggee
+			.as_mut()
+			.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))?;
+		let arg = |i: usize| {
+			args.get(i)
+				.and_then(|v| v.as_i32())
+				.map(|v| v as u32)
+				.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))
+		};
+		match DebugHostFunc::from_index(index) {
+			Some(DebugHostFunc::Probe) => {
+				let (function, pc) = (arg(0)?, arg(1)?);
+				let pause = debugger::probe(&debuggee.program, function, pc);
+				if pause {
+					debuggee.location = (function, pc);
+					debuggee.locals.clear();
+					debuggee.operands.clear();
+				}
+				Ok(Some(RuntimeValue::I32(pause as i32)))
+			}
+			Some(DebugHostFunc::Local) => {
+				debuggee.locals.extend(args.first().cloned());
+				Ok(None)
+			}
+			Some(DebugHostFunc::Operand) => {
+				debuggee.operands.extend(args.first().cloned());
+				Ok(None)
+			}
+			Some(DebugHostFunc::Pause) => {
+				let memory = debuggee.memory.clone();
+				let read = move |offset: u32, len: u32| match memory.as_ref() {
+					Some(memory) => memory.get(offset, len as usize).map_err(|e| e.to_string()),
+					None => Err("The instance has no imported memory".to_string()),
+				};
+				let (function, pc) = debuggee.location;
+				debugger::pause(
+					&debuggee.program,
+					function,
+					pc,
+					&debuggee.locals,
+					&debuggee.operands,
+					&read,
+				);
+				Ok(None)
+			}
+			None => Err(TrapKind::UnexpectedSignature.into()),
+		}
+	}
+
+	fn invoke_profile(
+		&mut self,
+		index: usize,
+		args: &[Value],
+	) -> Result<Option<RuntimeValue>, Trap> {
+		let profiler = self
+			.profiler
+			.as_mut()
+			.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))?;
+		let arg = || {
+			args.first()
+				.and_then(|v| v.as_i32())
+				.map(|v| v as u32)
+				.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))
+		};
+		match ProfileHostFunc::from_index(index) {
+			Some(ProfileHostFunc::Enter) => profiler.enter(arg()?),
+			Some(ProfileHostFunc::Count) => profiler.count(arg()? as u64),
+			Some(ProfileHostFunc::Call) => profiler.call(),
+			Some(ProfileHostFunc::Return) => profiler.returned(),
+			None => return Err(TrapKind::UnexpectedSignature.into()),
+		}
+		Ok(None)
+	}
+
+	fn invoke_coverage(&mut self, args: &[Value]) -> Result<Option<RuntimeValue>, Trap> {
+		let coverage = self
+			.coverage
+			.as_mut()
+			.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))?;
+		let block = args
+			.first()
+			.and_then(|v| v.as_i32())
+			.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))?;
+		coverage.hit(block as u32);
+		Ok(None)
+	}
+
+	fn invoke_snapshot(index: usize, args: &[Value]) -> Result<Option<RuntimeValue>, Trap> {
+		let arg = || {
+			args.first()
+				.cloned()
+				.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))
+		};
+		match SnapshotHostFunc::from_index(index) {
+			Some(SnapshotHostFunc::Enter) => snapshot::enter_frame(),
+			Some(SnapshotHostFunc::Exit) => snapshot::exit_frame(),
+			Some(SnapshotHostFunc::Record) => snapshot::record_frame(
+				arg()?
+					.as_i32()
+					.ok_or_else(|| Trap::from(TrapKind::UnexpectedSignature))? as u32,
+			),
+			Some(SnapshotHostFunc::Local) => snapshot::record_local(arg()?),
+			None => return Err(TrapKind::UnexpectedSignature.into()),
+		}
+		Ok(None)
+	}
 }
 
 impl<'a, T> Externals for GuestExternals<'a, T> {
@@ -114,7 +259,21 @@ impl<'a, T> Externals for GuestExternals<'a, T> {
 			})
 			.collect::<Vec<_>>();
 
-		let result = (self.defined_host_functions.funcs[index])(self.state, &args);
+		if let Some(index) = index.checked_sub(SNAPSHOT_FUNC_BASE) {
+			return Self::invoke_snapshot(index, &args);
+		}
+		if index >= COVERAGE_FUNC_BASE {
+			return self.invoke_coverage(&args);
+		}
+		if let Some(index) = index.checked_sub(PROFILE_FUNC_BASE) {
+			return self.invoke_profile(index, &args);
+		}
+		if let Some(index) = index.checked_sub(DEBUG_FUNC_BASE) {
+			return self.invoke_debug(index, &args);
+		}
+
+		let (name, f) = &self.defined_host_functions.funcs[index];
+		let result = recorder::call_host(name, *f, self.state, &args);
 		match result {
 			Ok(value) => Ok(match value {
 				ReturnValue::Value(v) => {
@@ -125,7 +284,7 @@ impl<'a, T> Externals for GuestExternals<'a, T> {
 				}
 				ReturnValue::Unit => None,
 			}),
-			Err(HostError) => Err(TrapKind::Host(Box::new(DummyHostError)).into()),
+			Err(HostError) => Err(TrapKind::Host(Box::new(host_error::failure(name))).into()),
 		}
 	}
 }
@@ -153,9 +312,11 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 		N1: Into<Vec<u8>>,
 		N2: Into<Vec<u8>>,
 	{
-		let idx = self.defined_host_functions.define(f);
-		self.map
-			.insert((module.into(), field.into()), ExternVal::HostFunc(idx));
+		let (module, field) = (module.into(), field.into());
+		let idx = self
+			.defined_host_functions
+			.define(recorder::host_name(&module, &field), f);
+		self.map.insert((module, field), ExternVal::HostFunc(idx));
 	}
 
 	pub fn add_memory<N1, N2>(&mut self, module: N1, field: N2, mem: Memory)
@@ -249,9 +410,133 @@ impl<T> ImportResolver for EnvironmentDefinitionBuilder<T> {
 	}
 }
 
+/// Resolve the imports by the environment, and the debugger, profiler or coverage functions if
+/// the module is instrumented, and the trap snapshot functions if it records the frames.
+struct Resolver<'a, T> {
+	env: &'a EnvironmentDefinitionBuilder<T>,
+	instrumented: Option<Instrumentation>,
+	tracked: bool,
+	/// The resolved memory, which is read by the debugger.
+	memory: RefCell<Option<MemoryRef>>,
+}
+
+impl<'a, T> ImportResolver for Resolver<'a, T> {
+	fn resolve_func(
+		&self,
+		module_name: &str,
+		field_name: &str,
+		signature: &Signature,
+	) -> Result<FuncRef, patract_wasmi::Error> {
+		let host_func = match self.instrumented {
+			Some(Instrumentation::Debug) if module_name == debugger::MODULE => {
+				Some(debugger::host_func(field_name).map(|index| DEBUG_FUNC_BASE + index))
+			}
+			Some(Instrumentation::Profile) if module_name == profiler::MODULE => {
+				Some(profiler::host_func(field_name).map(|index| PROFILE_FUNC_BASE + index))
+			}
+			Some(Instrumentation::Coverage) if module_name == coverage::MODULE => {
+				Some(Some(COVERAGE_FUNC_BASE).filter(|_| field_name == coverage::HIT))
+			}
+			_ if self.tracked && module_name == snapshot::MODULE => {
+				Some(snapshot::host_func(field_name).map(|index| SNAPSHOT_FUNC_BASE + index))
+			}
+			_ => None,
+		};
+		if let Some(index) = host_func {
+			let index = index.ok_or_else(|| {
+				patract_wasmi::Error::Instantiation(format!(
+					"Export {}:{} not found",
+					module_name, field_name
+				))
+			})?;
+			return Ok(FuncInstance::alloc_host(signature.clone(), index));
+		}
+		self.env.resolve_func(module_name, field_name, signature)
+	}
+
+	fn resolve_global(
+		&self,
+		module_name: &str,
+		field_name: &str,
+		global_type: &GlobalDescriptor,
+	) -> Result<GlobalRef, patract_wasmi::Error> {
+		self.env
+			.resolve_global(module_name, field_name, global_type)
+	}
+
+	fn resolve_memory(
+		&self,
+		module_name: &str,
+		field_name: &str,
+		memory_type: &MemoryDescriptor,
+	) -> Result<MemoryRef, patract_wasmi::Error> {
+		let memory = self
+			.env
+			.resolve_memory(module_name, field_name, memory_type)?;
+		*self.memory.borrow_mut() = Some(memory.clone());
+		Ok(memory)
+	}
+
+	fn resolve_table(
+		&self,
+		module_name: &str,
+		field_name: &str,
+		table_type: &TableDescriptor,
+	) -> Result<TableRef, patract_wasmi::Error> {
+		self.env.resolve_table(module_name, field_name, table_type)
+	}
+}
+
+/// The instrumentation of the module, the debugger takes precedence over the profiler, and the
+/// profiler over the coverage.
+#[derive(Clone, Copy)]
+enum Instrumentation {
+	Debug,
+	Profile,
+	Coverage,
+}
+
+/// The probes of the instrumented module.
+enum Probes {
+	Program(Program),
+	Coverage(CodeCoverage),
+}
+
+impl Instrumentation {
+	fn current() -> Option<Instrumentation> {
+		if debugger::enabled() {
+			Some(Instrumentation::Debug)
+		} else if profiler::profiling() {
+			Some(Instrumentation::Profile)
+		} else if coverage::coverage() {
+			Some(Instrumentation::Coverage)
+		} else {
+			None
+		}
+	}
+
+	fn instrument(&self, code: &[u8]) -> Result<(Vec<u8>, Probes), String> {
+		match self {
+			Instrumentation::Debug => {
+				debugger::instrument(code).map(|(code, program)| (code, Probes::Program(program)))
+			}
+			Instrumentation::Profile => {
+				profiler::instrument(code).map(|(code, program)| (code, Probes::Program(program)))
+			}
+			Instrumentation::Coverage => coverage::instrument(code)
+				.map(|(code, coverage)| (code, Probes::Coverage(coverage))),
+		}
+	}
+}
+
 pub struct Instance<T> {
 	instance: ModuleRef,
+	/// The names of the exports, which are not listed by wasmi.
+	exports: Vec<String>,
 	defined_host_functions: DefinedHostFunctions<T>,
+	debuggee: Option<Debuggee>,
+	profiler: Option<Profiler>,
+	coverage: Option<CodeCoverage>,
 }
 
 impl<T> Instance<T> {
@@ -260,17 +545,83 @@ impl<T> Instance<T> {
 		env_def_builder: &EnvironmentDefinitionBuilder<T>,
 		state: &mut T,
 	) -> Result<Instance<T>, Error> {
+		let instrumented = Instrumentation::current().and_then(|instrumentation| {
+			instrumentation
+				.instrument(code)
+				.map(|(code, program)| (instrumentation, code, program))
+				.map_err(|e| {
+					log::warn!(
+						target: "sandbox",
+						"Could not instrument the module for the {}: {}",
+						match instrumentation {
+							Instrumentation::Debug => "debugger",
+							Instrumentation::Profile => "profiler",
+							Instrumentation::Coverage => "coverage",
+						},
+						e
+					)
+				})
+				.ok()
+		});
+		let original = code;
+		let code = instrumented.as_ref().map_or(code, |(_, code, _)| &code[..]);
+		// the frames are recorded after the other instrumentation, which is not recorded.
+		let tracked = if snapshot::tracked() {
+			snapshot::instrument(code, original)
+				.map_err(|e| {
+					log::warn!(
+						target: "sandbox",
+						"Could not instrument the module for the trap snapshot: {}",
+						e
+					)
+				})
+				.ok()
+		} else {
+			None
+		};
+		let code = tracked.as_deref().unwrap_or(code);
+
 		let module = Module::from_buffer(code)
 			.map_err(|_| Error::Module)?
 			.try_parse_names();
+		let exports = introspection::export_names(code);
+		let resolver = Resolver {
+			env: env_def_builder,
+			instrumented: instrumented
+				.as_ref()
+				.map(|(instrumentation, _, _)| *instrumentation),
+			tracked: tracked.is_some(),
+			memory: RefCell::new(None),
+		};
 		let not_started_instance =
-			ModuleInstance::new(&module, env_def_builder).map_err(|_| Error::Module)?;
+			ModuleInstance::new(&module, &resolver).map_err(|_| Error::Module)?;
 
 		let defined_host_functions = env_def_builder.defined_host_functions.clone();
+		let (mut debuggee, mut profiler, mut coverage) = match instrumented {
+			Some((Instrumentation::Debug, _, Probes::Program(program))) => (
+				Some(Debuggee {
+					program,
+					memory: resolver.memory.into_inner(),
+					location: (0, 0),
+					locals: Vec::new(),
+					operands: Vec::new(),
+				}),
+				None,
+				None,
+			),
+			Some((Instrumentation::Profile, _, Probes::Program(program))) => {
+				(None, Some(Profiler::new(program)), None)
+			}
+			Some((_, _, Probes::Coverage(coverage))) => (None, None, Some(coverage)),
+			_ => (None, None, None),
+		};
 		let instance = {
 			let mut externals = GuestExternals {
 				state,
 				defined_host_functions: &defined_host_functions,
+				debuggee: debuggee.as_mut(),
+				profiler: profiler.as_mut(),
+				coverage: coverage.as_mut(),
 			};
 			let instance = not_started_instance
 				.run_start(&mut externals)
@@ -280,7 +631,11 @@ impl<T> Instance<T> {
 
 		Ok(Instance {
 			instance,
+			exports,
 			defined_host_functions,
+			debuggee,
+			profiler,
+			coverage,
 		})
 	}
 
@@ -298,11 +653,27 @@ impl<T> Instance<T> {
 				transmute::<wasmi::RuntimeValue, patract_wasmi::RuntimeValue>(wv)
 			})
 			.collect::<Vec<_>>();
+		if let Some(profiler) = self.profiler.as_mut() {
+			profiler.reset();
+		}
 		let mut externals = GuestExternals {
 			state,
 			defined_host_functions: &self.defined_host_functions,
+			debuggee: self.debuggee.as_mut(),
+			profiler: self.profiler.as_mut(),
+			coverage: self.coverage.as_mut(),
+		};
+		let max_stack_height = limits::limits().max_stack_height;
+		let result = match max_stack_height {
+			// the frames of the call stack are limited, the values are left to the default.
+			Some(height) => self.instance.invoke_export_with_stack(
+				&name,
+				&args,
+				&mut externals,
+				&mut StackRecycler::with_limits(DEFAULT_VALUE_STACK_LIMIT, height as usize),
+			),
+			None => self.instance.invoke_export(&name, &args, &mut externals),
 		};
-		let result = self.instance.invoke_export(&name, &args, &mut externals);
 
 		match result {
 			Ok(None) => Ok(ReturnValue::Unit),
@@ -312,12 +683,30 @@ impl<T> Instance<T> {
 				))
 			},
 			Err(e) => Err(match e {
-				patract_wasmi::Error::Trap(t) => Error::Trap(t.into()),
+				patract_wasmi::Error::Trap(t) => {
+					let mut trap: OutterTrap = t.into();
+					if trap.code == TrapCode::StackOverflow && max_stack_height.is_some() {
+						trap.code = TrapCode::StackLimitExceeded;
+					}
+					Error::Trap(trap)
+				}
 				_ => Error::Execution,
 			}),
 		}
 	}
 
+	/// The profile of the last invocation of `function` in the profiling mode.
+	pub fn profile(&self, function: &str, depth: u32) -> Option<Profile> {
+		self.profiler
+			.as_ref()
+			.map(|profiler| profiler.profile(function, depth))
+	}
+
+	/// Take the coverage of the last invocation in the coverage mode.
+	pub fn take_coverage(&mut self) -> Option<CodeCoverage> {
+		self.coverage.as_mut().map(|coverage| coverage.take())
+	}
+
 	pub fn get_global_val(&self, name: &str) -> Option<Value> {
 		let global = self.instance.export_by_name(name)?.as_global()?.get();
 
@@ -325,15 +714,96 @@ impl<T> Instance<T> {
 			transmute::<patract_wasmi::RuntimeValue, wasmi::RuntimeValue>(global).into()
 		})
 	}
+
+	pub fn exports(&self) -> Vec<ExportType> {
+		self.exports
+			.iter()
+			.filter_map(|name| {
+				let ty = match self.instance.export_by_name(name)? {
+					ExternVal::Func(func) => ExternType::Func(func_type(func.signature())),
+					ExternVal::Global(global) => ExternType::Global {
+						ty: val_type(global.value_type()),
+						mutable: global.is_mutable(),
+					},
+					ExternVal::Memory(memory) => ExternType::Memory {
+						initial: memory.initial().0 as u32,
+						maximum: memory.maximum().map(|maximum| maximum.0 as u32),
+					},
+					ExternVal::Table(table) => ExternType::Table {
+						element: ValType::FuncRef,
+						initial: table.initial_size(),
+						maximum: table.maximum_size(),
+					},
+				};
+				Some(ExportType {
+					name: name.clone(),
+					ty,
+				})
+			})
+			.collect()
+	}
+
+	/// The current pages of the exported memory.
+	pub fn memory_size(&self, name: &str) -> Option<u32> {
+		let memory = self.instance.export_by_name(name)?.as_memory()?.clone();
+		Some(memory.current_size().0 as u32)
+	}
+
+	pub fn read_memory(&self, name: &str, ptr: u32, buf: &mut [u8]) -> Result<(), Error> {
+		let memory = self
+			.instance
+			.export_by_name(name)
+			.and_then(|export| export.as_memory().cloned())
+			.ok_or(Error::Execution)?;
+		memory.get_into(ptr, buf).map_err(|_| Error::OutOfBounds)
+	}
+
+	/// The current elements of the exported table.
+	pub fn table_size(&self, name: &str) -> Option<u32> {
+		let table = self.instance.export_by_name(name)?.as_table()?.clone();
+		Some(table.current_size())
+	}
+
+	pub fn table_get(&self, name: &str, index: u32) -> Result<Option<FuncType>, Error> {
+		let table = self
+			.instance
+			.export_by_name(name)
+			.and_then(|export| export.as_table().cloned())
+			.ok_or(Error::Execution)?;
+		let func = table.get(index).map_err(|_| Error::OutOfBounds)?;
+		Ok(func.map(|func| func_type(func.signature())))
+	}
+}
+
+fn val_type(ty: ValueType) -> ValType {
+	match ty {
+		ValueType::I32 => ValType::I32,
+		ValueType::I64 => ValType::I64,
+		ValueType::F32 => ValType::F32,
+		ValueType::F64 => ValType::F64,
+	}
+}
+
+fn func_type(signature: &Signature) -> FuncType {
+	FuncType {
+		params: signature.params().iter().cloned().map(val_type).collect(),
+		results: signature.return_type().map(val_type).into_iter().collect(),
+	}
 }
 
 impl Into<OutterTrap> for Trap {
 	fn into(self) -> OutterTrap {
-		super::Trap {
+		let trace = self.wasm_trace().to_vec();
+		if let TrapKind::Host(e) = self.kind() {
+			if let Some(failure) = e.downcast_ref::<HostFailure>() {
+				return OutterTrap::host(failure.clone(), trace);
+			}
+		}
+		OutterTrap {
 			code: match self.kind() {
 				TrapKind::StackOverflow => TrapCode::StackOverflow,
 				TrapKind::DivisionByZero => TrapCode::IntegerDivisionByZero,
-				TrapKind::ElemUninitialized => TrapCode::BadSignature,
+				TrapKind::ElemUninitialized => TrapCode::IndirectCallToNull,
 				TrapKind::InvalidConversionToInt => TrapCode::BadConversionToInteger,
 				TrapKind::MemoryAccessOutOfBounds => TrapCode::MemoryOutOfBounds,
 				TrapKind::TableAccessOutOfBounds => TrapCode::TableOutOfBounds,
@@ -341,27 +811,8 @@ impl Into<OutterTrap> for Trap {
 				TrapKind::Unreachable => TrapCode::UnreachableCodeReached,
 				TrapKind::Host(_) => TrapCode::HostError,
 			},
-			trace: self.wasm_trace().to_vec(),
+			trace,
+			host_error: None,
 		}
 	}
 }
-
-impl fmt::Display for OutterTrap {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-		let trace = &self.trace;
-		if trace.len() == 0 {
-			write!(f, "[]")?;
-		} else {
-			for (index, trace) in trace.iter().enumerate() {
-				if index == trace.len() - 1 {
-					write!(f, "\n\t╰─>")?;
-				} else {
-					write!(f, "\n\t|  ")?;
-				}
-				write!(f, "{}", trace)?;
-			}
-		}
-
-		Ok(())
-	}
-}
@@ -0,0 +1,71 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Compiled module cache
+//!
+//! All stores share one engine, thus the compiled modules could be reused across the calls.
+//! The modules are cached by the hash of the code, and evicted in LRU.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lru::LruCache;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use sp_core::hashing::blake2_256;
+use wasmtime::{Engine, Module};
+
+use super::{gdb, util};
+use crate::{imp::ModuleCacheStats, Error};
+
+/// The maximum number of the cached modules.
+const MODULE_CACHE_SIZE: usize = 128;
+
+/// Whether the engine generates the native DWARF, which is set once the engine is created.
+static DEBUG_INFO: OnceCell<bool> = OnceCell::new();
+
+static ENGINE: Lazy<Engine> = Lazy::new(|| {
+	let _ = DEBUG_INFO.set(gdb::serving());
+	Engine::new(&util::config()).expect("init wasmtime engine fail")
+});
+
+static MODULES: Lazy<Mutex<LruCache<[u8; 32], Module>>> =
+	Lazy::new(|| Mutex::new(LruCache::new(MODULE_CACHE_SIZE)));
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// The engine shared by all stores.
+pub fn engine() -> &'static Engine {
+	&ENGINE
+}
+
+/// Whether the engine generates the native DWARF, `None` if the engine is not created yet.
+pub fn debug_info() -> Option<bool> {
+	DEBUG_INFO.get().copied()
+}
+
+/// Get the compiled module of `code`, compile and cache it if missing.
+pub fn module(code: &[u8]) -> Result<Module, Error> {
+	let hash = blake2_256(code);
+	if let Some(module) = MODULES.lock().get(&hash) {
+		HITS.fetch_add(1, Ordering::Relaxed);
+		return Ok(module.clone());
+	}
+
+	MISSES.fetch_add(1, Ordering::Relaxed);
+	// NOTE: compile without the lock, the other threads may compile the same code meanwhile.
+	let module = Module::from_binary(engine(), code).map_err(|_| Error::Module)?;
+	MODULES.lock().put(hash, module.clone());
+	Ok(module)
+}
+
+/// The statistics of the module cache.
+pub fn stats() -> ModuleCacheStats {
+	let modules = MODULES.lock();
+	ModuleCacheStats {
+		hits: HITS.load(Ordering::Relaxed),
+		misses: MISSES.load(Ordering::Relaxed),
+		cached: modules.len(),
+		capacity: modules.cap(),
+	}
+}
@@ -4,7 +4,7 @@
 
 //! Wasmtime Enviroment
 use super::{util, Memory};
-use crate::{Error, HostFuncType};
+use crate::{imp::recorder, Error, HostFuncType};
 use sp_std::collections::btree_map::BTreeMap;
 use wasmtime::{Extern, ExternType, ImportType, Store};
 
@@ -80,8 +80,9 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 				External::Func(func) => match ty.ty() {
 					ExternType::Func(sig) => {
 						let fn_ptr = *func as usize;
+						let name = recorder::host_name(&key.0, &key.1);
 						imports.push(Extern::Func(util::wrap_fn::<T>(
-							store, state_ptr, fn_ptr, sig,
+							store, state_ptr, name, fn_ptr, sig,
 						)));
 					}
 					_ => continue,
@@ -0,0 +1,215 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! GDB remote debugging of the compiled contracts
+//!
+//! The compiled code of the contracts is registered with the GDB JIT interface together with the
+//! DWARF of the contracts (`debug_info` in the engine config), thus a native debugger attached to
+//! the process could set the breakpoints in the contract source. The native DWARF is only
+//! generated if the port is set before the shared engine is created, and the modules are not
+//! instrumented for the coverage and the stack height limit meanwhile, since the native DWARF is
+//! translated from the DWARF of the original code, which could not be mapped through the
+//! instrumentation.
+//!
+//! A process could not trace itself, thus the GDB remote serial protocol server is a `gdbserver`
+//! (or `lldb-server`) which is attached to this process. It is started at the next invocation,
+//! either a dry-run or a call in an extrinsic, if no debugger is attached, and the invocation is
+//! paused until the server attaches, at most [`ATTACH_TIMEOUT`]. The server exits once the
+//! debugger disconnects, then the next invocation starts another one.
+//!
+//! The server traces the whole process by `ptrace`: it stops every thread of the node after it
+//! attaches, until the debugger connects and continues them, and again whenever the debugger
+//! stops at a breakpoint. Thus the block production, the import and the rpc are paused meanwhile,
+//! and the block production waits for the server at the contract calls of the extrinsics.
+//!
+//! Only the spawned server is allowed to trace this process under the Yama `ptrace_scope`, since
+//! it is not an ancestor of this process.
+use std::{
+	process::{Child, Command},
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::cache;
+
+/// How often to check whether the server attaches.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long an invocation waits for the server to attach.
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Server {
+	port: u16,
+	/// The path of the `gdbserver` or `lldb-server`.
+	program: String,
+	/// The running server, `None` if it is not started or exits.
+	child: Option<Child>,
+}
+
+static SERVER: Lazy<Mutex<Option<Server>>> = Lazy::new(|| Mutex::new(None));
+
+/// Find the GDB remote serial protocol server in `PATH`.
+fn find_program() -> Option<String> {
+	let paths = std::env::var_os("PATH")?;
+	["gdbserver", "lldb-server"].iter().find_map(|name| {
+		std::env::split_paths(&paths)
+			.map(|dir| dir.join(name))
+			.find(|path| path.is_file())
+			.map(|path| path.display().to_string())
+	})
+}
+
+/// Serve the debugger at `127.0.0.1:port` from the next invocation, or stop serving if `port` is
+/// `None`.
+pub fn set_port(port: Option<u16>) -> Result<(), String> {
+	let mut server = SERVER.lock();
+	let port = match port {
+		Some(port) => port,
+		None => {
+			*server = None;
+			allow_tracer(None);
+			return Ok(());
+		}
+	};
+	if !cfg!(target_os = "linux") {
+		return Err("The contract debug port is only supported on Linux".into());
+	}
+	let program = find_program().ok_or(
+		"Neither `gdbserver` nor `lldb-server` is found in PATH for the contract debug port",
+	)?;
+	// the config of the shared engine is fixed once it is created.
+	if cache::debug_info() == Some(false) {
+		return Err(
+			"The contract debug port must be set before the first contract is compiled".into(),
+		);
+	}
+	*server = Some(Server {
+		port,
+		program,
+		child: None,
+	});
+	Ok(())
+}
+
+/// Allow the server `pid`, which is a child of this process, to attach to this process under the
+/// Yama `ptrace_scope`, or disallow the former server if `pid` is `None`.
+#[cfg(target_os = "linux")]
+fn allow_tracer(pid: Option<u32>) {
+	let pid = pid.unwrap_or(0) as libc::c_ulong;
+	if unsafe { libc::prctl(libc::PR_SET_PTRACER, pid, 0, 0, 0) } != 0 {
+		log::warn!(
+			target: "sandbox",
+			"Could not allow the tracer: {}",
+			std::io::Error::last_os_error()
+		);
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allow_tracer(_pid: Option<u32>) {}
+
+/// Whether a debugger (or the server) is attached to this process.
+fn attached() -> bool {
+	std::fs::read_to_string("/proc/self/status")
+		.ok()
+		.and_then(|status| {
+			status
+				.lines()
+				.find_map(|line| line.strip_prefix("TracerPid:"))
+				.and_then(|pid| pid.trim().parse::<u32>().ok())
+		})
+		.map_or(false, |pid| pid != 0)
+}
+
+impl Server {
+	/// Spawn the server, which is allowed to trace this process right away, before it attaches
+	/// after its startup.
+	fn spawn(&self) -> std::io::Result<Child> {
+		let pid = std::process::id().to_string();
+		let address = format!("127.0.0.1:{}", self.port);
+		let mut command = Command::new(&self.program);
+		if self.program.ends_with("lldb-server") {
+			command.args(&["gdbserver", "--attach", &pid, &address]);
+		} else {
+			command.args(&["--once", "--attach", &address, &pid]);
+		}
+		let child = command.spawn()?;
+		allow_tracer(Some(child.id()));
+		Ok(child)
+	}
+
+	/// Whether the server is running, the exited server is no longer allowed to trace this
+	/// process.
+	fn running(&mut self) -> bool {
+		if let Some(child) = self.child.as_mut() {
+			if !matches!(child.try_wait(), Ok(None)) {
+				self.child = None;
+				allow_tracer(None);
+			}
+		}
+		self.child.is_some()
+	}
+}
+
+/// Whether the debug port is set.
+pub fn serving() -> bool {
+	SERVER.lock().is_some()
+}
+
+/// Start the server if it is not running, returns the port, or `None` if the debug port is not
+/// set or the server could not be started.
+fn start_server() -> Option<u16> {
+	let mut guard = SERVER.lock();
+	let server = guard.as_mut()?;
+	if !server.running() {
+		match server.spawn() {
+			Ok(child) => server.child = Some(child),
+			Err(e) => {
+				log::warn!(target: "sandbox", "Could not start {}: {}", server.program, e);
+				return None;
+			}
+		}
+	}
+	Some(server.port)
+}
+
+/// Pause the invocation until a debugger attaches, at most [`ATTACH_TIMEOUT`], if the debug port
+/// is set.
+///
+/// The lock of the server is not held while waiting, thus the other invocations are not blocked.
+pub fn wait_for_debugger() {
+	if !serving() || attached() {
+		return;
+	}
+	let port = match start_server() {
+		Some(port) => port,
+		None => return,
+	};
+	log::info!(
+		target: "sandbox",
+		"Waiting for a debugger at 127.0.0.1:{}, e.g. `lldb -o 'gdb-remote {}'` or `gdb -ex 'target remote :{}'`",
+		port,
+		port,
+		port
+	);
+
+	// the server stops the process after it attaches, until the debugger continues.
+	let deadline = Instant::now() + ATTACH_TIMEOUT;
+	while !attached() {
+		if !SERVER.lock().as_mut().map_or(false, Server::running) {
+			log::warn!(target: "sandbox", "The debug server exits before attaching");
+			return;
+		}
+		if Instant::now() >= deadline {
+			log::warn!(
+				target: "sandbox",
+				"No debugger attaches in {:?}, continue the invocation",
+				ATTACH_TIMEOUT
+			);
+			return;
+		}
+		std::thread::sleep(POLL_INTERVAL);
+	}
+}
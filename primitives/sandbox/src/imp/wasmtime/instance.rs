@@ -3,9 +3,21 @@
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
 //! Wasmtime Instance
-use super::{util, EnvironmentDefinitionBuilder};
-use crate::{Error, ReturnValue, Value};
-use wasmtime::{Extern, Global, Instance as InstanceRef, Module, Trap, Val};
+use std::{
+	borrow::Cow,
+	cell::{Cell, RefCell},
+	rc::Rc,
+};
+
+use super::{
+	cache, gdb, interrupt, memory, source_map::SourceMap, util, EnvironmentDefinitionBuilder,
+};
+use crate::{
+	coverage::{self, CodeCoverage},
+	introspection::{ExportType, ExternType, FuncType, ValType},
+	limits, snapshot, Error, ReturnValue, Value,
+};
+use wasmtime::{Extern, Func, Global, Instance as InstanceRef, Mutability, Trap, Val};
 
 fn extern_global(extern_: &Extern) -> Option<&Global> {
 	match extern_ {
@@ -16,6 +28,14 @@ fn extern_global(extern_: &Extern) -> Option<&Global> {
 
 pub struct Instance<T> {
 	instance: InstanceRef,
+	/// The hits of the blocks in the coverage mode.
+	coverage: Option<Rc<RefCell<CodeCoverage>>>,
+	/// The depth of the call stack if the stack height is limited.
+	depth: Option<Rc<Cell<u32>>>,
+	/// The fuel added into the store so far.
+	fuel: u64,
+	/// The frames of the traps are mapped to the original code if the module is instrumented.
+	source_map: Option<SourceMap>,
 	_marker: std::marker::PhantomData<T>,
 }
 
@@ -31,13 +51,148 @@ impl<T> Instance<T> {
 		} else {
 			&dummy_store
 		};
-		let module = Module::from_binary(&store.engine(), code).map_err(|_| Error::Module)?;
-		let imports =
-			env_def_builder.resolve(store, state, module.imports().collect::<Vec<_>>())?;
+		let original = code;
+		let mut code = Cow::Borrowed(code);
+		// the native DWARF, which the native debugger reads, could not be mapped through the
+		// instrumentation.
+		let instrument = !gdb::serving();
+		if !instrument && (coverage::coverage() || limits::limits().max_stack_height.is_some()) {
+			log::warn!(
+				target: "sandbox",
+				"The coverage and the stack height limit are disabled while the contract debug port is set"
+			);
+		}
+		let coverage = if instrument && coverage::coverage() {
+			match coverage::instrument(&code) {
+				Ok((instrumented, coverage)) => {
+					code = Cow::Owned(instrumented);
+					Some(Rc::new(RefCell::new(coverage)))
+				}
+				Err(e) => {
+					log::warn!(
+						target: "sandbox",
+						"Could not instrument the module for the coverage: {}",
+						e
+					);
+					None
+				}
+			}
+		} else {
+			None
+		};
+		// the frames are recorded before the calls are counted, which could trap.
+		let tracked = instrument && snapshot::tracked();
+		let tracked = match tracked.then(|| snapshot::instrument(&code, original)) {
+			Some(Ok(instrumented)) => {
+				code = Cow::Owned(instrumented);
+				true
+			}
+			Some(Err(e)) => {
+				log::warn!(
+					target: "sandbox",
+					"Could not instrument the module for the trap snapshot: {}",
+					e
+				);
+				false
+			}
+			None => false,
+		};
+		// the calls of the coverage and trap snapshot functions are imported ones, which are not
+		// counted.
+		let max_stack_height = limits::limits().max_stack_height.filter(|_| instrument);
+		let depth = match max_stack_height.map(|_| limits::instrument(&code)) {
+			Some(Ok(instrumented)) => {
+				code = Cow::Owned(instrumented);
+				Some(Rc::new(Cell::new(0)))
+			}
+			Some(Err(e)) => {
+				log::warn!(
+					target: "sandbox",
+					"Could not instrument the module for the stack height: {}",
+					e
+				);
+				None
+			}
+			None => None,
+		};
+
+		let source_map = match &code {
+			Cow::Owned(instrumented) => {
+				SourceMap::new(original, instrumented).unwrap_or_else(|e| {
+					log::warn!(
+						target: "sandbox",
+						"The traps are not mapped to the original code: {}",
+						e
+					);
+					None
+				})
+			}
+			Cow::Borrowed(_) => None,
+		};
+
+		let module = cache::module(&code)?;
+		// the coverage, trap snapshot and stack height functions are the last imports of the
+		// instrumented module, in the order of the instrumentation.
+		let mut imports = env_def_builder.resolve(
+			store,
+			state,
+			module
+				.imports()
+				.filter(|ty| {
+					ty.module() != coverage::MODULE
+						&& ty.module() != limits::MODULE
+						&& ty.module() != snapshot::MODULE
+				})
+				.collect::<Vec<_>>(),
+		)?;
+		if let Some(coverage) = coverage.clone() {
+			imports.push(Extern::Func(Func::wrap(store, move |block: i32| {
+				coverage.borrow_mut().hit(block as u32)
+			})));
+		}
+		if tracked {
+			imports.push(Extern::Func(Func::wrap(store, snapshot::enter_frame)));
+			imports.push(Extern::Func(Func::wrap(store, snapshot::exit_frame)));
+			imports.push(Extern::Func(Func::wrap(store, |function: i32| {
+				snapshot::record_frame(function as u32)
+			})));
+			imports.push(Extern::Func(Func::wrap(store, |v: i32| {
+				snapshot::record_local(Value::I32(v))
+			})));
+			imports.push(Extern::Func(Func::wrap(store, |v: i64| {
+				snapshot::record_local(Value::I64(v))
+			})));
+			imports.push(Extern::Func(Func::wrap(store, |v: f32| {
+				snapshot::record_local(Value::F32(v.to_bits()))
+			})));
+			imports.push(Extern::Func(Func::wrap(store, |v: f64| {
+				snapshot::record_local(Value::F64(v.to_bits()))
+			})));
+		}
+		if let (Some(depth), Some(max)) = (depth.clone(), max_stack_height) {
+			let enter = depth.clone();
+			imports.push(Extern::Func(Func::wrap(store, move || {
+				if enter.get() >= max {
+					return Err(Trap::new(limits::STACK_LIMIT_EXCEEDED));
+				}
+				enter.set(enter.get() + 1);
+				Ok(())
+			})));
+			imports.push(Extern::Func(Func::wrap(store, move || {
+				depth.set(depth.get().saturating_sub(1))
+			})));
+		}
+		// the start function consumes the fuel as well.
+		let mut fuel = 0;
+		interrupt::refuel(store, &mut fuel);
 		let instance = InstanceRef::new(store, &module, &imports).map_err(|_| Error::Module)?;
 
 		Ok(Instance {
 			instance,
+			coverage,
+			depth,
+			fuel,
+			source_map,
 			_marker: std::marker::PhantomData::<T>,
 		})
 	}
@@ -50,6 +205,12 @@ impl<T> Instance<T> {
 		// into closures while generating the WASM module.
 		_state: &mut T,
 	) -> Result<ReturnValue, Error> {
+		gdb::wait_for_debugger();
+		// the invoked export is the first frame.
+		if let Some(depth) = self.depth.as_ref() {
+			depth.set(1);
+		}
+
 		let args = args
 			.iter()
 			.cloned()
@@ -57,7 +218,13 @@ impl<T> Instance<T> {
 			.collect::<Vec<_>>();
 
 		let func = self.instance.get_func(name).ok_or(Error::Execution)?;
-		match func.call(&args) {
+		let store = self.instance.store();
+		interrupt::refuel(store, &mut self.fuel);
+		let result = {
+			let _watchdog = interrupt::Watchdog::start(store);
+			func.call(&args)
+		};
+		match result {
 			Ok(result) => Ok(util::to_ret_val(if result.len() != 1 {
 				return Ok(ReturnValue::Unit);
 			} else {
@@ -66,7 +233,7 @@ impl<T> Instance<T> {
 			.ok_or(Error::Execution)?),
 			Err(e) => {
 				if let Ok(trap) = e.downcast::<Trap>() {
-					Err(trap.into())
+					Err(util::trap(trap, self.source_map.as_ref()))
 				} else {
 					Err(Error::Execution)
 				}
@@ -74,6 +241,13 @@ impl<T> Instance<T> {
 		}
 	}
 
+	/// Take the coverage of the last invocation in the coverage mode.
+	pub fn take_coverage(&mut self) -> Option<CodeCoverage> {
+		self.coverage
+			.as_ref()
+			.map(|coverage| coverage.borrow_mut().take())
+	}
+
 	pub fn get_global_val(&self, name: &str) -> Option<Value> {
 		let global = match self.instance.get_export(name) {
 			Some(global) => global,
@@ -92,4 +266,81 @@ impl<T> Instance<T> {
 			_ => None,
 		}
 	}
+
+	pub fn exports(&self) -> Vec<ExportType> {
+		self.instance
+			.exports()
+			.filter_map(|export| {
+				let ty = match export.ty() {
+					wasmtime::ExternType::Func(func) => ExternType::Func(func_type(&func)),
+					wasmtime::ExternType::Global(global) => ExternType::Global {
+						ty: val_type(global.content()),
+						mutable: global.mutability() == Mutability::Var,
+					},
+					wasmtime::ExternType::Memory(memory) => ExternType::Memory {
+						initial: memory.limits().min(),
+						maximum: memory.limits().max(),
+					},
+					wasmtime::ExternType::Table(table) => ExternType::Table {
+						element: val_type(table.element()),
+						initial: table.limits().min(),
+						maximum: table.limits().max(),
+					},
+					// the module linking is not enabled.
+					_ => return None,
+				};
+				Some(ExportType {
+					name: export.name().to_string(),
+					ty,
+				})
+			})
+			.collect()
+	}
+
+	/// The current pages of the exported memory.
+	pub fn memory_size(&self, name: &str) -> Option<u32> {
+		self.instance.get_memory(name).map(|memory| memory.size())
+	}
+
+	pub fn read_memory(&self, name: &str, ptr: u32, buf: &mut [u8]) -> Result<(), Error> {
+		let memory = self.instance.get_memory(name).ok_or(Error::Execution)?;
+		// the memory does not grow while the slice is alive.
+		let data = unsafe { memory.data_unchecked() };
+		let range =
+			memory::checked_range(ptr as usize, buf.len(), data.len()).ok_or(Error::OutOfBounds)?;
+		buf.copy_from_slice(&data[range]);
+		Ok(())
+	}
+
+	/// The current elements of the exported table.
+	pub fn table_size(&self, name: &str) -> Option<u32> {
+		self.instance.get_table(name).map(|table| table.size())
+	}
+
+	pub fn table_get(&self, name: &str, index: u32) -> Result<Option<FuncType>, Error> {
+		let table = self.instance.get_table(name).ok_or(Error::Execution)?;
+		match table.get(index).ok_or(Error::OutOfBounds)? {
+			Val::FuncRef(func) => Ok(func.map(|func| func_type(&func.ty()))),
+			_ => Ok(None),
+		}
+	}
+}
+
+fn val_type(ty: &wasmtime::ValType) -> ValType {
+	match ty {
+		wasmtime::ValType::I32 => ValType::I32,
+		wasmtime::ValType::I64 => ValType::I64,
+		wasmtime::ValType::F32 => ValType::F32,
+		wasmtime::ValType::F64 => ValType::F64,
+		wasmtime::ValType::V128 => ValType::V128,
+		wasmtime::ValType::FuncRef => ValType::FuncRef,
+		wasmtime::ValType::ExternRef => ValType::ExternRef,
+	}
+}
+
+fn func_type(ty: &wasmtime::FuncType) -> FuncType {
+	FuncType {
+		params: ty.params().map(|ty| val_type(&ty)).collect(),
+		results: ty.results().map(|ty| val_type(&ty)).collect(),
+	}
 }
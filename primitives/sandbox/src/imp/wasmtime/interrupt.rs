@@ -0,0 +1,166 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Interruption of the compiled contracts
+//!
+//! An invocation is interrupted once it consumes the fuel budget, or runs longer than the
+//! wall-clock budget. The fuel is consumed by the instructions of the compiled code, and it is
+//! topped up to the budget before every invocation of the instance. The wall-clock budget is
+//! watched by a thread which interrupts the store of the instance, it covers the nested calls of
+//! the invocation as well. The wall-clock budget only applies to the dry-runs (in
+//! [`debuggable`](crate::debugger::debuggable)), thus the execution of the blocks never depends
+//! on the timing.
+//!
+//! The fuel is metered only if the budget is set before the engine is created, since the engine
+//! compiles the metering into the code. Both checks are performed at the function entries and the
+//! loop headers, and the interrupted invocation traps with `TrapCode::Interrupt`.
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, RecvTimeoutError, Sender},
+		Arc,
+	},
+	thread::JoinHandle,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use wasmtime::{Func, Store};
+
+use super::gdb;
+use crate::{debugger, imp::Interruption};
+
+/// The message of the traps raised by wasmtime when the fuel is exhausted.
+pub const OUT_OF_FUEL: &str = "all fuel consumed by WebAssembly";
+
+/// The fuel of the invocations without the fuel budget in the metered engine.
+const UNLIMITED_FUEL: u64 = i64::MAX as u64 / 2;
+
+static INTERRUPTION: Lazy<RwLock<Interruption>> =
+	Lazy::new(|| RwLock::new(Interruption::default()));
+/// Whether the engine is created with the fuel metering.
+static METERED: AtomicBool = AtomicBool::new(false);
+static ENGINE_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Set the budgets of the invocations.
+///
+/// Returns `Err` if the fuel budget is set after the engine is created without the metering.
+pub fn set(interruption: Interruption) -> Result<(), String> {
+	if interruption.fuel.is_some()
+		&& ENGINE_CREATED.load(Ordering::Relaxed)
+		&& !METERED.load(Ordering::Relaxed)
+	{
+		return Err("The fuel budget must be set before the jit executor is used".into());
+	}
+	*INTERRUPTION.write() = interruption;
+	Ok(())
+}
+
+/// The budgets of the invocations.
+pub fn interruption() -> Interruption {
+	*INTERRUPTION.read()
+}
+
+/// Whether to meter the fuel in the engine, which is called once when the engine is created.
+pub fn consume_fuel() -> bool {
+	let metered = interruption().fuel.is_some();
+	METERED.store(metered, Ordering::Relaxed);
+	ENGINE_CREATED.store(true, Ordering::Relaxed);
+	metered
+}
+
+/// Top up the fuel of `store` to the budget, `added` is the fuel added into the store so far.
+pub fn refuel(store: &Store, added: &mut u64) {
+	if !METERED.load(Ordering::Relaxed) {
+		return;
+	}
+	let budget = interruption().fuel.unwrap_or(UNLIMITED_FUEL);
+	let remaining = added.saturating_sub(store.fuel_consumed().unwrap_or_default());
+	if remaining < budget {
+		match store.add_fuel(budget - remaining) {
+			Ok(()) => *added += budget - remaining,
+			Err(e) => log::warn!(target: "sandbox", "Could not add fuel: {}", e),
+		}
+	}
+}
+
+/// The state of the watched invocation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Watched {
+	Running,
+	Interrupted,
+	Finished,
+}
+
+/// Interrupt the store if the invocation does not finish in the wall-clock budget, the watchdog
+/// is stopped when it is dropped.
+pub struct Watchdog {
+	store: Store,
+	state: Arc<Mutex<Watched>>,
+	done: Option<Sender<()>>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+	/// Watch the invocation in `store`, `None` if there is no wall-clock budget, or the invocation
+	/// is not a dry-run.
+	///
+	/// The budget is ignored while the debug port is set, since the debugger pauses the
+	/// invocation.
+	pub fn start(store: &Store) -> Option<Watchdog> {
+		let timeout = interruption().timeout?;
+		if !debugger::in_dry_run() || gdb::serving() {
+			return None;
+		}
+		let handle = match store.interrupt_handle() {
+			Ok(handle) => handle,
+			Err(e) => {
+				log::warn!(target: "sandbox", "Could not interrupt the store: {}", e);
+				return None;
+			}
+		};
+		let state = Arc::new(Mutex::new(Watched::Running));
+		let (done, rx) = mpsc::channel();
+		let thread = {
+			let state = state.clone();
+			std::thread::spawn(move || {
+				if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(timeout) {
+					let mut state = state.lock();
+					if *state == Watched::Running {
+						log::warn!(
+							target: "sandbox",
+							"Interrupt the invocation which runs longer than {:?}",
+							timeout
+						);
+						handle.interrupt();
+						*state = Watched::Interrupted;
+					}
+				}
+			})
+		};
+		Some(Watchdog {
+			store: store.clone(),
+			state,
+			done: Some(done),
+			thread: Some(thread),
+		})
+	}
+}
+
+impl Drop for Watchdog {
+	fn drop(&mut self) {
+		let interrupted =
+			std::mem::replace(&mut *self.state.lock(), Watched::Finished) == Watched::Interrupted;
+		// the thread exits once the sender is dropped.
+		self.done.take();
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+		// the interrupt which is sent after the call returns is pending in the store, which
+		// would trap the next invocation at once. It is consumed by entering the store.
+		if interrupted {
+			let _ = Func::wrap(&self.store, || {}).call(&[]);
+		}
+	}
+}
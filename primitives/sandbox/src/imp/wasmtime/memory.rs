@@ -37,6 +37,11 @@ impl Memory {
 		})
 	}
 
+	/// The current size of the memory in bytes.
+	pub fn size(&self) -> usize {
+		self.inner.data_size()
+	}
+
 	pub fn store(&self) -> &Store {
 		&self.store
 	}
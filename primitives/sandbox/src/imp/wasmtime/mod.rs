@@ -3,9 +3,13 @@
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
 //! Wasmtime executor
+pub mod cache;
 mod env;
+pub mod gdb;
 mod instance;
+pub mod interrupt;
 mod memory;
+mod source_map;
 mod util;
 
 // use self::host::DefinedHostFunctions;
@@ -0,0 +1,200 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Source mapping of the instrumented modules
+//!
+//! The coverage, the stack height limiter and the trap snapshot inject the calls of their host
+//! functions into the bodies, and the imports of them shift the defined functions, thus the
+//! frames of the compiled module no longer match the DWARF sections which are copied from the
+//! original code. The frames of the traps are mapped back to the instructions of the original
+//! code, which are looked up in its DWARF.
+use wasmparser::{ImportSectionEntryType, Operator, Parser, Payload, TypeDef};
+use wasmtime::FrameInfo;
+
+use crate::{coverage, instrument};
+
+pub struct SourceMap {
+	/// The original code, which carries the DWARF sections.
+	code: Vec<u8>,
+	/// The number of the imported functions in the original code.
+	imported: u32,
+	/// The number of the host functions imported by the instrumentation.
+	added: u32,
+	/// The offsets of the instructions in the instrumented module, and the index of the original
+	/// instruction which each one maps to, per defined function. The injected instructions map
+	/// to the next original one.
+	functions: Vec<Vec<(u32, usize)>>,
+	/// The offsets of the instructions in the code section of the original code, per defined
+	/// function.
+	offsets: Vec<Vec<u32>>,
+}
+
+/// The number of the params of the imported functions of `code`.
+fn import_params(code: &[u8]) -> Result<Vec<usize>, String> {
+	let mut types = Vec::new();
+	let mut params = Vec::new();
+	for payload in Parser::new(0).parse_all(code) {
+		match payload.map_err(|e| e.to_string())? {
+			Payload::TypeSection(reader) => {
+				for ty in reader {
+					types.push(match ty.map_err(|e| e.to_string())? {
+						TypeDef::Func(ty) => ty.params.len(),
+						_ => 0,
+					});
+				}
+			}
+			Payload::ImportSection(reader) => {
+				for import in reader {
+					if let ImportSectionEntryType::Function(ty) =
+						import.map_err(|e| e.to_string())?.ty
+					{
+						params.push(types.get(ty as usize).copied().unwrap_or_default());
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	Ok(params)
+}
+
+impl SourceMap {
+	/// Map the instructions of `instrumented` to the ones of `code`.
+	///
+	/// The instrumentations import their host functions after the imports of `code`, and push
+	/// the arguments of every injected call by the instructions right before it.
+	///
+	/// Returns `None` if `code` has no DWARF, thus there is nothing to map.
+	pub fn new(code: &[u8], instrumented: &[u8]) -> Result<Option<SourceMap>, String> {
+		if coverage::dwarf(code).is_none() {
+			return Ok(None);
+		}
+		let offsets = coverage::offsets(code)?;
+		let imported = instrument::imported_functions(code)?;
+		let params = import_params(instrumented)?;
+		let added = (params.len() as u32)
+			.checked_sub(imported)
+			.ok_or("The imported functions of the original code are removed")?;
+		// the number of the params of the injected function.
+		let injected = |function: u32| {
+			if function >= imported && function < imported + added {
+				params.get(function as usize).copied()
+			} else {
+				None
+			}
+		};
+
+		let mut functions = Vec::new();
+		for payload in Parser::new(0).parse_all(instrumented) {
+			let body = match payload.map_err(|e| e.to_string())? {
+				Payload::CodeSectionEntry(body) => body,
+				_ => continue,
+			};
+			let mut reader = body.get_operators_reader().map_err(|e| e.to_string())?;
+			let mut operators = Vec::new();
+			while !reader.eof() {
+				operators.push(reader.read_with_offset().map_err(|e| e.to_string())?);
+			}
+
+			let mut is_injected = vec![false; operators.len()];
+			for (index, (operator, _)) in operators.iter().enumerate() {
+				if let Operator::Call { function_index } = operator {
+					if let Some(params) = injected(*function_index) {
+						is_injected[index.saturating_sub(params)..=index].fill(true);
+					}
+				}
+			}
+			let mut pc = 0;
+			let mut function = Vec::with_capacity(operators.len());
+			for ((_, offset), is_injected) in operators.iter().zip(is_injected) {
+				function.push((*offset as u32, pc));
+				if !is_injected {
+					pc += 1;
+				}
+			}
+			// every instruction is either injected or the next one of the original code.
+			if offsets.get(functions.len()).map(Vec::len) != Some(pc) {
+				return Err(format!(
+					"The function {} is not instrumented from the original code",
+					imported + functions.len() as u32
+				));
+			}
+			functions.push(function);
+		}
+
+		Ok(Some(SourceMap {
+			code: code.to_vec(),
+			imported,
+			added,
+			functions,
+			offsets,
+		}))
+	}
+
+	/// The trace of the frames in the same format as [`super::util`], the frames which are not
+	/// mapped fall back to `fallback`.
+	pub fn trace(
+		&self,
+		frames: &[FrameInfo],
+		fallback: fn(&FrameInfo) -> Vec<String>,
+	) -> Vec<String> {
+		let dwarf = coverage::dwarf(&self.code);
+		frames
+			.iter()
+			.flat_map(|frame| {
+				dwarf
+					.as_ref()
+					.and_then(|dwarf| self.frame_trace(dwarf, frame))
+					.unwrap_or_else(|| fallback(frame))
+			})
+			.collect()
+	}
+
+	/// The trace of the frame of a defined function, which is looked up in `dwarf` at the
+	/// original instruction.
+	fn frame_trace(&self, dwarf: &coverage::Dwarf, frame: &FrameInfo) -> Option<Vec<String>> {
+		let index = frame.func_index().checked_sub(self.imported + self.added)?;
+		let function = self.functions.get(index as usize)?;
+		let offset = frame.module_offset() as u32;
+		let pc = function
+			.iter()
+			.take_while(|(instruction, _)| *instruction <= offset)
+			.last()?
+			.1;
+		let offsets = self.offsets.get(index as usize)?;
+		// the injected instructions at the end map to the last original one.
+		let offset = offsets.get(pc).or_else(|| offsets.last())?;
+
+		let index = self.imported + index;
+		let func = frame
+			.func_name()
+			.map(|name| name.to_string())
+			.unwrap_or_else(|| format!("<wasm function {}>", index));
+		let mut trace = Vec::new();
+		let mut frames = dwarf.find_frames(*offset as u64).ok()?;
+		while let Ok(Some(symbol)) = frames.next() {
+			let name = symbol
+				.function
+				.as_ref()
+				.and_then(|function| function.raw_name().ok())
+				.map(|name| name.to_string())
+				.unwrap_or_else(|| func.clone());
+			let mut line = format!("{}[{}]", name, index);
+			if let Some(file) = symbol.location.as_ref().and_then(|l| l.file) {
+				line.push_str(&format!(" at {}", file));
+				if let Some(l) = symbol.location.as_ref().and_then(|l| l.line) {
+					line.push_str(&format!(":{}", l));
+					if let Some(c) = symbol.location.as_ref().and_then(|l| l.column) {
+						line.push_str(&format!(":{}", c));
+					}
+				}
+			}
+			trace.push(line);
+		}
+		if trace.is_empty() {
+			trace.push(format!("{}[{}]", func, index));
+		}
+		Some(trace)
+	}
+}
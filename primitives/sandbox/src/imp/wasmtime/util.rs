@@ -3,36 +3,93 @@
 // Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
 
 //! Util
+use super::{cache, gdb, interrupt, source_map::SourceMap};
 use crate::{
-	imp::{Trap as OutterTrap, TrapCode as OutterTrapCode},
-	Error, HostFuncType, ReturnValue, Value,
+	host_error,
+	imp::{recorder, Trap as OutterTrap, TrapCode as OutterTrapCode},
+	limits, Error, HostFailure, HostFuncType, ReturnValue, Value,
 };
-use sp_std::{fmt, mem};
+use sp_std::{cell::RefCell, mem};
 use wasmtime::{
-	Caller,
-	Config,
-	Engine,
-	Func,
-	FuncType,
-	Store,
-	Trap,
-	TrapCode,
-	Val, // WasmBacktraceDetails,
+	Caller, Config, FrameInfo, Func, FuncType, Store, Trap, TrapCode, Val,
+	WasmBacktraceDetails,
 };
 
-/// Create store with DWARF enabled
+/// The config of the engine with DWARF enabled
 ///
-/// NOTE: The Debug info with native trace has some problem in
-/// aarch64-apple-darwin, not enable for default for now.
+/// The DWARF sections of the contracts built with debug info are parsed while compiling
+/// (`wasm_backtrace_details`), thus the frames of the trap could be mapped to
+/// `file:line:function`, see [`SourceMap`] for the instrumented modules.
+///
+/// The native DWARF of the compiled code (`debug_info`) is only generated for the native
+/// debugger, if the contract debug port is set before the engine is created, see [`gdb`].
+///
+/// The invocations could be interrupted, and the fuel is metered if the fuel budget is set, see
+/// [`interrupt`].
+///
+/// NOTE: The Debug info with native trace (`debug_info`) has some problem in
+/// aarch64-apple-darwin, only enable it for the other targets.
+pub fn config() -> Config {
+	let mut config = Config::new();
+	config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+	config.interruptable(true);
+	config.consume_fuel(interrupt::consume_fuel());
+	#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+	config.debug_info(gdb::serving());
+	config
+}
+
+/// Create store with DWARF enabled, from the shared engine.
 pub fn store_with_dwarf() -> Store {
-	let config = Config::new();
-	// .debug_info(true)
-	// .wasm_backtrace_details(WasmBacktraceDetails::Enable),
-	Store::new(&Engine::new(&config).expect("init wasmtime engine fail"))
+	Store::new(cache::engine())
+}
+
+/// Format the wasm frame in the same way as the forked wasmi, `function[index]`, and append
+/// the source location for each (inlined) function if the DWARF info exists.
+///
+/// The frames without DWARF info fallback to the name section, or the index of the function.
+fn frame_trace(frame: &FrameInfo) -> Vec<String> {
+	let func = frame
+		.func_name()
+		.map(|name| name.to_string())
+		.unwrap_or_else(|| format!("<wasm function {}>", frame.func_index()));
+	if frame.symbols().is_empty() {
+		return vec![format!("{}[{}]", func, frame.func_index())];
+	}
+
+	frame
+		.symbols()
+		.iter()
+		.map(|symbol| {
+			let mut line = format!(
+				"{}[{}]",
+				symbol.name().unwrap_or(&func),
+				frame.func_index()
+			);
+			if let Some(file) = symbol.file() {
+				line.push_str(&format!(" at {}", file));
+				if let Some(l) = symbol.line() {
+					line.push_str(&format!(":{}", l));
+					if let Some(c) = symbol.column() {
+						line.push_str(&format!(":{}", c));
+					}
+				}
+			}
+			line
+		})
+		.collect()
+}
+
+/// The message of the traps raised by the host functions.
+const HOST_ERROR: &str = "HostError";
+
+thread_local! {
+	/// The failure of the host function which raises the trap, wasmtime keeps the message only.
+	static HOST_FAILURE: RefCell<Option<HostFailure>> = RefCell::new(None);
 }
 
 /// Wrap host function into `Func`
-pub fn wrap_fn<T>(store: &Store, state: usize, f: usize, sig: FuncType) -> Func {
+pub fn wrap_fn<T>(store: &Store, state: usize, name: String, f: usize, sig: FuncType) -> Func {
 	let func = move |_: Caller<'_>, args: &[Val], results: &mut [Val]| {
 		let mut inner_args = vec![];
 		for arg in args {
@@ -50,14 +107,18 @@ pub fn wrap_fn<T>(store: &Store, state: usize, f: usize, sig: FuncType) -> Func
 		// Work for one call.
 		let state: &mut T = unsafe { mem::transmute(state) };
 		let func: HostFuncType<T> = unsafe { mem::transmute(f) };
-		match func(state, &inner_args) {
+		match recorder::call_host(&name, func, state, &inner_args) {
 			Ok(ret) => {
 				if let Some(ret) = from_ret_val(ret) {
 					results[0] = ret;
 				}
 				Ok(())
 			}
-			Err(e) => Err(Trap::new(format!("{:?}", e))),
+			Err(_) => {
+				let failure = host_error::failure(&name);
+				HOST_FAILURE.with(|f| *f.borrow_mut() = Some(failure));
+				Err(Trap::new(HOST_ERROR))
+			}
 		}
 	};
 	Func::new(store, sig, func)
@@ -98,42 +159,59 @@ fn from_ret_val(v: ReturnValue) -> Option<Val> {
 	}
 }
 
-impl From<Trap> for Error {
-	fn from(trap: Trap) -> Error {
-		let mut code = OutterTrapCode::Unknown;
-		if let Some(cc) = trap.trap_code() {
-			code = match cc {
-				TrapCode::BadConversionToInteger => OutterTrapCode::BadConversionToInteger,
-				TrapCode::BadSignature => OutterTrapCode::BadSignature,
-				TrapCode::HeapMisaligned => OutterTrapCode::HeapMisaligned,
-				TrapCode::IndirectCallToNull => OutterTrapCode::IndirectCallToNull,
-				TrapCode::IntegerDivisionByZero => OutterTrapCode::IntegerDivisionByZero,
-				TrapCode::IntegerOverflow => OutterTrapCode::IntegerOverflow,
-				TrapCode::Interrupt => OutterTrapCode::Interrupt,
-				TrapCode::MemoryOutOfBounds => OutterTrapCode::MemoryOutOfBounds,
-				TrapCode::StackOverflow => OutterTrapCode::StackOverflow,
-				TrapCode::TableOutOfBounds => OutterTrapCode::TableOutOfBounds,
-				TrapCode::UnreachableCodeReached => OutterTrapCode::UnreachableCodeReached,
-				_ => OutterTrapCode::Unknown,
-			}
+/// Convert the trap of an invocation, the frames of which are mapped by `source_map` if the
+/// module is instrumented.
+pub fn trap(trap: Trap, source_map: Option<&SourceMap>) -> Error {
+	let reason = format!("{}", trap).lines().next().map(ToString::to_string);
+	let failure = match reason.as_deref() {
+		Some(HOST_ERROR) => HOST_FAILURE.with(|f| f.borrow_mut().take()),
+		_ => None,
+	};
+	let mut code = match reason.as_deref() {
+		Some(HOST_ERROR) => OutterTrapCode::HostError,
+		Some(limits::STACK_LIMIT_EXCEEDED) => OutterTrapCode::StackLimitExceeded,
+		Some(interrupt::OUT_OF_FUEL) => OutterTrapCode::Interrupt,
+		_ => OutterTrapCode::Unknown,
+	};
+	if let Some(cc) = trap.trap_code() {
+		code = match cc {
+			TrapCode::BadConversionToInteger => OutterTrapCode::BadConversionToInteger,
+			TrapCode::BadSignature => OutterTrapCode::BadSignature,
+			TrapCode::HeapMisaligned => OutterTrapCode::HeapMisaligned,
+			TrapCode::IndirectCallToNull => OutterTrapCode::IndirectCallToNull,
+			TrapCode::IntegerDivisionByZero => OutterTrapCode::IntegerDivisionByZero,
+			TrapCode::IntegerOverflow => OutterTrapCode::IntegerOverflow,
+			TrapCode::Interrupt => OutterTrapCode::Interrupt,
+			TrapCode::MemoryOutOfBounds => OutterTrapCode::MemoryOutOfBounds,
+			TrapCode::StackOverflow => OutterTrapCode::StackOverflow,
+			TrapCode::TableOutOfBounds => OutterTrapCode::TableOutOfBounds,
+			TrapCode::UnreachableCodeReached => OutterTrapCode::UnreachableCodeReached,
+			_ => OutterTrapCode::Unknown,
 		}
-
-		Error::Trap(OutterTrap {
-			code,
-			trace: format!("{}", trap)
-				.split("\n")
-				.map(|s| s.to_string())
-				.collect::<Vec<_>>(),
-		})
 	}
-}
 
-impl fmt::Display for OutterTrap {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-		self.trace
+	// The innermost frame is the first one, the same as wasmi.
+	let mut trace = match source_map {
+		Some(source_map) => source_map.trace(trap.trace(), frame_trace),
+		None => trap
+			.trace()
 			.iter()
-			.map(|s| write!(f, "{}", s))
-			.collect::<Result<Vec<_>, fmt::Error>>()?;
-		Ok(())
+			.flat_map(frame_trace)
+			.collect::<Vec<_>>(),
+	};
+	if let Some(failure) = failure {
+		return Error::Trap(OutterTrap::host(failure, trace));
 	}
+	// The traps raised by the host functions carry the reason only in the message.
+	if trap.trap_code().is_none() || trace.is_empty() {
+		if let Some(reason) = reason {
+			trace.insert(0, reason);
+		}
+	}
+
+	Error::Trap(OutterTrap {
+		code,
+		trace,
+		host_error: None,
+	})
 }
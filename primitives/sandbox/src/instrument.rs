@@ -0,0 +1,524 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Instrumentation of the modules
+//!
+//! The backends do not expose the execution of single instructions, thus the debugger, the
+//! profiler and the coverage rewrite the bodies of the defined functions to call the host
+//! functions imported from their own module. The host functions are appended to the imports, thus the indices of
+//! the defined functions are shifted in the calls, exports, elements, start and names.
+use std::collections::BTreeMap;
+
+use parity_wasm::elements::{
+	BlockType, External, FunctionType, ImportCountType, ImportEntry, Instruction, Internal, Local,
+	Module, Type, ValueType,
+};
+use wasmparser::{ImportSectionEntryType, Parser, Payload};
+
+/// A host function imported by the instrumented module.
+pub(crate) struct HostFunc {
+	pub name: &'static str,
+	pub params: Vec<ValueType>,
+	pub results: Vec<ValueType>,
+}
+
+/// The original code of the instrumented module, to describe the locations.
+pub(crate) struct Program {
+	/// The number of the imported functions in the original module.
+	pub imported: u32,
+	names: BTreeMap<u32, String>,
+	/// The instructions of the defined functions.
+	bodies: Vec<Vec<Instruction>>,
+}
+
+impl Program {
+	pub fn name(&self, function: u32) -> Option<&String> {
+		self.names.get(&function)
+	}
+
+	/// The name of `function` in the name section, or `func[index]` if it is not named.
+	pub fn symbol(&self, function: u32) -> String {
+		self.name(function)
+			.cloned()
+			.unwrap_or_else(|| format!("func[{}]", function))
+	}
+
+	/// The indices of the defined functions.
+	pub fn functions(&self) -> std::ops::Range<u32> {
+		self.imported..self.imported + self.bodies.len() as u32
+	}
+
+	pub fn instruction(&self, function: u32, pc: u32) -> String {
+		function
+			.checked_sub(self.imported)
+			.and_then(|index| self.bodies.get(index as usize)?.get(pc as usize))
+			.map(|instruction| instruction.to_string())
+			.unwrap_or_default()
+	}
+}
+
+/// The number of the imported functions of `code`.
+pub(crate) fn imported_functions(code: &[u8]) -> Result<u32, String> {
+	for payload in Parser::new(0).parse_all(code) {
+		if let Payload::ImportSection(reader) = payload.map_err(|e| e.to_string())? {
+			let mut imported = 0;
+			for import in reader {
+				if let ImportSectionEntryType::Function(_) = import.map_err(|e| e.to_string())?.ty {
+					imported += 1;
+				}
+			}
+			return Ok(imported);
+		}
+	}
+	Ok(0)
+}
+
+/// Whether the instruction ends a straight-line run of instructions.
+pub(crate) fn is_control(instruction: &Instruction) -> bool {
+	matches!(
+		instruction,
+		Instruction::Block(_)
+			| Instruction::Loop(_)
+			| Instruction::If(_)
+			| Instruction::Else
+			| Instruction::End
+			| Instruction::Br(_)
+			| Instruction::BrIf(_)
+			| Instruction::BrTable(_)
+			| Instruction::Return
+			| Instruction::Call(_)
+			| Instruction::CallIndirect(_, _)
+			| Instruction::Unreachable
+	)
+}
+
+/// The defined function which is rewritten.
+pub(crate) struct Function<'a> {
+	/// The index of the function in the original module.
+	pub index: u32,
+	/// The types of the params and the locals.
+	pub locals: &'a [ValueType],
+	/// The original instructions, the calls of which are already shifted.
+	pub code: &'a [Instruction],
+	/// The types of the operands which are pushed in the innermost block before every
+	/// instruction, the bottom first. `None` if the types are not known, e.g. in the unreachable
+	/// code.
+	pub stacks: &'a [Option<Vec<ValueType>>],
+	/// The index of the first host function in the instrumented module.
+	pub host_base: u32,
+}
+
+/// The rewritten body of a defined function.
+pub(crate) struct Rewritten {
+	pub code: Vec<Instruction>,
+	/// The locals appended to the locals of the function, the first one is indexed by the length
+	/// of [`Function::locals`].
+	pub locals: Vec<ValueType>,
+}
+
+impl From<Vec<Instruction>> for Rewritten {
+	fn from(code: Vec<Instruction>) -> Rewritten {
+		Rewritten {
+			code,
+			locals: Vec::new(),
+		}
+	}
+}
+
+/// Import `host_funcs` from `module_name` into `code`, and rewrite the body of every defined
+/// function by `rewrite`.
+///
+/// Returns the instrumented code and the original program.
+pub(crate) fn instrument<R: Into<Rewritten>>(
+	code: &[u8],
+	module_name: &str,
+	host_funcs: &[HostFunc],
+	mut rewrite: impl FnMut(&Function) -> R,
+) -> Result<(Vec<u8>, Program), String> {
+	let module = parity_wasm::deserialize_buffer::<Module>(code).map_err(|e| e.to_string())?;
+	let mut module = module.parse_names().unwrap_or_else(|(_, module)| module);
+
+	let imported = module.import_count(ImportCountType::Function) as u32;
+	let added = host_funcs.len() as u32;
+	let shift = |index: u32| {
+		if index >= imported {
+			index + added
+		} else {
+			index
+		}
+	};
+
+	let names = module
+		.names_section()
+		.and_then(|section| section.functions())
+		.map(|functions| {
+			functions
+				.names()
+				.iter()
+				.map(|(index, name)| (index, name.clone()))
+				.collect()
+		})
+		.unwrap_or_default();
+
+	// the types of the host functions.
+	let types = module
+		.type_section_mut()
+		.ok_or("The module has no type section")?
+		.types_mut();
+	let type_base = types.len() as u32;
+	for func in host_funcs {
+		types.push(Type::Function(FunctionType::new(
+			func.params.clone(),
+			func.results.clone(),
+		)));
+	}
+	let func_types = module
+		.type_section()
+		.map(|section| section.types().to_vec())
+		.unwrap_or_default();
+	let context = Context::new(&module, &func_types[..type_base as usize]);
+	let params = module
+		.function_section()
+		.map(|section| {
+			section
+				.entries()
+				.iter()
+				.map(|func| match func_types.get(func.type_ref() as usize) {
+					Some(Type::Function(ty)) => ty.params().to_vec(),
+					None => Vec::new(),
+				})
+				.collect::<Vec<_>>()
+		})
+		.unwrap_or_default();
+
+	let imports = module
+		.import_section_mut()
+		.ok_or("The module has no import section")?
+		.entries_mut();
+	for (index, func) in host_funcs.iter().enumerate() {
+		imports.push(ImportEntry::new(
+			module_name.into(),
+			func.name.to_string(),
+			External::Function(type_base + index as u32),
+		));
+	}
+
+	let mut bodies = Vec::new();
+	if let Some(section) = module.code_section_mut() {
+		for (index, body) in section.bodies_mut().iter_mut().enumerate() {
+			let mut locals = params.get(index).cloned().unwrap_or_default();
+			for l in body.locals() {
+				locals.extend((0..l.count()).map(|_| l.value_type()));
+			}
+
+			let original = body.code().elements().to_vec();
+			let shifted = original
+				.iter()
+				.map(|instruction| match instruction {
+					Instruction::Call(index) => Instruction::Call(shift(*index)),
+					instruction => instruction.clone(),
+				})
+				.collect::<Vec<_>>();
+			let results = context
+				.function_type(imported + index as u32)
+				.map(|ty| ty.results().to_vec())
+				.unwrap_or_default();
+			let stacks = context.operand_stacks(&original, &locals, results);
+			let rewritten = rewrite(&Function {
+				index: imported + index as u32,
+				locals: &locals,
+				code: &shifted,
+				stacks: &stacks,
+				host_base: imported,
+			})
+			.into();
+			*body.code_mut().elements_mut() = rewritten.code;
+			body.locals_mut()
+				.extend(rewritten.locals.into_iter().map(|ty| Local::new(1, ty)));
+			bodies.push(original);
+		}
+	}
+
+	// shift the references to the defined functions.
+	if let Some(section) = module.export_section_mut() {
+		for entry in section.entries_mut() {
+			if let Internal::Function(index) = entry.internal_mut() {
+				*index = shift(*index);
+			}
+		}
+	}
+	if let Some(section) = module.elements_section_mut() {
+		for segment in section.entries_mut() {
+			for member in segment.members_mut() {
+				*member = shift(*member);
+			}
+		}
+	}
+	if let Some(start) = module.start_section() {
+		module.set_start_section(shift(start));
+	}
+	if let Some(section) = module.names_section_mut() {
+		if let Some(functions) = section.functions_mut() {
+			let shifted = functions
+				.names()
+				.iter()
+				.map(|(index, name)| (shift(index), name.clone()))
+				.collect::<Vec<_>>();
+			let names = functions.names_mut();
+			*names = Default::default();
+			for (index, name) in shifted {
+				names.insert(index, name);
+			}
+		}
+		if let Some(locals) = section.locals_mut() {
+			let shifted = locals
+				.local_names()
+				.iter()
+				.map(|(index, names)| (shift(index), names.clone()))
+				.collect::<Vec<_>>();
+			let names = locals.local_names_mut();
+			*names = Default::default();
+			for (index, local_names) in shifted {
+				names.insert(index, local_names);
+			}
+		}
+	}
+
+	let code = parity_wasm::serialize(module).map_err(|e| e.to_string())?;
+	Ok((
+		code,
+		Program {
+			imported,
+			names,
+			bodies,
+		},
+	))
+}
+
+/// The types of the functions and the globals in the original module, to track the types of the
+/// operands.
+struct Context<'a> {
+	types: &'a [Type],
+	/// The type indices of the functions in the function index space.
+	functions: Vec<u32>,
+	/// The types of the globals in the global index space.
+	globals: Vec<ValueType>,
+}
+
+/// A block in the tracking of the operands.
+struct Frame {
+	/// The height of the operand stack when the block is entered.
+	height: usize,
+	results: Vec<ValueType>,
+	/// The code after an unconditional branch is unreachable until the end of the block.
+	unreachable: bool,
+}
+
+impl<'a> Context<'a> {
+	fn new(module: &Module, types: &'a [Type]) -> Self {
+		let mut functions = Vec::new();
+		let mut globals = Vec::new();
+		for import in module
+			.import_section()
+			.map(|s| s.entries())
+			.unwrap_or_default()
+		{
+			match import.external() {
+				External::Function(ty) => functions.push(*ty),
+				External::Global(ty) => globals.push(ty.content_type()),
+				_ => {}
+			}
+		}
+		functions.extend(
+			module
+				.function_section()
+				.map(|s| s.entries())
+				.unwrap_or_default()
+				.iter()
+				.map(|func| func.type_ref()),
+		);
+		globals.extend(
+			module
+				.global_section()
+				.map(|s| s.entries())
+				.unwrap_or_default()
+				.iter()
+				.map(|global| global.global_type().content_type()),
+		);
+		Context {
+			types,
+			functions,
+			globals,
+		}
+	}
+
+	fn signature(&self, ty: u32) -> Option<&FunctionType> {
+		match self.types.get(ty as usize)? {
+			Type::Function(ty) => Some(ty),
+		}
+	}
+
+	fn function_type(&self, function: u32) -> Option<&FunctionType> {
+		self.signature(*self.functions.get(function as usize)?)
+	}
+
+	/// Track the types of the operands in the innermost block before every instruction of `code`.
+	///
+	/// The module is valid, thus the types are popped without checking. The instructions which
+	/// are not tracked (e.g. the post-MVP ones) make the types unknown until the end of the block.
+	fn operand_stacks(
+		&self,
+		code: &[Instruction],
+		locals: &[ValueType],
+		results: Vec<ValueType>,
+	) -> Vec<Option<Vec<ValueType>>> {
+		use Instruction::*;
+		use ValueType::{F32, F64, I32, I64};
+
+		let mut stack: Vec<ValueType> = Vec::new();
+		let mut frames = vec![Frame {
+			height: 0,
+			results,
+			unreachable: false,
+		}];
+		let mut stacks = Vec::with_capacity(code.len());
+		for instruction in code {
+			let frame = match frames.last_mut() {
+				Some(frame) => frame,
+				None => {
+					stacks.push(None);
+					continue;
+				}
+			};
+			stacks.push(if frame.unreachable {
+				None
+			} else {
+				Some(stack[frame.height..].to_vec())
+			});
+			let height = frame.height;
+			let pop = |stack: &mut Vec<ValueType>, n: usize| {
+				stack.truncate(stack.len().saturating_sub(n).max(height))
+			};
+			// (the popped operands, the pushed operand)
+			let (pops, push): (usize, Option<ValueType>) = match instruction {
+				Nop => (0, None),
+				Unreachable | Br(_) | BrTable(_) | Return => {
+					frame.unreachable = true;
+					stack.truncate(height);
+					continue;
+				}
+				Block(ty) | Loop(ty) | If(ty) => {
+					if let If(_) = instruction {
+						pop(&mut stack, 1);
+					}
+					let results = match ty {
+						BlockType::NoResult => Vec::new(),
+						BlockType::Value(ty) => vec![*ty],
+					};
+					let unreachable = frame.unreachable;
+					frames.push(Frame {
+						height: stack.len(),
+						results,
+						unreachable,
+					});
+					continue;
+				}
+				Else => {
+					stack.truncate(height);
+					frame.unreachable = false;
+					continue;
+				}
+				End => {
+					stack.truncate(height);
+					if let Some(frame) = frames.pop() {
+						stack.extend(frame.results);
+					}
+					continue;
+				}
+				BrIf(_) => (1, None),
+				Call(function) => match self.function_type(*function) {
+					Some(ty) => (ty.params().len(), ty.results().first().cloned()),
+					None => {
+						frame.unreachable = true;
+						continue;
+					}
+				},
+				CallIndirect(ty, _) => match self.signature(*ty) {
+					Some(ty) => (ty.params().len() + 1, ty.results().first().cloned()),
+					None => {
+						frame.unreachable = true;
+						continue;
+					}
+				},
+				Drop => (1, None),
+				Select => {
+					pop(&mut stack, 1);
+					let ty = stack.last().cloned();
+					(2, ty)
+				}
+				GetLocal(index) => (0, locals.get(*index as usize).cloned()),
+				SetLocal(_) => (1, None),
+				TeeLocal(index) => (1, locals.get(*index as usize).cloned()),
+				GetGlobal(index) => (0, self.globals.get(*index as usize).cloned()),
+				SetGlobal(_) => (1, None),
+
+				I32Load(..) | I32Load8S(..) | I32Load8U(..) | I32Load16S(..) | I32Load16U(..) => {
+					(1, Some(I32))
+				}
+				I64Load(..) | I64Load8S(..) | I64Load8U(..) | I64Load16S(..) | I64Load16U(..)
+				| I64Load32S(..) | I64Load32U(..) => (1, Some(I64)),
+				F32Load(..) => (1, Some(F32)),
+				F64Load(..) => (1, Some(F64)),
+				I32Store(..) | I64Store(..) | F32Store(..) | F64Store(..) | I32Store8(..)
+				| I32Store16(..) | I64Store8(..) | I64Store16(..) | I64Store32(..) => (2, None),
+				CurrentMemory(_) => (0, Some(I32)),
+				GrowMemory(_) => (1, Some(I32)),
+
+				I32Const(_) => (0, Some(I32)),
+				I64Const(_) => (0, Some(I64)),
+				F32Const(_) => (0, Some(F32)),
+				F64Const(_) => (0, Some(F64)),
+
+				I32Eqz | I64Eqz => (1, Some(I32)),
+				I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU | I32GeS
+				| I32GeU | I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU
+				| I64GeS | I64GeU | F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq
+				| F64Ne | F64Lt | F64Gt | F64Le | F64Ge => (2, Some(I32)),
+
+				I32Clz | I32Ctz | I32Popcnt => (1, Some(I32)),
+				I32Add | I32Sub | I32Mul | I32DivS | I32DivU | I32RemS | I32RemU | I32And
+				| I32Or | I32Xor | I32Shl | I32ShrS | I32ShrU | I32Rotl | I32Rotr => (2, Some(I32)),
+				I64Clz | I64Ctz | I64Popcnt => (1, Some(I64)),
+				I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS | I64RemU | I64And
+				| I64Or | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl | I64Rotr => (2, Some(I64)),
+				F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt => {
+					(1, Some(F32))
+				}
+				F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => (2, Some(F32)),
+				F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Sqrt => {
+					(1, Some(F64))
+				}
+				F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => (2, Some(F64)),
+
+				I32WrapI64 | I32TruncSF32 | I32TruncUF32 | I32TruncSF64 | I32TruncUF64
+				| I32ReinterpretF32 => (1, Some(I32)),
+				I64ExtendSI32 | I64ExtendUI32 | I64TruncSF32 | I64TruncUF32 | I64TruncSF64
+				| I64TruncUF64 | I64ReinterpretF64 => (1, Some(I64)),
+				F32ConvertSI32 | F32ConvertUI32 | F32ConvertSI64 | F32ConvertUI64
+				| F32DemoteF64 | F32ReinterpretI32 => (1, Some(F32)),
+				F64ConvertSI32 | F64ConvertUI32 | F64ConvertSI64 | F64ConvertUI64
+				| F64PromoteF32 | F64ReinterpretI64 => (1, Some(F64)),
+
+				#[allow(unreachable_patterns)]
+				_ => {
+					frame.unreachable = true;
+					continue;
+				}
+			};
+			pop(&mut stack, pops);
+			stack.extend(push);
+		}
+		stacks
+	}
+}
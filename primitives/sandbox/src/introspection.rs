@@ -0,0 +1,106 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Introspection of the instances
+//!
+//! The exports of an instance are listed with their types by `Instance::exports`, including the
+//! tables and the globals of the reference types which are not a [`crate::Value`]. The exported
+//! memories and tables are read by `Instance::read_memory` and `Instance::table_get`, which
+//! work the same on both backends, thus the tooling such as the trap snapshots and the debuggers
+//! does not have to parse the module again.
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use wasmparser::{Parser, Payload};
+
+/// The type of a wasm value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValType {
+	/// A 32-bit integer.
+	I32,
+	/// A 64-bit integer.
+	I64,
+	/// A 32-bit floating-point number.
+	F32,
+	/// A 64-bit floating-point number.
+	F64,
+	/// A 128-bit vector of the SIMD proposal.
+	V128,
+	/// A reference to a function.
+	FuncRef,
+	/// A reference to a host object.
+	ExternRef,
+}
+
+/// The signature of a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct FuncType {
+	/// The types of the parameters.
+	pub params: Vec<ValType>,
+	/// The types of the results.
+	pub results: Vec<ValType>,
+}
+
+/// The type of an export, the sizes are in pages for the memories and in elements for the tables.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExternType {
+	/// A function with its signature.
+	Func(FuncType),
+	/// A global with the type of its value.
+	Global {
+		/// The type of the value.
+		ty: ValType,
+		/// Whether the global could be set.
+		mutable: bool,
+	},
+	/// A linear memory with its limits.
+	Memory {
+		/// The minimum pages.
+		initial: u32,
+		/// The maximum pages, `None` if unlimited.
+		maximum: Option<u32>,
+	},
+	/// A table with the type and the limits of its elements.
+	Table {
+		/// The type of the elements.
+		element: ValType,
+		/// The minimum elements.
+		initial: u32,
+		/// The maximum elements, `None` if unlimited.
+		maximum: Option<u32>,
+	},
+}
+
+/// An export of an instance.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ExportType {
+	/// The name of the export.
+	pub name: String,
+	/// The type of the export.
+	pub ty: ExternType,
+}
+
+/// The names of the exports in `code`, in the order of the export section.
+///
+/// Returns the names before the error if the code could not be parsed.
+pub(crate) fn export_names(code: &[u8]) -> Vec<String> {
+	let mut names = Vec::new();
+	for payload in Parser::new(0).parse_all(code) {
+		match payload {
+			Ok(Payload::ExportSection(reader)) => {
+				for export in reader {
+					match export {
+						Ok(export) => names.push(export.field.to_string()),
+						Err(_) => break,
+					}
+				}
+				break;
+			}
+			Ok(_) => {}
+			Err(_) => break,
+		}
+	}
+	names
+}
@@ -43,7 +43,35 @@ use sp_std::prelude::*;
 pub use sp_core::sandbox::HostError;
 pub use sp_wasm_interface::{ReturnValue, Value};
 
+pub mod coverage;
+mod debug_message;
+pub mod debugger;
+pub mod differential;
+pub mod host_error;
 mod imp;
+mod instrument;
+pub mod introspection;
+pub mod limits;
+pub mod profiler;
+pub mod replay;
+pub mod snapshot;
+pub mod validation;
+
+pub use coverage::{coverage, set_coverage, set_pristine_code, CodeCoverage};
+pub use differential::{differential, set_differential};
+pub use host_error::{host_error, host_error_with_payload, HostFailure};
+pub use introspection::{ExportType, ExternType, FuncType, ValType};
+pub use limits::{limits, set_limits, SandboxLimits};
+pub use profiler::{profiling, set_profiling, Profile};
+pub use replay::{recording, set_recording, Recording, Replayed};
+pub use snapshot::{
+	current_frame, set_trap_snapshot, trap_snapshot, FrameSnapshot, MemorySnapshot, TrapSnapshot,
+};
+pub use validation::{validate, ValidationReport};
+pub use imp::{
+	executor, module_cache_stats, set_debug_port, set_executor, set_interruption, Interruption,
+	ModuleCacheStats, SandboxExecutor, Trap, TrapCode,
+};
 
 /// add serde function for sp_wasm_interface::ReturnValue & Value;
 /// notice it's a hack operation, if ReturnValue, Value are changed, this part should also need change.
@@ -51,7 +79,7 @@ pub mod serde_opt_wasm_returnvalue {
 	use super::*;
 	use serde::{de, ser, Deserialize, Serialize};
 
-	#[derive(Clone, Copy, Serialize, Deserialize)]
+	#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 	/// A hack struct for `Value`
 	pub enum SerdeValue {
 		/// A 32-bit integer.
@@ -75,6 +103,11 @@ pub mod serde_opt_wasm_returnvalue {
 		/// For returning some concrete value.
 		Value(SerdeValue),
 	}
+	impl From<Value> for SerdeValue {
+		fn from(v: Value) -> Self {
+			unsafe { std::mem::transmute::<Value, SerdeValue>(v) }
+		}
+	}
 	impl From<ReturnValue> for SerdeReturnValue {
 		fn from(v: ReturnValue) -> Self {
 			unsafe { std::mem::transmute::<ReturnValue, SerdeReturnValue>(v) }
@@ -112,7 +145,12 @@ pub mod serde_opt_wasm_returnvalue {
 
 /// Error that can occur while using this crate.
 #[derive(
-	sp_core::RuntimeDebug, codec::Encode, codec::Decode, serde::Serialize, serde::Deserialize,
+	Clone,
+	sp_core::RuntimeDebug,
+	codec::Encode,
+	codec::Decode,
+	serde::Serialize,
+	serde::Deserialize,
 )]
 pub enum Error {
 	/// Module is not valid, couldn't be instantiated.
@@ -130,12 +168,6 @@ pub enum Error {
 	Trap(imp::Trap),
 }
 
-impl From<Error> for HostError {
-	fn from(_e: Error) -> HostError {
-		HostError
-	}
-}
-
 /// Function pointer for specifying functions by the
 /// supervisor in [`EnvironmentDefinitionBuilder`].
 ///
@@ -150,8 +182,15 @@ pub type HostFuncType<T> = fn(&mut T, &[Value]) -> Result<ReturnValue, HostError
 #[derive(Clone)]
 pub struct Memory {
 	inner: imp::Memory,
+	/// The memory of the secondary backend in the differential mode.
+	shadow: Option<imp::Memory>,
+	/// The unique id of the memory, which is shared by the clones.
+	id: u64,
+	maximum: Option<u32>,
 }
 
+static MEMORY_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
 impl Memory {
 	/// Construct a new linear memory instance.
 	///
@@ -163,12 +202,43 @@ impl Memory {
 	/// `maximum`. If not specified, this memory instance would be able to allocate up to 4GiB.
 	///
 	/// Allocated memory is always zeroed.
+	///
+	/// The memory is created by the backend selected by [`set_executor`].
 	pub fn new(initial: u32, maximum: Option<u32>) -> Result<Memory, Error> {
+		Self::new_with_executor(imp::executor(), initial, maximum)
+	}
+
+	/// Construct a new linear memory instance by the given backend.
+	///
+	/// Returns `Err(Error::Module)` if the backend is not compiled in.
+	pub fn new_with_executor(
+		executor: SandboxExecutor,
+		initial: u32,
+		maximum: Option<u32>,
+	) -> Result<Memory, Error> {
+		let shadow = if differential::differential() {
+			imp::Memory::new(differential::secondary(executor), initial, maximum).ok()
+		} else {
+			None
+		};
 		Ok(Memory {
-			inner: imp::Memory::new(initial, maximum)?,
+			inner: imp::Memory::new(executor, initial, maximum)?,
+			shadow,
+			id: MEMORY_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+			maximum,
 		})
 	}
 
+	/// The backend which creates this memory.
+	pub fn executor(&self) -> SandboxExecutor {
+		self.inner.executor()
+	}
+
+	/// The current size of the memory in bytes.
+	pub fn size(&self) -> usize {
+		self.inner.size()
+	}
+
 	/// Read a memory area at the address `ptr` with the size of the provided slice `buf`.
 	///
 	/// Returns `Err` if the range is out-of-bounds.
@@ -180,7 +250,15 @@ impl Memory {
 	///
 	/// Returns `Err` if the range is out-of-bounds.
 	pub fn set(&self, ptr: u32, value: &[u8]) -> Result<(), Error> {
-		self.inner.set(ptr, value)
+		self.inner.set(ptr, value)?;
+		if let Some(shadow) = &self.shadow {
+			// the writes in the host calls are replayed to the secondary backend later.
+			if let Some(shadow) = imp::recorder::record_write(shadow.clone(), ptr, value) {
+				shadow.set(ptr, value)?;
+			}
+		}
+		replay::record_write(self, ptr, value);
+		Ok(())
 	}
 }
 
@@ -190,6 +268,14 @@ impl Memory {
 /// to the module at the instantiation time.
 pub struct EnvironmentDefinitionBuilder<T> {
 	inner: imp::EnvironmentDefinitionBuilder<T>,
+	/// The environment of the secondary backend in the differential mode, `None` if any memory
+	/// has no shadow.
+	shadow: Option<differential::ShadowBuilder<T>>,
+	/// The memories with their import names, which are captured in the trap snapshot and the
+	/// reproducer.
+	memories: Vec<(Vec<u8>, Vec<u8>, Memory)>,
+	/// The import names of the host functions, `None` if the recording mode is disabled.
+	host_funcs: Option<Vec<(Vec<u8>, Vec<u8>)>>,
 }
 
 impl<T> EnvironmentDefinitionBuilder<T> {
@@ -197,6 +283,13 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 	pub fn new() -> EnvironmentDefinitionBuilder<T> {
 		EnvironmentDefinitionBuilder {
 			inner: imp::EnvironmentDefinitionBuilder::new(),
+			shadow: if differential::differential() {
+				Some(differential::ShadowBuilder::new())
+			} else {
+				None
+			},
+			memories: Vec::new(),
+			host_funcs: replay::recording().map(|_| Vec::new()),
 		}
 	}
 
@@ -211,6 +304,13 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 		N1: Into<Vec<u8>>,
 		N2: Into<Vec<u8>>,
 	{
+		let (module, field) = (module.into(), field.into());
+		if let Some(shadow) = &mut self.shadow {
+			shadow.add_host_func(module.clone(), field.clone(), f);
+		}
+		if let Some(host_funcs) = &mut self.host_funcs {
+			host_funcs.push((module.clone(), field.clone()));
+		}
 		self.inner.add_host_func(module, field, f);
 	}
 
@@ -220,7 +320,24 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 		N1: Into<Vec<u8>>,
 		N2: Into<Vec<u8>>,
 	{
-		self.inner.add_memory(module, field, mem.inner);
+		let (module, field) = (module.into(), field.into());
+		let shadowed = match (&mut self.shadow, &mem.shadow) {
+			(Some(shadow), Some(secondary)) => {
+				shadow.add_memory(
+					module.clone(),
+					field.clone(),
+					mem.inner.clone(),
+					secondary.clone(),
+				);
+				true
+			}
+			_ => false,
+		};
+		if !shadowed {
+			self.shadow = None;
+		}
+		self.inner.add_memory(module.clone(), field.clone(), mem.inner.clone());
+		self.memories.push((module, field, mem));
 	}
 }
 
@@ -229,6 +346,13 @@ impl<T> EnvironmentDefinitionBuilder<T> {
 /// This instance can be used for invoking exported functions.
 pub struct Instance<T> {
 	inner: imp::Instance<T>,
+	/// The instance of the secondary backend in the differential mode.
+	shadow: Option<differential::Shadow<T>>,
+	/// The exported globals, `None` if the trap snapshot is disabled.
+	globals: Option<Vec<String>>,
+	memories: Vec<(Vec<u8>, Vec<u8>, Memory)>,
+	/// The recorder of the invocations, `None` if the recording mode is disabled.
+	recorder: Option<replay::Recorder>,
 }
 
 impl<T> Instance<T> {
@@ -239,17 +363,66 @@ impl<T> Instance<T> {
 	/// environment. If execution of `start` function generated a trap, then `Err(Error::Execution)` will
 	/// be returned.
 	///
+	/// The module is instantiated by the backend of the memories in the environment, or the
+	/// backend selected by [`set_executor`] if there is no memory.
+	///
 	/// [`EnvironmentDefinitionBuilder`]: struct.EnvironmentDefinitionBuilder.html
 	pub fn new(
 		code: &[u8],
 		env_def_builder: &EnvironmentDefinitionBuilder<T>,
 		state: &mut T,
 	) -> Result<Instance<T>, Error> {
+		let executor = env_def_builder.inner.executor()?;
+		Self::new_with_executor(executor, code, env_def_builder, state)
+	}
+
+	/// Instantiate a module by the given backend.
+	///
+	/// Returns `Err(Error::Module)` if the backend is not compiled in, or the memories in the
+	/// environment are created by another backend.
+	///
+	/// In [`debugger::debuggable`] with a debugger attached, the interpreter instruments the
+	/// module to be paused by the debugger.
+	pub fn new_with_executor(
+		executor: SandboxExecutor,
+		code: &[u8],
+		env_def_builder: &EnvironmentDefinitionBuilder<T>,
+		state: &mut T,
+	) -> Result<Instance<T>, Error> {
+		if executor == SandboxExecutor::Jit && debugger::enabled() {
+			log::warn!(
+				target: "sandbox",
+				"The debugger is attached, but only the interpreter could be debugged"
+			);
+		}
+		let inner = imp::Instance::new(executor, code, &env_def_builder.inner, state)?;
+		let shadow = env_def_builder.shadow.as_ref().and_then(|shadow| {
+			debugger::undebuggable(|| {
+				snapshot::untracked(|| shadow.instantiate(executor, code, state))
+			})
+		});
+		let globals = if snapshot::trap_snapshot() {
+			Some(snapshot::exported_globals(&inner))
+		} else {
+			None
+		};
+		let recorder = env_def_builder.host_funcs.as_ref().and_then(|host_funcs| {
+			replay::Recorder::new(code, host_funcs, &env_def_builder.memories)
+		});
 		Ok(Instance {
-			inner: imp::Instance::new(code, &env_def_builder.inner, state)?,
+			inner,
+			shadow,
+			globals,
+			memories: env_def_builder.memories.clone(),
+			recorder,
 		})
 	}
 
+	/// The backend which instantiates this instance.
+	pub fn executor(&self) -> SandboxExecutor {
+		self.inner.executor()
+	}
+
 	/// Invoke an exported function with the given name.
 	///
 	/// # Errors
@@ -261,13 +434,48 @@ impl<T> Instance<T> {
 	/// - If types of the arguments passed to the function doesn't match function signature
 	///   then trap occurs (as if the exported function was called via call_indirect),
 	/// - Trap occurred at the execution time.
+	///
+	/// If the trap snapshot is enabled, the state of this instance is captured on the trap, see
+	/// [`snapshot`]. In the recording mode, the invocation is written into a reproducer, see
+	/// [`replay`]. In the profiling mode, the profile of the invocation by the interpreter is
+	/// reported, see [`profiler`]. In the coverage mode, the hits of the blocks are aggregated,
+	/// see [`coverage`].
 	pub fn invoke(
 		&mut self,
 		name: &str,
 		args: &[Value],
 		state: &mut T,
 	) -> Result<ReturnValue, Error> {
-		self.inner.invoke(name, args, state)
+		let depth = snapshot::DepthGuard::enter();
+		let _memory = debug_message::MemoryGuard::enter(
+			self.memories.first().map(|(_, _, memory)| memory.clone()),
+		);
+		let memories = self.recorder.as_ref().map(|recorder| recorder.begin());
+		let result = match &mut self.shadow {
+			Some(shadow) => shadow.invoke(&mut self.inner, name, args, state),
+			None => self.inner.invoke(name, args, state),
+		};
+		if let (Some(recorder), Some(memories)) = (&self.recorder, memories) {
+			recorder.end(self.inner.executor(), memories, name, args, &result);
+		}
+		if let Some(profile) = self.inner.profile(name, depth.depth()) {
+			profiler::report(profile);
+		}
+		if let Some(coverage) = self.inner.take_coverage() {
+			coverage::report(coverage);
+		}
+		if let (Err(Error::Trap(trap)), Some(globals)) = (&result, &self.globals) {
+			snapshot::capture(
+				depth.depth(),
+				&self.inner,
+				name,
+				args,
+				trap,
+				globals,
+				&self.memories,
+			);
+		}
+		result
 	}
 
 	/// Get the value from a global with the given `name`.
@@ -276,4 +484,40 @@ impl<T> Instance<T> {
 	pub fn get_global_val(&self, name: &str) -> Option<Value> {
 		self.inner.get_global_val(name)
 	}
+
+	/// List the exports of this instance with their types, in the order of the module.
+	pub fn exports(&self) -> Vec<ExportType> {
+		self.inner.exports()
+	}
+
+	/// Get the current size of the exported memory with the given `name`, in pages.
+	///
+	/// Returns `None` if the memory could not be found.
+	pub fn memory_size(&self, name: &str) -> Option<u32> {
+		self.inner.memory_size(name)
+	}
+
+	/// Read the exported memory with the given `name` at the address `ptr` with the size of the
+	/// provided slice `buf`.
+	///
+	/// Returns `Err(Error::Execution)` if the memory could not be found, or `Err(Error::OutOfBounds)`
+	/// if the range is out-of-bounds.
+	pub fn read_memory(&self, name: &str, ptr: u32, buf: &mut [u8]) -> Result<(), Error> {
+		self.inner.read_memory(name, ptr, buf)
+	}
+
+	/// Get the current size of the exported table with the given `name`, in elements.
+	///
+	/// Returns `None` if the table could not be found.
+	pub fn table_size(&self, name: &str) -> Option<u32> {
+		self.inner.table_size(name)
+	}
+
+	/// Get the signature of the function at `index` of the exported table with the given `name`.
+	///
+	/// Returns `Ok(None)` if the element is null or not a function, `Err(Error::Execution)` if the
+	/// table could not be found, or `Err(Error::OutOfBounds)` if the index is out-of-bounds.
+	pub fn table_get(&self, name: &str, index: u32) -> Result<Option<FuncType>, Error> {
+		self.inner.table_get(name, index)
+	}
 }
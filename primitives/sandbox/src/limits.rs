@@ -0,0 +1,223 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Resource limits of the sandbox
+//!
+//! The backends accept any module their implementation could execute, while a production chain
+//! is usually stricter. The limits emulate such a chain on both backends, and a violation fails
+//! with a dedicated [`TrapCode`]:
+//!
+//! - the size of the module and the initial size of its tables are checked before the module is
+//!   instantiated, as `ModuleSizeExceeded` and `TableLimitExceeded`;
+//! - the initial and maximum pages of the memories are checked when they are created, as
+//!   `MemoryLimitExceeded`, and the memories without a maximum can not grow beyond the limit;
+//! - the depth of the wasm call stack is checked when a function is called, as
+//!   `StackLimitExceeded`. The interpreter limits its call stack, and the jit instruments the
+//!   calls of the module to count the frames.
+use once_cell::sync::Lazy;
+use parity_wasm::elements::{Instruction, Module};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{
+	instrument::{self, HostFunc},
+	Error, Trap, TrapCode,
+};
+
+/// The import module of the stack height functions.
+pub const MODULE: &str = "__europa_limits";
+/// A wasm function is about to be called.
+const ENTER: &str = "enter";
+/// The called function returns.
+const EXIT: &str = "exit";
+/// The message of the traps raised by `enter` in the jit.
+pub(crate) const STACK_LIMIT_EXCEEDED: &str = "StackLimitExceeded";
+
+/// The resource limits, `None` leaves the limit to the backend.
+#[derive(
+	Clone,
+	Copy,
+	Default,
+	PartialEq,
+	Eq,
+	sp_core::RuntimeDebug,
+	codec::Encode,
+	codec::Decode,
+	Serialize,
+	Deserialize,
+)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SandboxLimits {
+	/// The maximum pages (64 KiB each) of a linear memory.
+	pub max_memory_pages: Option<u32>,
+	/// The maximum depth of the wasm call stack in frames, the invoked export is the first one.
+	pub max_stack_height: Option<u32>,
+	/// The maximum initial elements of a table.
+	pub max_table_size: Option<u32>,
+	/// The maximum size of a module in bytes.
+	pub max_module_size: Option<u32>,
+}
+
+impl SandboxLimits {
+	/// Fill the limits which are not set from `other`.
+	pub fn or(self, other: SandboxLimits) -> SandboxLimits {
+		SandboxLimits {
+			max_memory_pages: self.max_memory_pages.or(other.max_memory_pages),
+			max_stack_height: self.max_stack_height.or(other.max_stack_height),
+			max_table_size: self.max_table_size.or(other.max_table_size),
+			max_module_size: self.max_module_size.or(other.max_module_size),
+		}
+	}
+
+	/// Whether none of the limits is set.
+	pub fn is_empty(&self) -> bool {
+		*self == SandboxLimits::default()
+	}
+}
+
+impl fmt::Display for SandboxLimits {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let limits = [
+			("memory pages", self.max_memory_pages),
+			("stack height", self.max_stack_height),
+			("table size", self.max_table_size),
+			("module size", self.max_module_size),
+		];
+		let limits = limits
+			.iter()
+			.filter_map(|(name, limit)| limit.map(|limit| format!("{} {}", name, limit)))
+			.collect::<Vec<_>>();
+		if limits.is_empty() {
+			write!(f, "none")
+		} else {
+			write!(f, "{}", limits.join(", "))
+		}
+	}
+}
+
+static LIMITS: Lazy<RwLock<SandboxLimits>> = Lazy::new(|| RwLock::new(SandboxLimits::default()));
+
+/// Apply the limits to the memories and instances which are created after this call.
+pub fn set_limits(limits: SandboxLimits) {
+	*LIMITS.write() = limits;
+}
+
+/// The limits set by [`set_limits`].
+pub fn limits() -> SandboxLimits {
+	*LIMITS.read()
+}
+
+fn exceeded(code: TrapCode, reason: String) -> Error {
+	log::warn!(target: "sandbox", "{:?}: {}", code, reason);
+	Error::Trap(Trap {
+		code,
+		trace: vec![reason],
+		host_error: None,
+	})
+}
+
+fn check_pages(initial: u32, maximum: Option<u32>, limit: u32) -> Result<(), Error> {
+	if initial > limit || maximum.map_or(false, |maximum| maximum > limit) {
+		return Err(exceeded(
+			TrapCode::MemoryLimitExceeded,
+			format!(
+				"The memory of {} (maximum {:?}) pages exceeds the limit {}",
+				initial, maximum, limit
+			),
+		));
+	}
+	Ok(())
+}
+
+/// Check the pages of a memory to be created.
+///
+/// Returns the maximum of the memory, which is the limit if the memory has no maximum.
+pub(crate) fn check_memory(initial: u32, maximum: Option<u32>) -> Result<Option<u32>, Error> {
+	match limits().max_memory_pages {
+		Some(limit) => {
+			check_pages(initial, maximum, limit)?;
+			Ok(Some(maximum.unwrap_or(limit)))
+		}
+		None => Ok(maximum),
+	}
+}
+
+/// Check the size, the tables and the defined memories of a module to be instantiated.
+pub(crate) fn check_module(code: &[u8]) -> Result<(), Error> {
+	let limits = limits();
+	if let Some(limit) = limits.max_module_size {
+		if code.len() > limit as usize {
+			return Err(exceeded(
+				TrapCode::ModuleSizeExceeded,
+				format!(
+					"The module of {} bytes exceeds the limit {}",
+					code.len(),
+					limit
+				),
+			));
+		}
+	}
+	if limits.max_table_size.is_none() && limits.max_memory_pages.is_none() {
+		return Ok(());
+	}
+
+	let module = parity_wasm::deserialize_buffer::<Module>(code).map_err(|_| Error::Module)?;
+	if let (Some(limit), Some(section)) = (limits.max_table_size, module.table_section()) {
+		for table in section.entries() {
+			if table.limits().initial() > limit {
+				return Err(exceeded(
+					TrapCode::TableLimitExceeded,
+					format!(
+						"The table of {} elements exceeds the limit {}",
+						table.limits().initial(),
+						limit
+					),
+				));
+			}
+		}
+	}
+	if let (Some(limit), Some(section)) = (limits.max_memory_pages, module.memory_section()) {
+		for memory in section.entries() {
+			check_pages(memory.limits().initial(), memory.limits().maximum(), limit)?;
+		}
+	}
+	Ok(())
+}
+
+/// Instrument `code` to count the frames of the calls to the defined functions, the imported
+/// functions are executed by the host and push no frame.
+pub(crate) fn instrument(code: &[u8]) -> Result<Vec<u8>, String> {
+	let host_funcs = [
+		HostFunc {
+			name: ENTER,
+			params: vec![],
+			results: vec![],
+		},
+		HostFunc {
+			name: EXIT,
+			params: vec![],
+			results: vec![],
+		},
+	];
+	instrument::instrument(code, MODULE, &host_funcs, |function| {
+		let (enter, exit) = (function.host_base, function.host_base + 1);
+		let mut code = Vec::new();
+		for instruction in function.code {
+			match instruction {
+				Instruction::Call(index) if *index < function.host_base => {
+					code.push(instruction.clone())
+				}
+				Instruction::Call(_) | Instruction::CallIndirect(_, _) => {
+					code.push(Instruction::Call(enter));
+					code.push(instruction.clone());
+					code.push(Instruction::Call(exit));
+				}
+				instruction => code.push(instruction.clone()),
+			}
+		}
+		code
+	})
+	.map(|(code, _)| code)
+}
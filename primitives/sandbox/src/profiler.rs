@@ -0,0 +1,269 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Instruction profiler of the interpreter
+//!
+//! In the profiling mode, the module is instrumented before it is instantiated by the
+//! interpreter: every function notifies the profiler when it is entered, and every straight-line
+//! run of instructions reports its length before the control instruction which ends it. The
+//! calls are wrapped by the marks of the call site, thus the profiler unwinds its call stack once
+//! the callee returns. The instructions are attributed to the call stack, and the functions are
+//! named by the name section.
+//!
+//! The profile of every invocation is merged into the top frame of the contract tracing under
+//! `sandbox_profile`, by the `SandboxProfilesExt` externalities extension. The `folded` stacks
+//! are in the format of `flamegraph.pl` and `inferno-flamegraph`.
+use std::{
+	collections::BTreeMap,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use ep_extensions::SandboxProfilesExt;
+use parity_wasm::elements::{Instruction, ValueType};
+use serde::{Deserialize, Serialize};
+use sp_externalities::ExternalitiesExt;
+
+use crate::instrument::{self, is_control, Program};
+
+/// The import module of the profiler functions.
+pub const MODULE: &str = "__europa_profile";
+/// The function is entered.
+const ENTER: &str = "enter";
+/// Count the instructions of the current function.
+const COUNT: &str = "count";
+/// The function is about to call another one.
+const CALL: &str = "call";
+/// The callee returns to the function.
+const RETURN: &str = "return";
+/// The profiler functions, in the order of the imports.
+const HOST_FUNCS: [&str; 4] = [ENTER, COUNT, CALL, RETURN];
+
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+/// Enable the profiling mode, the instances created afterwards by the interpreter are profiled.
+pub fn set_profiling(enable: bool) {
+	PROFILING.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the profiling mode is enabled.
+pub fn profiling() -> bool {
+	PROFILING.load(Ordering::Relaxed)
+}
+
+/// The counters of a wasm function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionProfile {
+	/// The index of the function in the original module.
+	pub index: u32,
+	/// The symbol in the name section, or `func[index]`.
+	pub name: String,
+	pub calls: u64,
+	/// The instructions executed in the function itself, excluding the callees.
+	pub instructions: u64,
+}
+
+/// The profile of an invocation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+	/// The depth of the invocation, `1` for the outermost one.
+	pub depth: u32,
+	/// The invoked export.
+	pub function: String,
+	/// The instructions executed in total.
+	pub instructions: u64,
+	/// The functions sorted by the instructions, the hottest first.
+	pub functions: Vec<FunctionProfile>,
+	/// The call stacks with the instructions executed in them, e.g. `call;foo;bar 42`.
+	pub folded: Vec<String>,
+}
+
+impl Profile {
+	/// The folded stacks, one per line.
+	pub fn folded(&self) -> String {
+		self.folded.join("\n")
+	}
+}
+
+/// Report the profile in log and the contract tracing.
+pub(crate) fn report(profile: Profile) {
+	log::debug!(
+		target: "sandbox",
+		"Profile `{}` at depth {}: {} instructions in {} functions",
+		profile.function,
+		profile.depth,
+		profile.instructions,
+		profile.functions.len()
+	);
+	if let Ok(record) = serde_json::to_vec(&profile) {
+		sp_externalities::with_externalities(|ext| {
+			if let Some(records) = ext.extension::<SandboxProfilesExt>() {
+				records.0.push(record);
+			}
+		});
+	}
+}
+
+/// The counters of the instrumented instance.
+pub(crate) struct Profiler {
+	program: Program,
+	/// The functions being executed, the innermost last.
+	stack: Vec<u32>,
+	/// The length of the stack at the call sites.
+	calls: Vec<usize>,
+	/// The (calls, instructions) of the functions.
+	functions: BTreeMap<u32, (u64, u64)>,
+	stacks: BTreeMap<Vec<u32>, u64>,
+}
+
+impl Profiler {
+	pub fn new(program: Program) -> Self {
+		Profiler {
+			program,
+			stack: Vec::new(),
+			calls: Vec::new(),
+			functions: BTreeMap::new(),
+			stacks: BTreeMap::new(),
+		}
+	}
+
+	/// Clear the counters before an invocation.
+	pub fn reset(&mut self) {
+		self.stack.clear();
+		self.calls.clear();
+		self.functions.clear();
+		self.stacks.clear();
+	}
+
+	pub fn enter(&mut self, function: u32) {
+		self.stack.push(function);
+		self.functions.entry(function).or_default().0 += 1;
+	}
+
+	pub fn count(&mut self, instructions: u64) {
+		let function = match self.stack.last() {
+			Some(function) => *function,
+			None => return,
+		};
+		self.functions.entry(function).or_default().1 += instructions;
+		match self.stacks.get_mut(&self.stack[..]) {
+			Some(count) => *count += instructions,
+			None => {
+				self.stacks.insert(self.stack.clone(), instructions);
+			}
+		}
+	}
+
+	pub fn call(&mut self) {
+		self.calls.push(self.stack.len());
+	}
+
+	pub fn returned(&mut self) {
+		if let Some(len) = self.calls.pop() {
+			self.stack.truncate(len);
+		}
+	}
+
+	/// The profile of the last invocation.
+	pub fn profile(&self, function: &str, depth: u32) -> Profile {
+		let mut functions = self
+			.functions
+			.iter()
+			.map(|(index, (calls, instructions))| FunctionProfile {
+				index: *index,
+				name: self.program.symbol(*index),
+				calls: *calls,
+				instructions: *instructions,
+			})
+			.collect::<Vec<_>>();
+		functions.sort_by(|a, b| b.instructions.cmp(&a.instructions));
+		let folded = self
+			.stacks
+			.iter()
+			.map(|(stack, instructions)| {
+				let symbols = stack
+					.iter()
+					.map(|function| self.program.symbol(*function))
+					.collect::<Vec<_>>();
+				format!("{} {}", symbols.join(";"), instructions)
+			})
+			.collect();
+		Profile {
+			depth,
+			function: function.to_string(),
+			instructions: self.stacks.values().sum(),
+			functions,
+			folded,
+		}
+	}
+}
+
+/// The index of the profiler function `field` in [`HOST_FUNCS`].
+pub(crate) fn host_func(field: &str) -> Option<usize> {
+	HOST_FUNCS.iter().position(|f| *f == field)
+}
+
+/// The profiler function at `index` of [`HOST_FUNCS`].
+pub(crate) enum HostFunc {
+	Enter,
+	Count,
+	Call,
+	Return,
+}
+
+impl HostFunc {
+	pub fn from_index(index: usize) -> Option<HostFunc> {
+		match HOST_FUNCS.get(index)? {
+			&ENTER => Some(HostFunc::Enter),
+			&COUNT => Some(HostFunc::Count),
+			&CALL => Some(HostFunc::Call),
+			_ => Some(HostFunc::Return),
+		}
+	}
+}
+
+/// Instrument `code` with the counters of the instructions and calls.
+///
+/// Returns the instrumented code and the original program.
+pub(crate) fn instrument(code: &[u8]) -> Result<(Vec<u8>, Program), String> {
+	let signatures: [Vec<ValueType>; 4] =
+		[vec![ValueType::I32], vec![ValueType::I32], vec![], vec![]];
+	let host_funcs = HOST_FUNCS
+		.iter()
+		.zip(signatures.iter().cloned())
+		.map(|(name, params)| instrument::HostFunc {
+			name: *name,
+			params,
+			results: vec![],
+		})
+		.collect::<Vec<_>>();
+
+	instrument::instrument(code, MODULE, &host_funcs, |function| {
+		let base = function.host_base;
+		let (enter, count, call, ret) = (base, base + 1, base + 2, base + 3);
+
+		let mut code = vec![
+			Instruction::I32Const(function.index as i32),
+			Instruction::Call(enter),
+		];
+		let mut instructions = 0;
+		for instruction in function.code {
+			instructions += 1;
+			// the run includes the control instruction which ends it.
+			if is_control(instruction) {
+				code.push(Instruction::I32Const(instructions));
+				code.push(Instruction::Call(count));
+				instructions = 0;
+			}
+			match instruction {
+				Instruction::Call(_) | Instruction::CallIndirect(_, _) => {
+					code.push(Instruction::Call(call));
+					code.push(instruction.clone());
+					code.push(Instruction::Call(ret));
+				}
+				instruction => code.push(instruction.clone()),
+			}
+		}
+		code
+	})
+}
@@ -0,0 +1,374 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Host-call recording and offline replay
+//!
+//! In the recording mode, every `Instance::invoke` is written into a reproducer file in the
+//! recording directory. The reproducer holds the code, the imports, the memories before the
+//! invocation and the arguments. It also holds every host call, with its arguments, the
+//! returned value and the memory writes done by the host, and the result of the invocation.
+//!
+//! [`replay`] re-executes the reproducer without the runtime: the recorded host calls are
+//! returned to the module in order, and the memory writes are applied again, thus the execution
+//! is deterministic and could be stepped through by either backend.
+//!
+//! The host calls in the `start` function and the globals changed by the former invocations of
+//! the same instance are not recorded, neither happens in the contracts.
+use std::{
+	cell::RefCell,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use codec::{Decode, Encode};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::{
+	host_error,
+	imp::{self, recorder},
+	Error, HostError, Memory, ReturnValue, SandboxExecutor, Value,
+};
+
+/// The zstd level of the memories in the reproducer.
+const COMPRESSION_LEVEL: i32 = 3;
+/// The size of a wasm page.
+const PAGE_SIZE: usize = 65536;
+/// The extension of the reproducer files.
+pub const EXTENSION: &str = "replay";
+
+static RECORDING: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Record the invocations of the instances which are created after this call into the
+/// reproducer files in `dir`, or stop recording if `dir` is `None`.
+pub fn set_recording(dir: Option<PathBuf>) -> Result<(), String> {
+	if let Some(dir) = dir.as_ref() {
+		std::fs::create_dir_all(dir)
+			.map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+	}
+	*RECORDING.write() = dir;
+	Ok(())
+}
+
+/// The recording directory, `None` if the recording mode is disabled.
+pub fn recording() -> Option<PathBuf> {
+	RECORDING.read().clone()
+}
+
+/// The reproducer of an invocation.
+#[derive(Debug, Encode, Decode)]
+pub struct Recording {
+	/// The backend which executes the invocation.
+	pub executor: SandboxExecutor,
+	pub code: Vec<u8>,
+	/// The host functions in the environment, in `(module, field)`.
+	pub host_funcs: Vec<(String, String)>,
+	/// The memories in the environment before the invocation.
+	pub memories: Vec<RecordedMemory>,
+	/// The invoked export.
+	pub function: String,
+	pub args: Vec<Value>,
+	pub calls: Vec<RecordedCall>,
+	pub result: Result<ReturnValue, Error>,
+}
+
+/// The memory before the invocation.
+#[derive(Debug, Encode, Decode)]
+pub struct RecordedMemory {
+	pub module: String,
+	pub field: String,
+	/// The size in pages.
+	pub pages: u32,
+	/// The maximum size in pages.
+	pub maximum: Option<u32>,
+	/// The zstd compressed content.
+	pub data: Vec<u8>,
+}
+
+/// A host call in the invocation.
+#[derive(Debug, Encode, Decode)]
+pub struct RecordedCall {
+	/// The import name, in `module.field`.
+	pub name: String,
+	pub args: Vec<Value>,
+	/// `Err` if the host function returns `HostError`.
+	pub result: Result<ReturnValue, ()>,
+	/// The memory writes done by the host function.
+	pub writes: Vec<RecordedWrite>,
+}
+
+/// A memory write done by the host function.
+#[derive(Debug, Encode, Decode)]
+pub struct RecordedWrite {
+	/// The index of the memory in [`Recording::memories`].
+	pub memory: u32,
+	pub ptr: u32,
+	pub data: Vec<u8>,
+}
+
+impl Recording {
+	/// Read the reproducer file.
+	pub fn load(path: &Path) -> Result<Recording, String> {
+		let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+		Recording::decode(&mut &bytes[..]).map_err(|e| e.to_string())
+	}
+
+	/// Write the reproducer file.
+	pub fn save(&self, path: &Path) -> Result<(), String> {
+		std::fs::write(path, self.encode()).map_err(|e| e.to_string())
+	}
+}
+
+/// The result of the replay.
+#[derive(Debug)]
+pub struct Replayed {
+	pub result: Result<ReturnValue, Error>,
+	/// The host call which the module does not agree with the recorded one, in
+	/// `(index, expected, actual)`.
+	pub mismatch: Option<(usize, Option<String>, String)>,
+	/// The recorded calls which are not called by the module.
+	pub remaining: usize,
+}
+
+impl Replayed {
+	/// Whether the replay reproduces the recorded result with all the recorded host calls.
+	pub fn reproduced(&self, recording: &Recording) -> bool {
+		self.mismatch.is_none()
+			&& self.remaining == 0
+			&& match (&self.result, &recording.result) {
+				(Ok(a), Ok(b)) => a == b,
+				(Err(Error::Trap(a)), Err(Error::Trap(b))) => a.code == b.code,
+				(Err(a), Err(b)) => std::mem::discriminant(a) == std::mem::discriminant(b),
+				_ => false,
+			}
+	}
+}
+
+/// The host functions are never called in the replay, the recorded calls are returned instead.
+fn unrecorded(_: &mut (), _: &[Value]) -> Result<ReturnValue, HostError> {
+	Err(host_error::host_error("the host call is not recorded"))
+}
+
+/// Re-execute the reproducer by `executor`, or the recorded backend if it is `None`.
+pub fn replay(recording: &Recording, executor: Option<SandboxExecutor>) -> Result<Replayed, Error> {
+	let executor = executor.unwrap_or(recording.executor);
+	let mut env_builder = imp::EnvironmentDefinitionBuilder::<()>::new();
+	for (module, field) in recording.host_funcs.iter() {
+		env_builder.add_host_func(module.as_bytes(), field.as_bytes(), unrecorded);
+	}
+	let mut memories = Vec::new();
+	for memory in recording.memories.iter() {
+		let mem = imp::Memory::new(executor, memory.pages, memory.maximum)?;
+		env_builder.add_memory(
+			memory.module.as_bytes(),
+			memory.field.as_bytes(),
+			mem.clone(),
+		);
+		memories.push(mem);
+	}
+
+	// NOTE: the backend instance is used directly, the replay is never recorded, captured or
+	// checked by the other backend.
+	let mut instance = imp::Instance::new(executor, &recording.code, &env_builder, &mut ())?;
+	// restore the memories after the data segments are applied by the instantiation.
+	for (mem, memory) in memories.iter().zip(recording.memories.iter()) {
+		let data = zstd::block::decompress(&memory.data, memory.pages as usize * PAGE_SIZE)
+			.map_err(|_| Error::Module)?;
+		mem.set(0, &data)?;
+	}
+
+	let calls = recording
+		.calls
+		.iter()
+		.map(|call| {
+			let writes = call
+				.writes
+				.iter()
+				.map(|write| {
+					Ok(recorder::MemoryWrite {
+						memory: memories
+							.get(write.memory as usize)
+							.ok_or(Error::Module)?
+							.clone(),
+						ptr: write.ptr,
+						data: write.data.clone(),
+					})
+				})
+				.collect::<Result<_, Error>>()?;
+			Ok(recorder::HostCall {
+				name: call.name.clone(),
+				args: call.args.clone(),
+				result: call.result,
+				writes,
+			})
+		})
+		.collect::<Result<Vec<_>, Error>>()?;
+
+	recorder::begin_replay(calls);
+	let result = instance.invoke(&recording.function, &recording.args, &mut ());
+	let replayed = recorder::end_replay();
+	Ok(Replayed {
+		result,
+		mismatch: replayed.mismatch.map(|mismatch| {
+			(
+				mismatch.index,
+				mismatch
+					.expected
+					.map(|(name, args)| format!("{}{:?}", name, args)),
+				format!("{}{:?}", mismatch.actual.0, mismatch.actual.1),
+			)
+		}),
+		remaining: replayed.remaining,
+	})
+}
+
+/// The calls of an invocation in progress.
+struct Capture {
+	/// The ids of the memories in the environment.
+	memories: Vec<u64>,
+	calls: Vec<RecordedCall>,
+	/// The writes of current host call, `None` if no host call is in progress.
+	writes: Option<Vec<RecordedWrite>>,
+}
+
+thread_local! {
+	static CAPTURES: RefCell<Vec<Capture>> = RefCell::new(Vec::new());
+}
+
+/// The environment of the recorded instance.
+pub(crate) struct Recorder {
+	dir: PathBuf,
+	code: Vec<u8>,
+	host_funcs: Vec<(String, String)>,
+	memories: Vec<(String, String, Memory)>,
+}
+
+impl Recorder {
+	/// Start recording the instance of `code`, `None` if the recording mode is disabled.
+	pub fn new(
+		code: &[u8],
+		host_funcs: &[(Vec<u8>, Vec<u8>)],
+		memories: &[(Vec<u8>, Vec<u8>, Memory)],
+	) -> Option<Recorder> {
+		let name = |name: &[u8]| String::from_utf8_lossy(name).to_string();
+		Some(Recorder {
+			dir: recording()?,
+			code: code.to_vec(),
+			host_funcs: host_funcs
+				.iter()
+				.map(|(module, field)| (name(module), name(field)))
+				.collect(),
+			memories: memories
+				.iter()
+				.map(|(module, field, memory)| (name(module), name(field), memory.clone()))
+				.collect(),
+		})
+	}
+
+	/// Start recording an invocation, returns the memories before the invocation.
+	pub fn begin(&self) -> Vec<RecordedMemory> {
+		CAPTURES.with(|captures| {
+			captures.borrow_mut().push(Capture {
+				memories: self
+					.memories
+					.iter()
+					.map(|(_, _, memory)| memory.id)
+					.collect(),
+				calls: Vec::new(),
+				writes: None,
+			})
+		});
+		self.memories
+			.iter()
+			.map(|(module, field, memory)| {
+				let content = memory.inner.to_vec();
+				RecordedMemory {
+					module: module.clone(),
+					field: field.clone(),
+					pages: (content.len() / PAGE_SIZE) as u32,
+					maximum: memory.maximum,
+					data: zstd::block::compress(&content, COMPRESSION_LEVEL).unwrap_or_default(),
+				}
+			})
+			.collect()
+	}
+
+	/// Stop recording the invocation of `function`, and write the reproducer file.
+	pub fn end(
+		&self,
+		executor: SandboxExecutor,
+		memories: Vec<RecordedMemory>,
+		function: &str,
+		args: &[Value],
+		result: &Result<ReturnValue, Error>,
+	) {
+		let calls = CAPTURES.with(|captures| match captures.borrow_mut().pop() {
+			Some(capture) => capture.calls,
+			None => unreachable!("end must be paired with begin; qed"),
+		});
+
+		let recording = Recording {
+			executor,
+			code: self.code.clone(),
+			host_funcs: self.host_funcs.clone(),
+			memories,
+			function: function.into(),
+			args: args.to_vec(),
+			calls,
+			result: result.clone(),
+		};
+		let path = self.dir.join(format!(
+			"{:06}-{}-{}.{}",
+			COUNTER.fetch_add(1, Ordering::Relaxed),
+			hex::encode(&sp_core::hashing::blake2_256(&self.code)[..4]),
+			function,
+			EXTENSION
+		));
+		if let Err(e) = recording.save(&path) {
+			log::warn!(target: "sandbox", "Could not record `{}`: {}", function, e);
+		}
+	}
+}
+
+/// Mark the start of a host call in current invocation, returns whether it is recorded.
+pub(crate) fn begin_host_call() -> bool {
+	CAPTURES.with(|captures| match captures.borrow_mut().last_mut() {
+		Some(capture) => {
+			capture.writes = Some(Vec::new());
+			true
+		}
+		None => false,
+	})
+}
+
+/// Record the host call, which is started by `begin_host_call`.
+pub(crate) fn end_host_call(name: &str, args: &[Value], result: &Result<ReturnValue, HostError>) {
+	CAPTURES.with(|captures| {
+		if let Some(capture) = captures.borrow_mut().last_mut() {
+			capture.calls.push(RecordedCall {
+				name: name.into(),
+				args: args.to_vec(),
+				result: result.as_ref().map(|r| *r).map_err(|_| ()),
+				writes: capture.writes.take().unwrap_or_default(),
+			});
+		}
+	});
+}
+
+/// Record the memory write done by the host in current host call.
+pub(crate) fn record_write(memory: &Memory, ptr: u32, data: &[u8]) {
+	CAPTURES.with(|captures| {
+		if let Some(capture) = captures.borrow_mut().last_mut() {
+			let index = capture.memories.iter().position(|id| *id == memory.id);
+			if let (Some(writes), Some(index)) = (capture.writes.as_mut(), index) {
+				writes.push(RecordedWrite {
+					memory: index as u32,
+					ptr,
+					data: data.to_vec(),
+				});
+			}
+		}
+	});
+}
@@ -0,0 +1,477 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Trap snapshot
+//!
+//! When enabled, the imported linear memories (compressed), the exported globals, the arguments
+//! and the locals of the trapping frame are captured when `Instance::invoke` traps, for the
+//! post-mortem debugging. The snapshots are stored next to the contract tracing by the
+//! `TrapSnapshotsExt` externalities extension.
+//!
+//! Neither backend exposes the locals of the frames after the trap, thus the module is
+//! instrumented like the step debugger does: every instruction which could trap (`unreachable`,
+//! the memory accesses, the integer divisions, the float truncations and the calls) is prefixed
+//! by a call to the functions imported from [`MODULE`], which pass the index of the function and
+//! its params and locals to the host one by one. The calls of the defined functions push and pop
+//! a frame, thus the innermost recorded frame is the trapping one. The traps which are not raised
+//! by an instruction, e.g. the interruption or the exhausted fuel, capture the locals recorded
+//! before the last instruction which could trap.
+use std::{
+	cell::{Cell, RefCell},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use codec::{Decode, Encode};
+use ep_extensions::TrapSnapshotsExt;
+use parity_wasm::elements::{Instruction, Module, Type, ValueType};
+use sp_externalities::ExternalitiesExt;
+
+use crate::{
+	imp,
+	instrument::{self, HostFunc as InstrumentedFunc},
+	ExternType, Memory, SandboxExecutor, Trap, Value,
+};
+
+/// The zstd level of the memory, the memory of the contracts is mostly zeroed.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The import module of the snapshot functions.
+pub const MODULE: &str = "__europa_snapshot";
+/// A defined function is about to be called.
+const ENTER: &str = "enter";
+/// The called function returns.
+const EXIT: &str = "exit";
+/// An instruction which could trap is about to be executed by `function`, the locals follow.
+const RECORD: &str = "record";
+/// Pass a local of the recorded frame.
+const LOCAL_I32: &str = "local_i32";
+const LOCAL_I64: &str = "local_i64";
+const LOCAL_F32: &str = "local_f32";
+const LOCAL_F64: &str = "local_f64";
+/// The snapshot functions, in the order of the imports.
+const HOST_FUNCS: [&str; 7] = [
+	ENTER, EXIT, RECORD, LOCAL_I32, LOCAL_I64, LOCAL_F32, LOCAL_F64,
+];
+
+static TRAP_SNAPSHOT: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+	/// The indexes of the running invocations (the innermost is the last one), and the index of
+	/// the next invocation.
+	static FRAMES: RefCell<(Vec<u32>, u32)> = RefCell::new((Vec::new(), 0));
+	/// The wasm call stacks of the running invocations, the frames which have not recorded yet
+	/// are `None`.
+	static CALL_STACKS: RefCell<Vec<Vec<Option<FrameSnapshot>>>> = RefCell::new(Vec::new());
+	static UNTRACKED: Cell<bool> = Cell::new(false);
+}
+
+/// Enable or disable the trap snapshot for the instances which are created after this call.
+pub fn set_trap_snapshot(enable: bool) {
+	TRAP_SNAPSHOT.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the trap snapshot is enabled.
+pub fn trap_snapshot() -> bool {
+	TRAP_SNAPSHOT.load(Ordering::Relaxed)
+}
+
+/// Run `f` with the instances created in it not recording their frames, e.g. the secondary
+/// instance in the differential mode, which would overwrite the frames of the primary one.
+pub(crate) fn untracked<R>(f: impl FnOnce() -> R) -> R {
+	let previous = UNTRACKED.with(|u| u.replace(true));
+	let result = f();
+	UNTRACKED.with(|u| u.set(previous));
+	result
+}
+
+/// Whether the instance created now should be instrumented to record its frames.
+pub(crate) fn tracked() -> bool {
+	trap_snapshot() && !UNTRACKED.with(|u| u.get())
+}
+
+/// The state of the instance when it traps.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct TrapSnapshot {
+	/// The depth of the invocation, `1` for the outermost one.
+	pub depth: u32,
+	pub executor: SandboxExecutor,
+	/// The invoked export.
+	pub function: String,
+	/// The arguments passed to the invoked export.
+	pub args: Vec<Value>,
+	/// The trapping frame, `None` if the instance is not instrumented or traps before any frame
+	/// is recorded.
+	pub frame: Option<FrameSnapshot>,
+	pub trap: Trap,
+	/// The values of the exported globals.
+	pub globals: Vec<(String, Value)>,
+	/// The imported linear memories.
+	pub memories: Vec<MemorySnapshot>,
+}
+
+/// The frame of the function which traps.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct FrameSnapshot {
+	/// The index of the function in the function index space of the original code.
+	pub function: u32,
+	/// The params and then the locals, before the last instruction which could trap.
+	pub locals: Vec<Value>,
+}
+
+/// The linear memory when the instance traps.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct MemorySnapshot {
+	/// The import module of the memory.
+	pub module: String,
+	/// The import field of the memory.
+	pub field: String,
+	/// The size of the memory in bytes.
+	pub size: u32,
+	/// The zstd compressed content.
+	pub data: Vec<u8>,
+}
+
+impl MemorySnapshot {
+	/// Decompress the content of the memory.
+	pub fn decompress(&self) -> Result<Vec<u8>, String> {
+		zstd::block::decompress(&self.data, self.size as usize).map_err(|e| e.to_string())
+	}
+}
+
+/// Track the depth of the invocation, the depth is decreased when the guard is dropped.
+pub(crate) struct DepthGuard(u32);
+
+impl DepthGuard {
+	pub fn enter() -> Self {
+		let guard = DepthGuard(FRAMES.with(|frames| {
+			let (running, next) = &mut *frames.borrow_mut();
+			// the indexes restart from the outermost invocation.
+			if running.is_empty() {
+				*next = 0;
+			}
+			running.push(*next);
+			*next += 1;
+			running.len() as u32
+		}));
+		// the invoked export is the first frame.
+		CALL_STACKS.with(|stacks| stacks.borrow_mut().push(vec![None]));
+		guard
+	}
+
+	pub fn depth(&self) -> u32 {
+		self.0
+	}
+}
+
+impl Drop for DepthGuard {
+	fn drop(&mut self) {
+		FRAMES.with(|frames| frames.borrow_mut().0.pop());
+		CALL_STACKS.with(|stacks| stacks.borrow_mut().pop());
+	}
+}
+
+/// Update the call stack of the innermost invocation, nothing happens out of an invocation.
+fn with_call_stack(f: impl FnOnce(&mut Vec<Option<FrameSnapshot>>)) {
+	CALL_STACKS.with(|stacks| {
+		if let Some(stack) = stacks.borrow_mut().last_mut() {
+			f(stack)
+		}
+	})
+}
+
+/// A defined function is called.
+pub(crate) fn enter_frame() {
+	with_call_stack(|stack| stack.push(None))
+}
+
+/// The called function returns.
+pub(crate) fn exit_frame() {
+	with_call_stack(|stack| {
+		stack.pop();
+	})
+}
+
+/// The current frame of `function` is about to execute an instruction which could trap, the
+/// locals are passed by [`record_local`] next.
+pub(crate) fn record_frame(function: u32) {
+	with_call_stack(|stack| {
+		if let Some(frame) = stack.last_mut() {
+			*frame = Some(FrameSnapshot {
+				function,
+				locals: Vec::new(),
+			});
+		}
+	})
+}
+
+/// Pass a local of the frame recorded by [`record_frame`].
+pub(crate) fn record_local(value: Value) {
+	with_call_stack(|stack| {
+		if let Some(Some(frame)) = stack.last_mut() {
+			frame.locals.push(value);
+		}
+	})
+}
+
+/// The innermost recorded frame of the innermost invocation.
+fn trapping_frame() -> Option<FrameSnapshot> {
+	CALL_STACKS.with(|stacks| {
+		stacks
+			.borrow()
+			.last()?
+			.iter()
+			.rev()
+			.find_map(|frame| frame.clone())
+	})
+}
+
+/// The index of the snapshot function `field` in [`HOST_FUNCS`].
+pub(crate) fn host_func(field: &str) -> Option<usize> {
+	HOST_FUNCS.iter().position(|f| *f == field)
+}
+
+/// The snapshot function at `index` of [`HOST_FUNCS`].
+pub(crate) enum HostFunc {
+	Enter,
+	Exit,
+	Record,
+	Local,
+}
+
+impl HostFunc {
+	pub fn from_index(index: usize) -> Option<HostFunc> {
+		match HOST_FUNCS.get(index)? {
+			&ENTER => Some(HostFunc::Enter),
+			&EXIT => Some(HostFunc::Exit),
+			&RECORD => Some(HostFunc::Record),
+			_ => Some(HostFunc::Local),
+		}
+	}
+}
+
+/// Whether the instruction could trap, thus the frame is recorded before it.
+fn could_trap(instruction: &Instruction) -> bool {
+	use Instruction::*;
+	matches!(
+		instruction,
+		Unreachable
+			| Call(_) | CallIndirect(_, _)
+			| I32Load(_, _)
+			| I64Load(_, _)
+			| F32Load(_, _)
+			| F64Load(_, _)
+			| I32Load8S(_, _)
+			| I32Load8U(_, _)
+			| I32Load16S(_, _)
+			| I32Load16U(_, _)
+			| I64Load8S(_, _)
+			| I64Load8U(_, _)
+			| I64Load16S(_, _)
+			| I64Load16U(_, _)
+			| I64Load32S(_, _)
+			| I64Load32U(_, _)
+			| I32Store(_, _)
+			| I64Store(_, _)
+			| F32Store(_, _)
+			| F64Store(_, _)
+			| I32Store8(_, _)
+			| I32Store16(_, _)
+			| I64Store8(_, _)
+			| I64Store16(_, _)
+			| I64Store32(_, _)
+			| I32DivS | I32DivU
+			| I32RemS | I32RemU
+			| I64DivS | I64DivU
+			| I64RemS | I64RemU
+			| I32TruncSF32
+			| I32TruncUF32
+			| I32TruncSF64
+			| I32TruncUF64
+			| I64TruncSF32
+			| I64TruncUF32
+			| I64TruncSF64
+			| I64TruncUF64
+	)
+}
+
+/// The number of the params and the locals of every defined function in `code`.
+fn frame_sizes(code: &[u8]) -> Result<Vec<usize>, String> {
+	let module = parity_wasm::deserialize_buffer::<Module>(code).map_err(|e| e.to_string())?;
+	let types = module
+		.type_section()
+		.map(|section| section.types())
+		.unwrap_or_default();
+	let functions = module
+		.function_section()
+		.map(|section| section.entries())
+		.unwrap_or_default();
+	let bodies = module
+		.code_section()
+		.map(|section| section.bodies())
+		.unwrap_or_default();
+	Ok(functions
+		.iter()
+		.zip(bodies)
+		.map(|(func, body)| {
+			let params = match types.get(func.type_ref() as usize) {
+				Some(Type::Function(ty)) => ty.params().len(),
+				None => 0,
+			};
+			params
+				+ body
+					.locals()
+					.iter()
+					.map(|l| l.count() as usize)
+					.sum::<usize>()
+		})
+		.collect())
+}
+
+/// Instrument `code`, which is `original` or instrumented from it, to record the frames before
+/// every instruction which could trap.
+///
+/// The calls of the functions imported by the previous instrumentation are left alone, thus the
+/// arguments of them are still pushed right before the calls. The stack height limiter, whose
+/// calls could trap, instruments the calls after this one.
+pub(crate) fn instrument(code: &[u8], original: &[u8]) -> Result<Vec<u8>, String> {
+	let imported = instrument::imported_functions(original)?;
+	let sizes = frame_sizes(original)?;
+	let signatures: [Vec<ValueType>; 7] = [
+		vec![],
+		vec![],
+		vec![ValueType::I32],
+		vec![ValueType::I32],
+		vec![ValueType::I64],
+		vec![ValueType::F32],
+		vec![ValueType::F64],
+	];
+	let host_funcs = HOST_FUNCS
+		.iter()
+		.zip(signatures.iter().cloned())
+		.map(|(name, params)| InstrumentedFunc {
+			name: *name,
+			params,
+			results: vec![],
+		})
+		.collect::<Vec<_>>();
+
+	instrument::instrument(code, MODULE, &host_funcs, |function| {
+		let base = function.host_base;
+		let (enter, exit, record) = (base, base + 1, base + 2);
+		let local = |ty: ValueType| match ty {
+			ValueType::I32 => base + 3,
+			ValueType::I64 => base + 4,
+			ValueType::F32 => base + 5,
+			ValueType::F64 => base + 6,
+		};
+		// the previous instrumentation imports its functions after the original ones, and
+		// appends its locals after the original ones.
+		let index = function.index - (base - imported);
+		let size = index
+			.checked_sub(imported)
+			.and_then(|index| sizes.get(index as usize))
+			.copied()
+			.unwrap_or_default();
+
+		let injected = |instruction: &Instruction| match instruction {
+			Instruction::Call(index) => *index >= imported && *index < base,
+			_ => false,
+		};
+
+		let mut code = Vec::new();
+		for instruction in function.code {
+			if could_trap(instruction) && !injected(instruction) {
+				code.push(Instruction::I32Const(index as i32));
+				code.push(Instruction::Call(record));
+				for (i, ty) in function.locals.iter().take(size).enumerate() {
+					code.push(Instruction::GetLocal(i as u32));
+					code.push(Instruction::Call(local(*ty)));
+				}
+			}
+			match instruction {
+				// the imported functions push no frame.
+				Instruction::Call(index) if *index < base => code.push(instruction.clone()),
+				Instruction::Call(_) | Instruction::CallIndirect(_, _) => {
+					code.push(Instruction::Call(enter));
+					code.push(instruction.clone());
+					code.push(Instruction::Call(exit));
+				}
+				instruction => code.push(instruction.clone()),
+			}
+		}
+		code
+	})
+	.map(|(code, _)| code)
+}
+
+/// The innermost running invocation as `(depth, index)`, the depth is `1` for the outermost
+/// invocation, and the index counts the invocations under the outermost one in the order they
+/// are entered, `0` for the outermost one. `None` if no invocation is running.
+///
+/// A host function which is called by the sandboxed code is in the frame of the invocation.
+pub fn current_frame() -> Option<(u32, u32)> {
+	FRAMES.with(|frames| {
+		let (running, _) = &*frames.borrow();
+		running.last().map(|index| (running.len() as u32, *index))
+	})
+}
+
+/// The names of the exported globals of the instance, which are read when the instance traps.
+pub(crate) fn exported_globals<T>(instance: &imp::Instance<T>) -> Vec<String> {
+	instance
+		.exports()
+		.into_iter()
+		.filter(|export| matches!(export.ty, ExternType::Global { .. }))
+		.map(|export| export.name)
+		.collect()
+}
+
+/// Capture the snapshot of the trapped instance, and push it into the `TrapSnapshotsExt`.
+pub(crate) fn capture<T>(
+	depth: u32,
+	instance: &imp::Instance<T>,
+	function: &str,
+	args: &[Value],
+	trap: &Trap,
+	globals: &[String],
+	memories: &[(Vec<u8>, Vec<u8>, Memory)],
+) {
+	let snapshot = TrapSnapshot {
+		depth,
+		executor: instance.executor(),
+		function: function.into(),
+		args: args.to_vec(),
+		frame: trapping_frame(),
+		trap: trap.clone(),
+		globals: globals
+			.iter()
+			.filter_map(|name| Some((name.clone(), instance.get_global_val(name)?)))
+			.collect(),
+		memories: memories
+			.iter()
+			.map(|(module, field, memory)| {
+				let content = memory.inner.to_vec();
+				MemorySnapshot {
+					module: String::from_utf8_lossy(module).into(),
+					field: String::from_utf8_lossy(field).into(),
+					size: content.len() as u32,
+					data: zstd::block::compress(&content, COMPRESSION_LEVEL).unwrap_or_default(),
+				}
+			})
+			.collect(),
+	};
+
+	log::debug!(
+		target: "sandbox",
+		"Capture the snapshot of `{}` at depth {}: {}",
+		function,
+		depth,
+		trap
+	);
+	sp_externalities::with_externalities(|ext| {
+		if let Some(snapshots) = ext.extension::<TrapSnapshotsExt>() {
+			snapshots.0.push(snapshot.encode());
+		}
+	});
+}
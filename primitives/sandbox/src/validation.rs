@@ -0,0 +1,591 @@
+// This file is part of europa
+
+// Copyright 2020-2021 patract labs. Licensed under GPL-3.0.
+
+//! Validation of the contract code before it is deployed
+//!
+//! The backends fail with a bare `Error::Module` when they could not instantiate a module, and
+//! pallet-contracts rejects the code which it could not instrument without telling why. The
+//! validator walks the uploaded code and reports every wasm proposal, import, section or export
+//! which a contract could not use, together with the first place where it is found:
+//!
+//! - the proposals beyond the MVP, and the floats which are not deterministic;
+//! - the imports other than the functions of the `seal*` modules and the `env.memory`;
+//! - the memories defined by the module, since a contract imports its memory;
+//! - the missing `call` or `deploy` exports;
+//! - the violations of the resource limits set by [`crate::set_limits`].
+//!
+//! The module which is not even well-formed is reported as [`IssueKind::Malformed`].
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use wasmparser::{
+	ExternalKind, ImportSectionEntryType, Operator, Parser, Payload, Type, TypeDef, TypeOrFuncType,
+};
+
+use crate::{limits, Error};
+
+/// The floating-point types and instructions, which are not deterministic.
+pub const FLOATS: &str = "floats";
+/// The sign-extension operators proposal.
+pub const SIGN_EXT: &str = "sign-ext";
+/// The bulk memory operations proposal.
+pub const BULK_MEMORY: &str = "bulk-memory";
+/// The multi-value proposal.
+pub const MULTI_VALUE: &str = "multi-value";
+/// The reference types proposal.
+pub const REFERENCE_TYPES: &str = "reference-types";
+/// The fixed-width SIMD proposal.
+pub const SIMD: &str = "simd";
+/// The threads proposal.
+pub const THREADS: &str = "threads";
+/// The tail call proposal.
+pub const TAIL_CALL: &str = "tail-call";
+/// The exception handling proposal.
+pub const EXCEPTIONS: &str = "exceptions";
+
+/// The import modules of the host functions provided by pallet-contracts.
+const SEAL_MODULES: &[&str] = &["seal0", "seal1", "__unstable__"];
+/// The exports which are called by pallet-contracts.
+const EXPORTS: &[&str] = &["call", "deploy"];
+
+/// What is unsupported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+	/// The code is not a well-formed wasm module.
+	Malformed,
+	/// A wasm proposal, the name of the issue is one of the constants in this module.
+	Feature,
+	/// An import, named as `module.field`.
+	Import,
+	/// A section, named after the section.
+	Section,
+	/// A missing export.
+	Export,
+	/// A resource limit, named after the trap code.
+	Limit,
+}
+
+/// An unsupported item of the code.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+	/// What is unsupported.
+	pub kind: IssueKind,
+	/// The name of the item.
+	pub name: String,
+	/// Why the item is unsupported, and where it is first found.
+	pub reason: String,
+}
+
+/// The issues of the code, which is valid if there is none.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+	/// The issues in the order they are found, each item is reported once.
+	pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+	/// Whether the code could be deployed.
+	pub fn is_valid(&self) -> bool {
+		self.issues.is_empty()
+	}
+
+	/// The names of the unsupported wasm proposals.
+	pub fn features(&self) -> Vec<&str> {
+		self.issues
+			.iter()
+			.filter(|issue| issue.kind == IssueKind::Feature)
+			.map(|issue| issue.name.as_str())
+			.collect()
+	}
+
+	fn report(&mut self, kind: IssueKind, name: &str, reason: impl FnOnce() -> String) {
+		if !self
+			.issues
+			.iter()
+			.any(|issue| issue.kind == kind && issue.name == name)
+		{
+			self.issues.push(Issue {
+				kind,
+				name: name.to_string(),
+				reason: reason(),
+			});
+		}
+	}
+}
+
+impl fmt::Display for ValidationReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_valid() {
+			return write!(f, "valid");
+		}
+		for (i, issue) in self.issues.iter().enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{:?} {}: {}", issue.kind, issue.name, issue.reason)?;
+		}
+		Ok(())
+	}
+}
+
+/// The proposal which introduces the value type.
+fn type_feature(ty: Type) -> Option<&'static str> {
+	match ty {
+		Type::F32 | Type::F64 => Some(FLOATS),
+		Type::V128 => Some(SIMD),
+		Type::FuncRef | Type::ExternRef => Some(REFERENCE_TYPES),
+		_ => None,
+	}
+}
+
+/// The proposal which introduces the operator.
+fn operator_feature(operator: &Operator) -> Option<&'static str> {
+	match operator {
+		Operator::Block { ty } | Operator::Loop { ty } | Operator::If { ty } => match ty {
+			TypeOrFuncType::FuncType(_) => Some(MULTI_VALUE),
+			TypeOrFuncType::Type(ty) => type_feature(*ty),
+		},
+		Operator::Unreachable
+		| Operator::Nop
+		| Operator::Else
+		| Operator::End
+		| Operator::Br { .. }
+		| Operator::BrIf { .. }
+		| Operator::BrTable { .. }
+		| Operator::Return
+		| Operator::Call { .. }
+		| Operator::CallIndirect { .. }
+		| Operator::Drop
+		| Operator::Select
+		| Operator::LocalGet { .. }
+		| Operator::LocalSet { .. }
+		| Operator::LocalTee { .. }
+		| Operator::GlobalGet { .. }
+		| Operator::GlobalSet { .. }
+		| Operator::I32Load { .. }
+		| Operator::I64Load { .. }
+		| Operator::I32Load8S { .. }
+		| Operator::I32Load8U { .. }
+		| Operator::I32Load16S { .. }
+		| Operator::I32Load16U { .. }
+		| Operator::I64Load8S { .. }
+		| Operator::I64Load8U { .. }
+		| Operator::I64Load16S { .. }
+		| Operator::I64Load16U { .. }
+		| Operator::I64Load32S { .. }
+		| Operator::I64Load32U { .. }
+		| Operator::I32Store { .. }
+		| Operator::I64Store { .. }
+		| Operator::I32Store8 { .. }
+		| Operator::I32Store16 { .. }
+		| Operator::I64Store8 { .. }
+		| Operator::I64Store16 { .. }
+		| Operator::I64Store32 { .. }
+		| Operator::MemorySize { .. }
+		| Operator::MemoryGrow { .. }
+		| Operator::I32Const { .. }
+		| Operator::I64Const { .. }
+		| Operator::I32Eqz
+		| Operator::I32Eq
+		| Operator::I32Ne
+		| Operator::I32LtS
+		| Operator::I32LtU
+		| Operator::I32GtS
+		| Operator::I32GtU
+		| Operator::I32LeS
+		| Operator::I32LeU
+		| Operator::I32GeS
+		| Operator::I32GeU
+		| Operator::I64Eqz
+		| Operator::I64Eq
+		| Operator::I64Ne
+		| Operator::I64LtS
+		| Operator::I64LtU
+		| Operator::I64GtS
+		| Operator::I64GtU
+		| Operator::I64LeS
+		| Operator::I64LeU
+		| Operator::I64GeS
+		| Operator::I64GeU
+		| Operator::I32Clz
+		| Operator::I32Ctz
+		| Operator::I32Popcnt
+		| Operator::I32Add
+		| Operator::I32Sub
+		| Operator::I32Mul
+		| Operator::I32DivS
+		| Operator::I32DivU
+		| Operator::I32RemS
+		| Operator::I32RemU
+		| Operator::I32And
+		| Operator::I32Or
+		| Operator::I32Xor
+		| Operator::I32Shl
+		| Operator::I32ShrS
+		| Operator::I32ShrU
+		| Operator::I32Rotl
+		| Operator::I32Rotr
+		| Operator::I64Clz
+		| Operator::I64Ctz
+		| Operator::I64Popcnt
+		| Operator::I64Add
+		| Operator::I64Sub
+		| Operator::I64Mul
+		| Operator::I64DivS
+		| Operator::I64DivU
+		| Operator::I64RemS
+		| Operator::I64RemU
+		| Operator::I64And
+		| Operator::I64Or
+		| Operator::I64Xor
+		| Operator::I64Shl
+		| Operator::I64ShrS
+		| Operator::I64ShrU
+		| Operator::I64Rotl
+		| Operator::I64Rotr
+		| Operator::I32WrapI64
+		| Operator::I64ExtendI32S
+		| Operator::I64ExtendI32U => None,
+		Operator::F32Load { .. }
+		| Operator::F64Load { .. }
+		| Operator::F32Store { .. }
+		| Operator::F64Store { .. }
+		| Operator::F32Const { .. }
+		| Operator::F64Const { .. }
+		| Operator::F32Eq
+		| Operator::F32Ne
+		| Operator::F32Lt
+		| Operator::F32Gt
+		| Operator::F32Le
+		| Operator::F32Ge
+		| Operator::F64Eq
+		| Operator::F64Ne
+		| Operator::F64Lt
+		| Operator::F64Gt
+		| Operator::F64Le
+		| Operator::F64Ge
+		| Operator::F32Abs
+		| Operator::F32Neg
+		| Operator::F32Ceil
+		| Operator::F32Floor
+		| Operator::F32Trunc
+		| Operator::F32Nearest
+		| Operator::F32Sqrt
+		| Operator::F32Add
+		| Operator::F32Sub
+		| Operator::F32Mul
+		| Operator::F32Div
+		| Operator::F32Min
+		| Operator::F32Max
+		| Operator::F32Copysign
+		| Operator::F64Abs
+		| Operator::F64Neg
+		| Operator::F64Ceil
+		| Operator::F64Floor
+		| Operator::F64Trunc
+		| Operator::F64Nearest
+		| Operator::F64Sqrt
+		| Operator::F64Add
+		| Operator::F64Sub
+		| Operator::F64Mul
+		| Operator::F64Div
+		| Operator::F64Min
+		| Operator::F64Max
+		| Operator::F64Copysign
+		| Operator::I32TruncF32S
+		| Operator::I32TruncF32U
+		| Operator::I32TruncF64S
+		| Operator::I32TruncF64U
+		| Operator::I64TruncF32S
+		| Operator::I64TruncF32U
+		| Operator::I64TruncF64S
+		| Operator::I64TruncF64U
+		| Operator::F32ConvertI32S
+		| Operator::F32ConvertI32U
+		| Operator::F32ConvertI64S
+		| Operator::F32ConvertI64U
+		| Operator::F32DemoteF64
+		| Operator::F64ConvertI32S
+		| Operator::F64ConvertI32U
+		| Operator::F64ConvertI64S
+		| Operator::F64ConvertI64U
+		| Operator::F64PromoteF32
+		| Operator::I32ReinterpretF32
+		| Operator::I64ReinterpretF64
+		| Operator::F32ReinterpretI32
+		| Operator::F64ReinterpretI64
+		| Operator::I32TruncSatF32S
+		| Operator::I32TruncSatF32U
+		| Operator::I32TruncSatF64S
+		| Operator::I32TruncSatF64U
+		| Operator::I64TruncSatF32S
+		| Operator::I64TruncSatF32U
+		| Operator::I64TruncSatF64S
+		| Operator::I64TruncSatF64U => Some(FLOATS),
+		Operator::I32Extend8S
+		| Operator::I32Extend16S
+		| Operator::I64Extend8S
+		| Operator::I64Extend16S
+		| Operator::I64Extend32S => Some(SIGN_EXT),
+		Operator::MemoryInit { .. }
+		| Operator::DataDrop { .. }
+		| Operator::MemoryCopy { .. }
+		| Operator::MemoryFill { .. }
+		| Operator::TableInit { .. }
+		| Operator::ElemDrop { .. }
+		| Operator::TableCopy { .. } => Some(BULK_MEMORY),
+		Operator::TypedSelect { .. }
+		| Operator::RefNull { .. }
+		| Operator::RefIsNull
+		| Operator::RefFunc { .. }
+		| Operator::TableFill { .. }
+		| Operator::TableGet { .. }
+		| Operator::TableSet { .. }
+		| Operator::TableGrow { .. }
+		| Operator::TableSize { .. } => Some(REFERENCE_TYPES),
+		Operator::ReturnCall { .. } | Operator::ReturnCallIndirect { .. } => Some(TAIL_CALL),
+		Operator::Try { .. }
+		| Operator::Catch { .. }
+		| Operator::Throw { .. }
+		| Operator::Rethrow { .. }
+		| Operator::Unwind
+		| Operator::Delegate { .. }
+		| Operator::CatchAll => Some(EXCEPTIONS),
+		Operator::MemoryAtomicNotify { .. }
+		| Operator::MemoryAtomicWait32 { .. }
+		| Operator::MemoryAtomicWait64 { .. }
+		| Operator::AtomicFence { .. }
+		| Operator::I32AtomicLoad { .. }
+		| Operator::I64AtomicLoad { .. }
+		| Operator::I32AtomicLoad8U { .. }
+		| Operator::I32AtomicLoad16U { .. }
+		| Operator::I64AtomicLoad8U { .. }
+		| Operator::I64AtomicLoad16U { .. }
+		| Operator::I64AtomicLoad32U { .. }
+		| Operator::I32AtomicStore { .. }
+		| Operator::I64AtomicStore { .. }
+		| Operator::I32AtomicStore8 { .. }
+		| Operator::I32AtomicStore16 { .. }
+		| Operator::I64AtomicStore8 { .. }
+		| Operator::I64AtomicStore16 { .. }
+		| Operator::I64AtomicStore32 { .. }
+		| Operator::I32AtomicRmwAdd { .. }
+		| Operator::I64AtomicRmwAdd { .. }
+		| Operator::I32AtomicRmw8AddU { .. }
+		| Operator::I32AtomicRmw16AddU { .. }
+		| Operator::I64AtomicRmw8AddU { .. }
+		| Operator::I64AtomicRmw16AddU { .. }
+		| Operator::I64AtomicRmw32AddU { .. }
+		| Operator::I32AtomicRmwSub { .. }
+		| Operator::I64AtomicRmwSub { .. }
+		| Operator::I32AtomicRmw8SubU { .. }
+		| Operator::I32AtomicRmw16SubU { .. }
+		| Operator::I64AtomicRmw8SubU { .. }
+		| Operator::I64AtomicRmw16SubU { .. }
+		| Operator::I64AtomicRmw32SubU { .. }
+		| Operator::I32AtomicRmwAnd { .. }
+		| Operator::I64AtomicRmwAnd { .. }
+		| Operator::I32AtomicRmw8AndU { .. }
+		| Operator::I32AtomicRmw16AndU { .. }
+		| Operator::I64AtomicRmw8AndU { .. }
+		| Operator::I64AtomicRmw16AndU { .. }
+		| Operator::I64AtomicRmw32AndU { .. }
+		| Operator::I32AtomicRmwOr { .. }
+		| Operator::I64AtomicRmwOr { .. }
+		| Operator::I32AtomicRmw8OrU { .. }
+		| Operator::I32AtomicRmw16OrU { .. }
+		| Operator::I64AtomicRmw8OrU { .. }
+		| Operator::I64AtomicRmw16OrU { .. }
+		| Operator::I64AtomicRmw32OrU { .. }
+		| Operator::I32AtomicRmwXor { .. }
+		| Operator::I64AtomicRmwXor { .. }
+		| Operator::I32AtomicRmw8XorU { .. }
+		| Operator::I32AtomicRmw16XorU { .. }
+		| Operator::I64AtomicRmw8XorU { .. }
+		| Operator::I64AtomicRmw16XorU { .. }
+		| Operator::I64AtomicRmw32XorU { .. }
+		| Operator::I32AtomicRmwXchg { .. }
+		| Operator::I64AtomicRmwXchg { .. }
+		| Operator::I32AtomicRmw8XchgU { .. }
+		| Operator::I32AtomicRmw16XchgU { .. }
+		| Operator::I64AtomicRmw8XchgU { .. }
+		| Operator::I64AtomicRmw16XchgU { .. }
+		| Operator::I64AtomicRmw32XchgU { .. }
+		| Operator::I32AtomicRmwCmpxchg { .. }
+		| Operator::I64AtomicRmwCmpxchg { .. }
+		| Operator::I32AtomicRmw8CmpxchgU { .. }
+		| Operator::I32AtomicRmw16CmpxchgU { .. }
+		| Operator::I64AtomicRmw8CmpxchgU { .. }
+		| Operator::I64AtomicRmw16CmpxchgU { .. }
+		| Operator::I64AtomicRmw32CmpxchgU { .. } => Some(THREADS),
+		// the rest are the `V128`, `I8x16` to `I64x2`, `F32x4` and `F64x2` operators.
+		_ => Some(SIMD),
+	}
+}
+
+/// Walk the sections of the code, the errors are the malformed code.
+fn walk(code: &[u8], report: &mut ValidationReport) -> Result<(), String> {
+	let mut imported_funcs = 0;
+	let mut bodies = 0;
+	let mut exports = Vec::new();
+	for payload in Parser::new(0).parse_all(code) {
+		match payload.map_err(|e| e.to_string())? {
+			Payload::TypeSection(reader) => {
+				for (index, ty) in reader.into_iter().enumerate() {
+					let ty = match ty.map_err(|e| e.to_string())? {
+						TypeDef::Func(ty) => ty,
+						_ => continue,
+					};
+					if ty.returns.len() > 1 {
+						report.report(IssueKind::Feature, MULTI_VALUE, || {
+							format!("the type {} returns {} values", index, ty.returns.len())
+						});
+					}
+					for value in ty.params.iter().chain(ty.returns.iter()) {
+						if let Some(feature) = type_feature(*value) {
+							report.report(IssueKind::Feature, feature, || {
+								format!("the type {} uses {:?}", index, value)
+							});
+						}
+					}
+				}
+			}
+			Payload::ImportSection(reader) => {
+				for import in reader {
+					let import = import.map_err(|e| e.to_string())?;
+					let field = import.field.unwrap_or_default();
+					let name = format!("{}.{}", import.module, field);
+					match import.ty {
+						ImportSectionEntryType::Function(_) => {
+							imported_funcs += 1;
+							if !SEAL_MODULES.contains(&import.module) {
+								report.report(IssueKind::Import, &name, || {
+									format!(
+										"the functions are imported from {}",
+										SEAL_MODULES.join(", ")
+									)
+								});
+							}
+						}
+						ImportSectionEntryType::Memory(_) if name == "env.memory" => {}
+						ImportSectionEntryType::Memory(_) => {
+							report.report(IssueKind::Import, &name, || {
+								"the memory is imported as env.memory".to_string()
+							});
+						}
+						ty => report.report(IssueKind::Import, &name, || {
+							format!(
+								"only the functions and the memory are imported, not {:?}",
+								ty
+							)
+						}),
+					}
+				}
+			}
+			Payload::TableSection(reader) => {
+				if reader.get_count() > 1 {
+					report.report(IssueKind::Feature, REFERENCE_TYPES, || {
+						format!("the module defines {} tables", reader.get_count())
+					});
+				}
+				for table in reader {
+					let table = table.map_err(|e| e.to_string())?;
+					if table.element_type != Type::FuncRef {
+						report.report(IssueKind::Feature, REFERENCE_TYPES, || {
+							format!("the table of {:?}", table.element_type)
+						});
+					}
+				}
+			}
+			Payload::MemorySection(reader) => {
+				report.report(IssueKind::Section, "memory", || {
+					format!(
+						"the module defines {} memories, while the memory is imported as env.memory",
+						reader.get_count()
+					)
+				});
+			}
+			Payload::GlobalSection(reader) => {
+				for (index, global) in reader.into_iter().enumerate() {
+					let ty = global.map_err(|e| e.to_string())?.ty.content_type;
+					if let Some(feature) = type_feature(ty) {
+						report.report(IssueKind::Feature, feature, || {
+							format!("the global {} is {:?}", index, ty)
+						});
+					}
+				}
+			}
+			Payload::ExportSection(reader) => {
+				for export in reader {
+					let export = export.map_err(|e| e.to_string())?;
+					if export.kind == ExternalKind::Function {
+						exports.push(export.field);
+					}
+				}
+			}
+			Payload::DataCountSection { .. } => {
+				report.report(IssueKind::Feature, BULK_MEMORY, || {
+					"the module has the data count section".to_string()
+				});
+			}
+			Payload::CodeSectionEntry(body) => {
+				let function = imported_funcs + bodies;
+				bodies += 1;
+				let mut locals = body.get_locals_reader().map_err(|e| e.to_string())?;
+				for _ in 0..locals.get_count() {
+					let (_, ty) = locals.read().map_err(|e| e.to_string())?;
+					if let Some(feature) = type_feature(ty) {
+						report.report(IssueKind::Feature, feature, || {
+							format!("the locals of the function {} are {:?}", function, ty)
+						});
+					}
+				}
+				let mut reader = body.get_operators_reader().map_err(|e| e.to_string())?;
+				while !reader.eof() {
+					let (operator, offset) =
+						reader.read_with_offset().map_err(|e| e.to_string())?;
+					if let Some(feature) = operator_feature(&operator) {
+						report.report(IssueKind::Feature, feature, || {
+							format!(
+								"{:?} in the function {} at offset {:#x}",
+								operator, function, offset
+							)
+						});
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	for export in EXPORTS {
+		if !exports.contains(export) {
+			report.report(IssueKind::Export, export, || {
+				"the function is called by pallet-contracts".to_string()
+			});
+		}
+	}
+	Ok(())
+}
+
+/// Validate the contract code before it is deployed.
+pub fn validate(code: &[u8]) -> ValidationReport {
+	let mut report = ValidationReport::default();
+	if let Err(e) = walk(code, &mut report) {
+		report.report(IssueKind::Malformed, "module", || e);
+	}
+	if let Err(Error::Trap(trap)) = limits::check_module(code) {
+		report.report(IssueKind::Limit, &format!("{:?}", trap.code), || {
+			trap.trace.join("\n")
+		});
+	}
+	// what is not found by the walk, e.g. the mismatched types.
+	if report.is_valid() {
+		if let Err(e) = wasmparser::validate(code) {
+			report.report(IssueKind::Malformed, "module", || e.to_string());
+		}
+	}
+	report
+}
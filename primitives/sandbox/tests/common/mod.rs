@@ -0,0 +1,124 @@
+//! The helpers shared by the tests.
+use gimli::{
+	write::{Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections},
+	Encoding, Format, LineEncoding,
+};
+use wasmparser::{Parser, Payload};
+
+fn leb128(out: &mut Vec<u8>, mut n: u64) {
+	loop {
+		let byte = (n & 0x7f) as u8;
+		n >>= 7;
+		if n == 0 {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+	let mut content = Vec::new();
+	leb128(&mut content, name.len() as u64);
+	content.extend_from_slice(name.as_bytes());
+	content.extend_from_slice(data);
+	module.push(0);
+	leb128(module, content.len() as u64);
+	module.extend_from_slice(&content);
+}
+
+/// Append the DWARF sections to `code`, which map the `k`th instruction of the `i`th defined
+/// function, named `names[i]`, to the line `lines[i] + k` of `/src/lib.rs`.
+///
+/// The addresses in the DWARF of wasm are the offsets in the content of the code section.
+pub fn with_dwarf(code: &[u8], names: &[&str], lines: &[u64]) -> Vec<u8> {
+	let mut section = 0;
+	// the range and the offsets of the instructions of the bodies.
+	let mut bodies = Vec::new();
+	for payload in Parser::new(0).parse_all(code) {
+		match payload.unwrap() {
+			Payload::CodeSectionStart { range, .. } => section = range.start,
+			Payload::CodeSectionEntry(body) => {
+				let range = body.range();
+				let mut reader = body.get_operators_reader().unwrap();
+				let mut offsets = Vec::new();
+				while !reader.eof() {
+					offsets.push((reader.read_with_offset().unwrap().1 - section) as u64);
+				}
+				let (start, end) = ((range.start - section) as u64, (range.end - section) as u64);
+				bodies.push((start, end, offsets));
+			}
+			_ => {}
+		}
+	}
+	let end = bodies.last().expect("the module has no defined function").1;
+
+	let encoding = Encoding {
+		format: Format::Dwarf32,
+		version: 4,
+		address_size: 4,
+	};
+	let mut program = LineProgram::new(
+		encoding,
+		LineEncoding::default(),
+		LineString::String(b"/src".to_vec()),
+		LineString::String(b"lib.rs".to_vec()),
+		None,
+	);
+	let dir = program.default_directory();
+	let file = program.add_file(LineString::String(b"lib.rs".to_vec()), dir, None);
+	program.begin_sequence(Some(Address::Constant(0)));
+	for ((start, _, offsets), line) in bodies.iter().zip(lines) {
+		let mut row = |address: u64, line: u64| {
+			program.row().address_offset = address;
+			program.row().file = file;
+			program.row().line = line;
+			program.generate_row();
+		};
+		// the locals, then the instructions.
+		row(*start, *line);
+		for (k, offset) in offsets.iter().enumerate() {
+			row(*offset, line + k as u64);
+		}
+	}
+	program.end_sequence(end);
+
+	let mut dwarf = DwarfUnit::new(encoding);
+	dwarf.unit.line_program = program;
+	let root = dwarf.unit.root();
+	// wasmtime only transforms the names in the string section.
+	let name = dwarf.strings.add("lib.rs");
+	let comp_dir = dwarf.strings.add("/src");
+	let unit = dwarf.unit.get_mut(root);
+	unit.set(gimli::DW_AT_name, AttributeValue::StringRef(name));
+	unit.set(gimli::DW_AT_comp_dir, AttributeValue::StringRef(comp_dir));
+	unit.set(
+		gimli::DW_AT_low_pc,
+		AttributeValue::Address(Address::Constant(0)),
+	);
+	unit.set(gimli::DW_AT_high_pc, AttributeValue::Udata(end));
+	for ((start, end, _), name) in bodies.iter().zip(names) {
+		let name = dwarf.strings.add(*name);
+		let id = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+		let subprogram = dwarf.unit.get_mut(id);
+		subprogram.set(gimli::DW_AT_name, AttributeValue::StringRef(name));
+		subprogram.set(
+			gimli::DW_AT_low_pc,
+			AttributeValue::Address(Address::Constant(*start)),
+		);
+		subprogram.set(gimli::DW_AT_high_pc, AttributeValue::Udata(end - start));
+	}
+
+	let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+	dwarf.write(&mut sections).unwrap();
+	let mut module = code.to_vec();
+	sections
+		.for_each(|id, data| {
+			if !data.slice().is_empty() {
+				custom_section(&mut module, id.name(), data.slice());
+			}
+			Ok::<_, ()>(())
+		})
+		.unwrap();
+	module
+}
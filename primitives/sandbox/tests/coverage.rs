@@ -0,0 +1,308 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
+
+use codec::Decode;
+use ep_extensions::{ContractTracingDb, ContractTracingDbExt};
+use ep_sandbox::{
+	CodeCoverage, EnvironmentDefinitionBuilder, Error, HostError, Instance, Memory, ReturnValue,
+	SandboxExecutor, SandboxLimits, Value,
+};
+use parking_lot::{const_mutex, MutexGuard};
+
+mod common;
+
+/// The coverage mode is global, thus the tests which enable it are serialized.
+static COVERAGE_MODE: parking_lot::Mutex<()> = const_mutex(());
+
+/// Enable the coverage mode until the guard is dropped.
+struct CoverageMode(MutexGuard<'static, ()>);
+
+impl CoverageMode {
+	fn enable() -> Self {
+		let guard = COVERAGE_MODE.lock();
+		ep_sandbox::set_coverage(true);
+		CoverageMode(guard)
+	}
+}
+
+impl Drop for CoverageMode {
+	fn drop(&mut self) {
+		ep_sandbox::set_coverage(false);
+	}
+}
+
+/// The coverages by code hash.
+#[derive(Clone, Default)]
+struct CoverageDb(Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>);
+
+impl ContractTracingDb for CoverageDb {
+	fn set_tracing(&mut self, _number: u32, _index: u32, _tracing: String) {}
+	fn set_trap_snapshots(&mut self, _number: u32, _index: u32, _snapshots: Vec<Vec<u8>>) {}
+	fn coverage(&self, code_hash: &[u8]) -> Option<Vec<u8>> {
+		self.0.lock().unwrap().get(code_hash).cloned()
+	}
+	fn set_coverage(&mut self, code_hash: &[u8], coverage: Vec<u8>) {
+		self.0.lock().unwrap().insert(code_hash.to_vec(), coverage);
+	}
+}
+
+#[test]
+fn blocks_are_aggregated_per_code_hash() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $square (param i32) (result i32)
+				(i32.mul (local.get 0) (local.get 0))
+			)
+			(func $call (export "call") (param i32) (result i32)
+				(if (result i32) (local.get 0)
+					(then (call $square (local.get 0)))
+					(else (i32.const 0))
+				)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	let code_hash = sp_core::hashing::blake2_256(&code);
+
+	let _coverage = CoverageMode::enable();
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let db = CoverageDb::default();
+		let mut ext = sp_io::TestExternalities::default();
+		ext.register_extension(ContractTracingDbExt::new(db.clone()));
+		ext.execute_with(|| {
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+			let mut instance =
+				Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+			for _ in 0..2 {
+				let result = instance.invoke("call", &[Value::I32(3)], &mut ()).unwrap();
+				assert_eq!(result, ReturnValue::Value(Value::I32(9)));
+			}
+		});
+
+		let coverage = db.coverage(&code_hash).expect("the coverage is recorded");
+		let coverage = CodeCoverage::decode(&mut &coverage[..]).unwrap();
+		assert_eq!(coverage.code_hash, code_hash.into());
+		assert_eq!(coverage.invocations, 2);
+		assert_eq!(
+			coverage.functions,
+			vec![(0, "square".to_string()), (1, "call".to_string())]
+		);
+		// `square`, then `call` split by `if`, `call`, `else` and `end`.
+		assert_eq!(
+			coverage
+				.blocks
+				.iter()
+				.map(|block| (block.function, block.hits))
+				.collect::<Vec<_>>(),
+			vec![(0, 2), (1, 2), (1, 2), (1, 2), (1, 0), (1, 2)]
+		);
+		assert_eq!(coverage.covered(), 5);
+
+		// the code has no debug info, every block is a line of the pseudo file.
+		let lcov = coverage.lcov();
+		assert!(lcov.starts_with(&format!(
+			"TN:{:?}\nSF:{:?}.wasm\n",
+			coverage.code_hash, coverage.code_hash
+		)));
+		assert!(lcov.contains("FN:2,call\nFN:1,square\nFNDA:2,call\nFNDA:2,square\nFNF:2\nFNH:2\n"));
+		assert!(lcov.contains("DA:4,2\nDA:5,0\nDA:6,2\nLF:6\nLH:5\nend_of_record\n"));
+	}
+}
+
+fn gas(_e: &mut (), _args: &[Value]) -> Result<ReturnValue, HostError> {
+	Ok(ReturnValue::Unit)
+}
+
+#[test]
+fn blocks_are_mapped_to_the_source_by_the_pristine_code() {
+	let pristine = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $square (param i32) (result i32)
+				(i32.mul (local.get 0) (local.get 0))
+			)
+			(func $call (export "call") (param i32) (result i32)
+				(if (result i32) (local.get 0)
+					(then (call $square (local.get 0)))
+					(else (i32.const 0))
+				)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	// the instructions of `square` are at the lines 3 to 6, and the ones of `call` 10 to 17.
+	let pristine = common::with_dwarf(&pristine, &["square", "call"], &[3, 10]);
+	// instrumented by pallet-contracts, with the gas metering and the stack height limiter.
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "seal0" "gas" (func $gas (param i32)))
+			(import "env" "memory" (memory 1 1))
+			(global (mut i32) (i32.const 0))
+			(func $square (param i32) (result i32)
+				i32.const 3
+				call $gas
+				local.get 0
+				local.get 0
+				i32.mul
+			)
+			(func $call (export "call") (param i32) (result i32)
+				i32.const 2
+				call $gas
+				local.get 0
+				if (result i32)
+					i32.const 3
+					call $gas
+					local.get 0
+					global.get 0
+					i32.const 1
+					i32.add
+					global.set 0
+					global.get 0
+					i32.const 1024
+					i32.gt_u
+					if
+						unreachable
+					end
+					call $square
+					global.get 0
+					i32.const 1
+					i32.sub
+					global.set 0
+				else
+					i32.const 1
+					call $gas
+					i32.const 0
+				end
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	let code_hash = [7u8; 32];
+
+	let _coverage = CoverageMode::enable();
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let db = CoverageDb::default();
+		let mut ext = sp_io::TestExternalities::default();
+		ext.register_extension(ContractTracingDbExt::new(db.clone()));
+		ext.execute_with(|| {
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+			env_builder.add_host_func("seal0", "gas", gas);
+			ep_sandbox::set_pristine_code(code_hash.into(), pristine.clone());
+			let mut instance =
+				Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+			for _ in 0..2 {
+				let result = instance.invoke("call", &[Value::I32(3)], &mut ()).unwrap();
+				assert_eq!(result, ReturnValue::Value(Value::I32(9)));
+			}
+		});
+
+		// keyed by the code hash of pallet-contracts.
+		let coverage = db.coverage(&code_hash).expect("the coverage is recorded");
+		let coverage = CodeCoverage::decode(&mut &coverage[..]).unwrap();
+		assert_eq!(coverage.invocations, 2);
+		assert_eq!(coverage.files, vec!["/src/lib.rs".to_string()]);
+		// the blocks which only have the injected instructions are not in the source.
+		assert_eq!(
+			coverage
+				.blocks
+				.iter()
+				.map(|block| (block.function, block.lines.len(), block.hits))
+				.collect::<Vec<_>>(),
+			vec![
+				(1, 0, 2),
+				(1, 4, 2),
+				(2, 0, 2),
+				(2, 2, 2),
+				(2, 0, 2),
+				(2, 1, 2),
+				(2, 0, 0),
+				(2, 0, 0),
+				(2, 1, 2),
+				(2, 1, 2),
+				(2, 0, 0),
+				(2, 2, 0),
+				(2, 1, 2)
+			]
+		);
+
+		let lcov = coverage.lcov();
+		assert!(lcov.contains("SF:/src/lib.rs\nFN:10,call\nFN:3,square\n"));
+		assert!(lcov.contains(concat!(
+			"DA:3,2\nDA:4,2\nDA:5,2\nDA:6,2\n",
+			"DA:10,2\nDA:11,2\nDA:12,2\nDA:13,2\nDA:14,2\nDA:15,0\nDA:16,0\nDA:17,2\n",
+			"LF:12\nLH:10\nend_of_record\n"
+		)));
+	}
+}
+
+#[test]
+fn traps_are_mapped_to_the_source_through_the_instrumentation() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $inner
+				unreachable
+			)
+			(func $outer
+				call $inner
+			)
+			(func (export "call")
+				call $outer
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	let code = common::with_dwarf(&code, &["inner", "outer", "call"], &[3, 7, 11]);
+
+	// instrumented by the coverage, then by the stack height limiter.
+	let _coverage = CoverageMode::enable();
+	ep_sandbox::set_limits(SandboxLimits {
+		max_stack_height: Some(16),
+		..Default::default()
+	});
+	let memory = Memory::new_with_executor(SandboxExecutor::Jit, 1, Some(1)).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	env_builder.add_memory("env", "memory", memory);
+	let mut instance =
+		Instance::new_with_executor(SandboxExecutor::Jit, &code, &env_builder, &mut ()).unwrap();
+	let trap = match instance.invoke("call", &[], &mut ()) {
+		Err(Error::Trap(trap)) => trap,
+		_ => panic!("the call should trap"),
+	};
+	ep_sandbox::set_limits(SandboxLimits::default());
+
+	// the indices of the original code, and the lines of the original instructions.
+	for frame in &[
+		"inner[0] at /src/lib.rs:3",
+		"outer[1] at /src/lib.rs:7",
+		"call[2] at /src/lib.rs:11",
+	] {
+		assert!(
+			trap.trace.iter().any(|f| f.starts_with(frame)),
+			"{}: {:?}",
+			frame,
+			trap.trace
+		);
+	}
+}
@@ -0,0 +1,90 @@
+use std::{
+	sync::{mpsc, Mutex},
+	time::Duration,
+};
+
+use ep_sandbox::{
+	debugger::{self, Breakpoint, DebugEvent, FunctionRef, Paused},
+	serde_opt_wasm_returnvalue::SerdeValue,
+	EnvironmentDefinitionBuilder, Instance, Memory, ReturnValue, SandboxExecutor, Value,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn paused(events: &mpsc::Receiver<DebugEvent>) -> Paused {
+	match events.recv_timeout(TIMEOUT).unwrap() {
+		DebugEvent::Paused(paused) => paused,
+		event => panic!("Unexpected event: {:?}", event),
+	}
+}
+
+fn resumed(events: &mpsc::Receiver<DebugEvent>) {
+	assert!(matches!(
+		events.recv_timeout(TIMEOUT).unwrap(),
+		DebugEvent::Resumed
+	));
+}
+
+#[test]
+fn dry_run_is_paused_at_breakpoints_and_stepped() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $call (export "call") (param i32) (result i32)
+				(local i64)
+				(i32.store (i32.const 8) (local.get 0))
+				(i32.add (local.get 0) (i32.const 1))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	let (tx, events) = mpsc::channel();
+	let tx = Mutex::new(tx);
+	let id = debugger::attach(move |event| {
+		let _ = tx.lock().unwrap().send(event.clone());
+	});
+	// pause after the store.
+	debugger::set_breakpoints(vec![Breakpoint {
+		function: FunctionRef::Name("call".into()),
+		pc: Some(3),
+	}]);
+
+	let execution = std::thread::spawn(move || {
+		debugger::debuggable(|| {
+			let executor = SandboxExecutor::Interpreter;
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+			let mut instance =
+				Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+			instance.invoke("call", &[Value::I32(41)], &mut ())
+		})
+	});
+
+	let location = paused(&events);
+	assert_eq!((location.function, location.pc), (0, 3));
+	assert_eq!(location.name.as_deref(), Some("call"));
+	assert_eq!(
+		location.locals,
+		vec![SerdeValue::I32(41), SerdeValue::I64(0)]
+	);
+	assert!(location.operands.is_empty());
+	assert_eq!(debugger::paused().map(|p| p.pc), Some(3));
+	assert_eq!(debugger::memory(8, 4).unwrap(), vec![41, 0, 0, 0]);
+
+	debugger::step().unwrap();
+	resumed(&events);
+	let location = paused(&events);
+	assert_eq!(location.pc, 4);
+	assert_eq!(location.operands, vec![SerdeValue::I32(41)]);
+
+	debugger::resume().unwrap();
+	resumed(&events);
+	let result = execution.join().unwrap();
+	assert_eq!(result.unwrap(), ReturnValue::Value(Value::I32(42)));
+	assert!(debugger::step().is_err());
+	debugger::detach(id);
+}
@@ -0,0 +1,69 @@
+use ep_extensions::SandboxDivergencesExt;
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, HostError, Instance, Memory, ReturnValue, SandboxExecutor, Value,
+};
+use sp_externalities::ExternalitiesExt;
+
+struct State {
+	memory: Memory,
+	calls: u32,
+}
+
+fn env_write(e: &mut State, args: &[Value]) -> Result<ReturnValue, HostError> {
+	let ptr = args[0].as_i32().ok_or(HostError)? as u32;
+	e.calls += 1;
+	e.memory.set(ptr, &[0x2a, 0, 0, 0]).map_err(|_| HostError)?;
+	Ok(ReturnValue::Value(Value::I32(e.calls as i32)))
+}
+
+fn divergences() -> Vec<Vec<u8>> {
+	sp_externalities::with_externalities(|ext| {
+		ext.extension::<SandboxDivergencesExt>()
+			.map(|divergences| divergences.0.clone())
+	})
+	.flatten()
+	.unwrap_or_default()
+}
+
+#[test]
+fn host_calls_are_replayed_to_the_secondary_executor() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(import "env" "write" (func $write (param i32) (result i32)))
+			(func (export "call") (result i32)
+				(drop (call $write (i32.const 8)))
+				(i32.add
+					(i32.load (i32.const 8))
+					(call $write (i32.const 16))
+				)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	ep_sandbox::set_differential(true).unwrap();
+	let mut ext = sp_io::TestExternalities::default();
+	ext.register_extension(SandboxDivergencesExt::default());
+	ext.execute_with(|| {
+		for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit].iter().cloned() {
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut state = State {
+				memory: memory.clone(),
+				calls: 0,
+			};
+			let mut env_builder = EnvironmentDefinitionBuilder::new();
+			env_builder.add_memory("env", "memory", memory);
+			env_builder.add_host_func("env", "write", env_write);
+
+			let mut instance = Instance::new(&code, &env_builder, &mut state).unwrap();
+			let result = instance.invoke("call", &[], &mut state).unwrap();
+			assert_eq!(result, ReturnValue::Value(Value::I32(42 + 2)));
+			// the secondary executor does not call the host.
+			assert_eq!(state.calls, 2);
+		}
+		assert!(divergences().is_empty());
+	});
+}
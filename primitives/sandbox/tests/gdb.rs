@@ -0,0 +1,67 @@
+use std::process::{Child, Command};
+
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Instance, Memory, ReturnValue, SandboxExecutor, Value,
+};
+
+const PORT: u16 = 2345;
+
+/// Spawn `gdb`, which connects to the server once it listens and detaches at once, which
+/// continues the process.
+///
+/// It is a child process which retries by itself, since every thread of the test is stopped
+/// while the server is attached.
+fn spawn_debugger() -> Child {
+	let gdb = format!(
+		"gdb -batch -nx -ex 'target remote 127.0.0.1:{}' -ex detach",
+		PORT
+	);
+	let script = format!(
+		"for _ in $(seq 300); do {} 2>/dev/null | grep -q 'Remote debugging using' && exit 0; sleep 0.1; done; exit 1",
+		gdb
+	);
+	Command::new("sh")
+		.args(&["-c", &script])
+		.spawn()
+		.expect("sh is installed")
+}
+
+// NOTE: requires `gdbserver` and `gdb` in PATH on Linux, and a `ptrace_scope` which allows the
+// tracer to attach, run by `cargo test -p ep-sandbox --test gdb -- --ignored`.
+#[test]
+#[ignore]
+fn debug_port_pauses_the_invocation_until_attached() {
+	ep_sandbox::set_debug_port(Some(PORT)).unwrap();
+
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func (export "add") (param i32 i32) (result i32)
+				(i32.add (local.get 0) (local.get 1))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	let memory = Memory::new_with_executor(SandboxExecutor::Jit, 1, Some(1)).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	env_builder.add_memory("env", "memory", memory);
+	let mut instance =
+		Instance::new_with_executor(SandboxExecutor::Jit, &code, &env_builder, &mut ()).unwrap();
+
+	// the invocation is not a dry-run, e.g. a call in an extrinsic, it waits as well.
+	let mut debugger = spawn_debugger();
+	assert_eq!(
+		instance
+			.invoke("add", &[Value::I32(1), Value::I32(2)], &mut ())
+			.unwrap(),
+		ReturnValue::Value(Value::I32(3))
+	);
+	assert!(
+		debugger.wait().unwrap().success(),
+		"the debugger could not connect"
+	);
+
+	ep_sandbox::set_debug_port(None).unwrap();
+}
@@ -0,0 +1,67 @@
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, HostError, HostFailure, Instance, ReturnValue,
+	SandboxExecutor, TrapCode, Value,
+};
+
+fn set_storage(_e: &mut (), _args: &[Value]) -> Result<ReturnValue, HostError> {
+	Err(ep_sandbox::host_error_with_payload(
+		"value too large",
+		vec![1, 2, 3],
+	))
+}
+
+fn terminate(_e: &mut (), _args: &[Value]) -> Result<ReturnValue, HostError> {
+	Err(HostError)
+}
+
+#[test]
+fn host_errors_are_carried_by_traps() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "seal0" "seal_set_storage" (func $seal_set_storage))
+			(import "seal0" "seal_terminate" (func $seal_terminate))
+			(func (export "call") (call $seal_set_storage))
+			(func (export "deploy") (call $seal_terminate))
+		)
+		"#,
+	)
+	.unwrap();
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let mut env_builder = EnvironmentDefinitionBuilder::new();
+		env_builder.add_host_func("seal0", "seal_set_storage", set_storage);
+		env_builder.add_host_func("seal0", "seal_terminate", terminate);
+		let mut instance =
+			Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+
+		match instance.invoke("call", &[], &mut ()) {
+			Err(Error::Trap(trap)) => {
+				assert_eq!(trap.code, TrapCode::HostError, "{}", executor);
+				assert_eq!(
+					trap.host_error,
+					Some(HostFailure {
+						import: "seal0.seal_set_storage".into(),
+						reason: "value too large".into(),
+						payload: Some(vec![1, 2, 3]),
+					})
+				);
+				assert_eq!(trap.trace[0], "seal_set_storage: value too large");
+			}
+			r => panic!("{}: unexpected result {:?}", executor, r),
+		}
+
+		match instance.invoke("deploy", &[], &mut ()) {
+			Err(Error::Trap(trap)) => {
+				let failure = trap.host_error.expect("the failure of seal_terminate");
+				assert_eq!(failure.import, "seal0.seal_terminate");
+				assert_eq!(failure.reason, "the host function failed");
+				assert_eq!(failure.payload, None);
+			}
+			r => panic!("{}: unexpected result {:?}", executor, r),
+		}
+	}
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, Instance, Interruption, Memory, ReturnValue,
+	SandboxExecutor, TrapCode, Value,
+};
+
+#[test]
+fn infinite_loops_are_interrupted_with_backtrace() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $spin
+				(loop $l (br $l))
+			)
+			(func (export "call")
+				(call $spin)
+			)
+			(func (export "add") (param i32 i32) (result i32)
+				(i32.add (local.get 0) (local.get 1))
+			)
+			(func (export "count") (param i32) (result i32)
+				(loop $l
+					(local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+					(br_if $l (local.get 0))
+				)
+				(local.get 0)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	// the fuel is metered by the engine, which is created by the first jit memory.
+	ep_sandbox::set_interruption(Interruption {
+		fuel: Some(100_000),
+		timeout: None,
+	})
+	.unwrap();
+	let memory = Memory::new_with_executor(SandboxExecutor::Jit, 1, Some(1)).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	env_builder.add_memory("env", "memory", memory);
+	let mut instance =
+		Instance::new_with_executor(SandboxExecutor::Jit, &code, &env_builder, &mut ()).unwrap();
+
+	let mut assert_interrupted = |instance: &mut Instance<()>| {
+		match instance.invoke("call", &[], &mut ()) {
+			Err(Error::Trap(trap)) => {
+				assert_eq!(trap.code, TrapCode::Interrupt);
+				// the innermost frame is where the execution is stopped.
+				let spin = trap
+					.trace
+					.iter()
+					.position(|frame| frame.starts_with("spin["));
+				let call = trap.trace.iter().position(|frame| frame.contains("[1]"));
+				assert!(spin.is_some() && spin < call, "{:?}", trap.trace);
+			}
+			_ => panic!("the invocation is not interrupted"),
+		}
+		// the fuel is topped up for the next invocation.
+		assert_eq!(
+			instance
+				.invoke("add", &[Value::I32(1), Value::I32(2)], &mut ())
+				.unwrap(),
+			ReturnValue::Value(Value::I32(3))
+		);
+	};
+	assert_interrupted(&mut instance);
+
+	ep_sandbox::set_interruption(Interruption {
+		fuel: None,
+		timeout: Some(Duration::from_millis(100)),
+	})
+	.unwrap();
+	ep_sandbox::debugger::debuggable(|| assert_interrupted(&mut instance));
+
+	ep_sandbox::set_interruption(Interruption {
+		fuel: None,
+		timeout: Some(Duration::from_millis(1)),
+	})
+	.unwrap();
+	// the wall-clock budget is not applied out of the dry-runs, e.g. the block production.
+	assert_eq!(
+		instance
+			.invoke("count", &[Value::I32(100_000_000)], &mut ())
+			.unwrap(),
+		ReturnValue::Value(Value::I32(0))
+	);
+	// nor an interrupt which is late for the invocation traps the next one.
+	for _ in 0..100 {
+		let result = ep_sandbox::debugger::debuggable(|| {
+			instance.invoke("count", &[Value::I32(100_000)], &mut ())
+		});
+		assert!(
+			matches!(result, Ok(_) | Err(Error::Trap(_))),
+			"{:?}",
+			result.err()
+		);
+		assert_eq!(
+			instance
+				.invoke("add", &[Value::I32(1), Value::I32(2)], &mut ())
+				.unwrap(),
+			ReturnValue::Value(Value::I32(3))
+		);
+	}
+
+	ep_sandbox::set_interruption(Interruption::default()).unwrap();
+}
@@ -0,0 +1,97 @@
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, ExportType, ExternType, FuncType, Instance, Memory,
+	SandboxExecutor, ValType, Value,
+};
+
+#[test]
+fn exports_memories_and_tables_are_inspected() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1))
+			(global $counter (mut i32) (i32.const 7))
+			(table $table 2 4 funcref)
+			(func $add (param i32 i32) (result i32)
+				(i32.add (local.get 0) (local.get 1))
+			)
+			(elem (i32.const 0) $add)
+			(data (i32.const 16) "hello")
+			(export "memory" (memory 0))
+			(export "counter" (global $counter))
+			(export "table" (table $table))
+			(export "add" (func $add))
+		)
+		"#,
+	)
+	.unwrap();
+	let add = FuncType {
+		params: vec![ValType::I32, ValType::I32],
+		results: vec![ValType::I32],
+	};
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let memory = Memory::new_with_executor(executor, 1, None).unwrap();
+		assert_eq!(memory.size(), 65536);
+		let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+		env_builder.add_memory("env", "memory", memory);
+		let instance = Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+
+		assert_eq!(
+			instance.exports(),
+			vec![
+				ExportType {
+					name: "memory".into(),
+					ty: ExternType::Memory {
+						initial: 1,
+						maximum: None
+					},
+				},
+				ExportType {
+					name: "counter".into(),
+					ty: ExternType::Global {
+						ty: ValType::I32,
+						mutable: true
+					},
+				},
+				ExportType {
+					name: "table".into(),
+					ty: ExternType::Table {
+						element: ValType::FuncRef,
+						initial: 2,
+						maximum: Some(4)
+					},
+				},
+				ExportType {
+					name: "add".into(),
+					ty: ExternType::Func(add.clone()),
+				},
+			]
+		);
+		assert_eq!(instance.get_global_val("counter"), Some(Value::I32(7)));
+
+		assert_eq!(instance.memory_size("memory"), Some(1));
+		assert_eq!(instance.memory_size("add"), None);
+		let mut buf = [0; 5];
+		instance.read_memory("memory", 16, &mut buf).unwrap();
+		assert_eq!(&buf, b"hello");
+		assert!(matches!(
+			instance.read_memory("memory", 65534, &mut buf),
+			Err(Error::OutOfBounds)
+		));
+		assert!(matches!(
+			instance.read_memory("counter", 0, &mut buf),
+			Err(Error::Execution)
+		));
+
+		assert_eq!(instance.table_size("table"), Some(2));
+		assert_eq!(instance.table_get("table", 0).unwrap(), Some(add.clone()));
+		assert_eq!(instance.table_get("table", 1).unwrap(), None);
+		assert!(matches!(
+			instance.table_get("table", 2),
+			Err(Error::OutOfBounds)
+		));
+	}
+}
@@ -0,0 +1,106 @@
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, Instance, Memory, ReturnValue, SandboxExecutor,
+	SandboxLimits, TrapCode, Value,
+};
+
+fn trap_code<T>(result: Result<T, Error>) -> Option<TrapCode> {
+	match result {
+		Err(Error::Trap(trap)) => Some(trap.code),
+		_ => None,
+	}
+}
+
+fn instantiate(
+	executor: SandboxExecutor,
+	code: &[u8],
+	memory: Memory,
+) -> Result<Instance<()>, Error> {
+	let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	env_builder.add_memory("env", "memory", memory);
+	Instance::new_with_executor(executor, code, &env_builder, &mut ())
+}
+
+#[test]
+fn violations_trap_with_dedicated_codes() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1))
+			(table 4 funcref)
+			(func $rec (export "rec") (param i32) (result i32)
+				(if (result i32) (local.get 0)
+					(then (call $rec (i32.sub (local.get 0) (i32.const 1))))
+					(else (i32.const 0))
+				)
+			)
+			(func (export "grow") (param i32) (result i32)
+				(memory.grow (local.get 0))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+		.iter()
+		.cloned()
+	{
+		let memory = || Memory::new_with_executor(executor, 1, None).unwrap();
+
+		ep_sandbox::set_limits(SandboxLimits {
+			max_module_size: Some(code.len() as u32 - 1),
+			..Default::default()
+		});
+		assert_eq!(
+			trap_code(instantiate(executor, &code, memory())),
+			Some(TrapCode::ModuleSizeExceeded)
+		);
+
+		ep_sandbox::set_limits(SandboxLimits {
+			max_table_size: Some(2),
+			..Default::default()
+		});
+		assert_eq!(
+			trap_code(instantiate(executor, &code, memory())),
+			Some(TrapCode::TableLimitExceeded)
+		);
+
+		ep_sandbox::set_limits(SandboxLimits {
+			max_memory_pages: Some(8),
+			..Default::default()
+		});
+		assert_eq!(
+			trap_code(Memory::new_with_executor(executor, 1, Some(16))),
+			Some(TrapCode::MemoryLimitExceeded)
+		);
+		// the memory without a maximum grows up to the limit.
+		let mut instance = instantiate(executor, &code, memory()).unwrap();
+		let grow = |instance: &mut Instance<()>, pages: i32| {
+			instance
+				.invoke("grow", &[Value::I32(pages)], &mut ())
+				.unwrap()
+		};
+		assert_eq!(grow(&mut instance, 7), ReturnValue::Value(Value::I32(1)));
+		assert_eq!(grow(&mut instance, 1), ReturnValue::Value(Value::I32(-1)));
+
+		ep_sandbox::set_limits(SandboxLimits {
+			max_stack_height: Some(10),
+			..Default::default()
+		});
+		let mut instance = instantiate(executor, &code, memory()).unwrap();
+		assert_eq!(
+			instance.invoke("rec", &[Value::I32(5)], &mut ()).unwrap(),
+			ReturnValue::Value(Value::I32(0))
+		);
+		assert_eq!(
+			trap_code(instance.invoke("rec", &[Value::I32(20)], &mut ())),
+			Some(TrapCode::StackLimitExceeded)
+		);
+		// the depth is restored for the next invocation.
+		assert_eq!(
+			instance.invoke("rec", &[Value::I32(5)], &mut ()).unwrap(),
+			ReturnValue::Value(Value::I32(0))
+		);
+	}
+	ep_sandbox::set_limits(SandboxLimits::default());
+}
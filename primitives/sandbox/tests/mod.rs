@@ -1,5 +1,12 @@
 use assert_matches::assert_matches;
-use ep_sandbox::{EnvironmentDefinitionBuilder, Error, HostError, Instance, ReturnValue, Value};
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, HostError, Instance, Memory, ReturnValue,
+	SandboxExecutor, TrapCode, Value,
+};
+
+mod common;
+
+const EXECUTORS: [SandboxExecutor; 2] = [SandboxExecutor::Interpreter, SandboxExecutor::Jit];
 
 fn execute_sandboxed(code: &[u8], args: &[Value]) -> Result<ReturnValue, HostError> {
 	struct State {
@@ -170,3 +177,123 @@ fn cant_return_unmatching_type() {
 	// But this fails since we imported a function that returns i32 as if it returned i64.
 	assert_matches!(instance.invoke("call", &[], &mut ()), Err(Error::Trap(_)));
 }
+
+#[test]
+fn trap_backtrace_names_frames() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(func $inner
+				unreachable
+			)
+			(func $outer
+				call $inner
+			)
+			(func (export "call")
+				call $outer
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	for executor in EXECUTORS.iter().cloned() {
+		let env_builder = EnvironmentDefinitionBuilder::<()>::new();
+		let mut instance =
+			Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+		let trap = match instance.invoke("call", &[], &mut ()) {
+			Err(Error::Trap(trap)) => trap,
+			_ => panic!("the call should trap in {}", executor),
+		};
+		assert_eq!(trap.code, TrapCode::UnreachableCodeReached);
+
+		// the innermost frame is the first one.
+		let inner = trap.trace.iter().position(|f| f.starts_with("inner["));
+		let outer = trap.trace.iter().position(|f| f.starts_with("outer["));
+		assert!(inner.unwrap() < outer.unwrap(), "{}: {:?}", executor, trap.trace);
+	}
+}
+
+// the jit executor does not read the DWARF on aarch64-apple-darwin.
+#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+#[test]
+fn trap_backtrace_maps_frames_to_source() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(func $inner
+				unreachable
+			)
+			(func $outer
+				call $inner
+			)
+			(func (export "call")
+				call $outer
+			)
+		)
+		"#,
+	)
+	.unwrap();
+	let code = common::with_dwarf(&code, &["inner", "outer", "call"], &[3, 7, 11]);
+
+	let env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	let mut instance =
+		Instance::new_with_executor(SandboxExecutor::Jit, &code, &env_builder, &mut ()).unwrap();
+	let trap = match instance.invoke("call", &[], &mut ()) {
+		Err(Error::Trap(trap)) => trap,
+		_ => panic!("the call should trap"),
+	};
+
+	// the column is appended if it is in the line table.
+	for frame in &[
+		"inner[0] at /src/lib.rs:3",
+		"outer[1] at /src/lib.rs:7",
+		"call[2] at /src/lib.rs:11",
+	] {
+		assert!(
+			trap.trace.iter().any(|f| f.starts_with(frame)),
+			"{}: {:?}",
+			frame,
+			trap.trace
+		);
+	}
+}
+
+#[test]
+fn instance_follows_memory_executor() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func (export "call") (result i32)
+				(i32.load (i32.const 0))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	for executor in EXECUTORS.iter().cloned() {
+		let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+		memory.set(0, &42i32.to_le_bytes()).unwrap();
+		let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+		env_builder.add_memory("env", "memory", memory.clone());
+
+		let mut instance = Instance::new(&code, &env_builder, &mut ()).unwrap();
+		assert_eq!(instance.executor(), executor);
+		assert_eq!(
+			instance.invoke("call", &[], &mut ()).unwrap(),
+			ReturnValue::Value(Value::I32(42))
+		);
+	}
+
+	// the memory could not be shared between the backends.
+	let memory = Memory::new_with_executor(SandboxExecutor::Jit, 1, Some(1)).unwrap();
+	let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+	env_builder.add_memory("env", "memory", memory);
+	assert_matches!(
+		Instance::new_with_executor(SandboxExecutor::Interpreter, &code, &env_builder, &mut ())
+			.err(),
+		Some(Error::Module)
+	);
+}
@@ -0,0 +1,92 @@
+use ep_extensions::SandboxProfilesExt;
+use ep_sandbox::{
+	profiler::FunctionProfile, EnvironmentDefinitionBuilder, Instance, Memory, Profile,
+	ReturnValue, SandboxExecutor, Value,
+};
+use sp_externalities::ExternalitiesExt;
+
+fn profiles() -> Vec<Profile> {
+	sp_externalities::with_externalities(|ext| {
+		ext.extension::<SandboxProfilesExt>()
+			.map(|profiles| std::mem::take(&mut profiles.0))
+	})
+	.flatten()
+	.unwrap_or_default()
+	.iter()
+	.map(|profile| serde_json::from_slice(profile).unwrap())
+	.collect()
+}
+
+#[test]
+fn instructions_and_calls_are_counted_per_function() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func $square (param i32) (result i32)
+				(i32.mul (local.get 0) (local.get 0))
+			)
+			(func $call (export "call") (param i32) (result i32)
+				(i32.add
+					(call $square (local.get 0))
+					(call $square (i32.const 2))
+				)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	ep_sandbox::set_profiling(true);
+	let mut ext = sp_io::TestExternalities::default();
+	ext.register_extension(SandboxProfilesExt::default());
+	ext.execute_with(|| {
+		for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit]
+			.iter()
+			.cloned()
+		{
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+			let mut instance =
+				Instance::new_with_executor(executor, &code, &env_builder, &mut ()).unwrap();
+			let result = instance.invoke("call", &[Value::I32(3)], &mut ()).unwrap();
+			assert_eq!(result, ReturnValue::Value(Value::I32(13)));
+
+			let profiles = profiles();
+			if executor == SandboxExecutor::Jit {
+				// only the interpreter is profiled.
+				assert!(profiles.is_empty());
+				continue;
+			}
+			assert_eq!(
+				profiles,
+				vec![Profile {
+					depth: 1,
+					function: "call".into(),
+					instructions: 14,
+					functions: vec![
+						FunctionProfile {
+							index: 0,
+							name: "square".into(),
+							calls: 2,
+							instructions: 8,
+						},
+						FunctionProfile {
+							index: 1,
+							name: "call".into(),
+							calls: 1,
+							instructions: 6,
+						},
+					],
+					folded: vec!["call 6".into(), "call;square 8".into()],
+				}]
+			);
+
+			// the counters are reset for every invocation.
+			instance.invoke("call", &[Value::I32(3)], &mut ()).unwrap();
+			assert_eq!(profiles()[0].instructions, 14);
+		}
+	});
+	ep_sandbox::set_profiling(false);
+}
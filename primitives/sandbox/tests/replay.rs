@@ -0,0 +1,90 @@
+use ep_sandbox::{
+	replay, EnvironmentDefinitionBuilder, HostError, Instance, Memory, Recording, ReturnValue,
+	SandboxExecutor, Value,
+};
+
+const EXECUTORS: [SandboxExecutor; 2] = [SandboxExecutor::Interpreter, SandboxExecutor::Jit];
+
+struct State {
+	memory: Memory,
+	calls: u32,
+}
+
+fn env_write(e: &mut State, args: &[Value]) -> Result<ReturnValue, HostError> {
+	let ptr = args[0].as_i32().ok_or(HostError)? as u32;
+	e.calls += 1;
+	e.memory.set(ptr, &[0x2a, 0, 0, 0]).map_err(|_| HostError)?;
+	Ok(ReturnValue::Value(Value::I32(e.calls as i32)))
+}
+
+#[test]
+fn recorded_invocation_is_replayed_offline() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(import "env" "write" (func $write (param i32) (result i32)))
+			(func (export "call") (param i32) (result i32)
+				(drop (call $write (local.get 0)))
+				(i32.add
+					(i32.add (i32.load (local.get 0)) (i32.load (i32.const 4)))
+					(call $write (i32.const 16))
+				)
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	let dir = std::env::temp_dir().join(format!("ep-sandbox-replay-{}", std::process::id()));
+	ep_sandbox::set_recording(Some(dir.clone())).unwrap();
+	for executor in EXECUTORS.iter().cloned() {
+		let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+		// the memory before the invocation is recorded.
+		memory.set(4, &[1, 0, 0, 0]).unwrap();
+		let mut state = State {
+			memory: memory.clone(),
+			calls: 0,
+		};
+		let mut env_builder = EnvironmentDefinitionBuilder::new();
+		env_builder.add_memory("env", "memory", memory);
+		env_builder.add_host_func("env", "write", env_write);
+
+		let mut instance = Instance::new(&code, &env_builder, &mut state).unwrap();
+		let result = instance.invoke("call", &[Value::I32(8)], &mut state).unwrap();
+		assert_eq!(result, ReturnValue::Value(Value::I32(42 + 1 + 2)));
+	}
+	ep_sandbox::set_recording(None).unwrap();
+
+	let mut paths = std::fs::read_dir(&dir)
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.collect::<Vec<_>>();
+	paths.sort();
+	assert_eq!(paths.len(), EXECUTORS.len());
+	for (path, executor) in paths.iter().zip(EXECUTORS.iter().cloned()) {
+		let recording = Recording::load(path).unwrap();
+		assert_eq!(recording.executor, executor);
+		assert_eq!(recording.function, "call");
+		assert_eq!(recording.args, vec![Value::I32(8)]);
+		assert_eq!(recording.host_funcs, vec![("env".to_string(), "write".to_string())]);
+		assert_eq!(recording.calls.len(), 2);
+		assert_eq!(recording.calls[0].name, "env.write");
+		assert_eq!(recording.calls[0].args, vec![Value::I32(8)]);
+		assert_eq!(recording.calls[0].writes.len(), 1);
+		assert_eq!(recording.calls[0].writes[0].ptr, 8);
+
+		// the recording is reproduced by both backends, the host is never called.
+		for replayer in EXECUTORS.iter().cloned() {
+			let replayed = replay::replay(&recording, Some(replayer)).unwrap();
+			assert!(replayed.reproduced(&recording), "{:?}", replayed);
+		}
+
+		// another argument makes the module call the host differently.
+		let mut recording = recording;
+		recording.args = vec![Value::I32(12)];
+		let replayed = replay::replay(&recording, None).unwrap();
+		assert_eq!(replayed.mismatch.map(|(index, _, _)| index), Some(0));
+	}
+	std::fs::remove_dir_all(&dir).unwrap();
+}
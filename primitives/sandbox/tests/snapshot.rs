@@ -0,0 +1,132 @@
+use codec::Decode;
+use ep_extensions::TrapSnapshotsExt;
+use ep_sandbox::{
+	EnvironmentDefinitionBuilder, Error, FrameSnapshot, HostError, Instance, Memory, ReturnValue,
+	SandboxExecutor, TrapCode, TrapSnapshot, Value,
+};
+use sp_externalities::ExternalitiesExt;
+
+fn snapshots() -> Vec<TrapSnapshot> {
+	sp_externalities::with_externalities(|ext| {
+		ext.extension::<TrapSnapshotsExt>()
+			.map(|snapshots| std::mem::take(&mut snapshots.0))
+	})
+	.flatten()
+	.unwrap_or_default()
+	.iter()
+	.map(|snapshot| TrapSnapshot::decode(&mut &snapshot[..]).unwrap())
+	.collect()
+}
+
+#[test]
+fn trapped_instance_is_captured() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(global (export "__heap_base") i32 (i32.const 1024))
+			(func (export "call") (param i32)
+				(i32.store (i32.const 8) (local.get 0))
+				unreachable
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	ep_sandbox::set_trap_snapshot(true);
+	let mut ext = sp_io::TestExternalities::default();
+	ext.register_extension(TrapSnapshotsExt::default());
+	ext.execute_with(|| {
+		for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit].iter().cloned() {
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+
+			let mut instance = Instance::new(&code, &env_builder, &mut ()).unwrap();
+			let result = instance.invoke("call", &[Value::I32(0x2a)], &mut ());
+			assert!(matches!(result, Err(Error::Trap(_))));
+
+			let snapshots = snapshots();
+			assert_eq!(snapshots.len(), 1);
+			let snapshot = &snapshots[0];
+			assert_eq!(snapshot.depth, 1);
+			assert_eq!(snapshot.executor, executor);
+			assert_eq!(snapshot.function, "call");
+			assert_eq!(snapshot.args, vec![Value::I32(0x2a)]);
+			assert_eq!(
+				snapshot.frame,
+				Some(FrameSnapshot {
+					function: 0,
+					locals: vec![Value::I32(0x2a)],
+				})
+			);
+			assert_eq!(snapshot.trap.code, TrapCode::UnreachableCodeReached);
+			assert_eq!(snapshot.globals, vec![("__heap_base".to_string(), Value::I32(1024))]);
+
+			assert_eq!(snapshot.memories.len(), 1);
+			let memory = &snapshot.memories[0];
+			assert_eq!((&memory.module[..], &memory.field[..]), ("env", "memory"));
+			assert_eq!(memory.size, 65536);
+			let data = memory.decompress().unwrap();
+			assert_eq!(data.len(), 65536);
+			assert_eq!(&data[8..12], &[0x2a, 0, 0, 0]);
+		}
+	});
+}
+
+fn nop(_: &mut (), _: &[Value]) -> Result<ReturnValue, HostError> {
+	Ok(ReturnValue::Unit)
+}
+
+#[test]
+fn trapping_frame_is_captured() {
+	let code = wat::parse_str(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(import "env" "nop" (func $nop))
+			(func $divide (param i32 i32) (result i32) (local i64)
+				(local.set 2 (i64.const 7))
+				(call $nop)
+				(i32.div_u (local.get 0) (local.get 1))
+			)
+			(func (export "call") (local f64)
+				(local.set 0 (f64.const 1.5))
+				(drop (call $divide (i32.const 5) (i32.const 1)))
+				(drop (call $divide (i32.const 3) (i32.const 0)))
+			)
+		)
+		"#,
+	)
+	.unwrap();
+
+	ep_sandbox::set_trap_snapshot(true);
+	let mut ext = sp_io::TestExternalities::default();
+	ext.register_extension(TrapSnapshotsExt::default());
+	ext.execute_with(|| {
+		for executor in [SandboxExecutor::Interpreter, SandboxExecutor::Jit].iter().cloned() {
+			let memory = Memory::new_with_executor(executor, 1, Some(1)).unwrap();
+			let mut env_builder = EnvironmentDefinitionBuilder::<()>::new();
+			env_builder.add_memory("env", "memory", memory);
+			env_builder.add_host_func("env", "nop", nop);
+
+			let mut instance = Instance::new(&code, &env_builder, &mut ()).unwrap();
+			let result = instance.invoke("call", &[], &mut ());
+			assert!(matches!(result, Err(Error::Trap(_))));
+
+			let snapshots = snapshots();
+			assert_eq!(snapshots.len(), 1);
+			let snapshot = &snapshots[0];
+			assert_eq!(snapshot.trap.code, TrapCode::IntegerDivisionByZero);
+			// the frame of the second call, the index counts the imported function.
+			assert_eq!(
+				snapshot.frame,
+				Some(FrameSnapshot {
+					function: 1,
+					locals: vec![Value::I32(3), Value::I32(0), Value::I64(7)],
+				})
+			);
+		}
+	});
+}
@@ -0,0 +1,87 @@
+use ep_sandbox::validation::{self, IssueKind};
+
+fn validate(wat: &str) -> ep_sandbox::ValidationReport {
+	ep_sandbox::validate(&wat::parse_str(wat).unwrap())
+}
+
+#[test]
+fn contract_is_valid() {
+	let report = validate(
+		r#"
+		(module
+			(import "seal0" "seal_return" (func $seal_return (param i32 i32 i32)))
+			(import "env" "memory" (memory 1 1))
+			(func (export "deploy"))
+			(func (export "call")
+				(call $seal_return (i32.const 0) (i32.const 0) (i32.const 0))
+			)
+		)
+		"#,
+	);
+	assert!(report.is_valid(), "{}", report);
+}
+
+#[test]
+fn unsupported_features_are_reported_once() {
+	let report = validate(
+		r#"
+		(module
+			(import "env" "memory" (memory 1 1))
+			(func (export "deploy"))
+			(func (export "call") (result i32 i32)
+				(drop (i32.extend8_s (i32.const 1)))
+				(drop (i32.extend16_s (i32.const 1)))
+				(memory.fill (i32.const 0) (i32.const 0) (i32.const 1))
+				(drop (f32.add (f32.const 1) (f32.const 2)))
+				(i32.const 1)
+				(i32.const 2)
+			)
+		)
+		"#,
+	);
+	assert_eq!(
+		report.features(),
+		vec![
+			validation::MULTI_VALUE,
+			validation::SIGN_EXT,
+			validation::BULK_MEMORY,
+			validation::FLOATS
+		]
+	);
+	let sign_ext = &report.issues[1];
+	assert!(
+		sign_ext.reason.starts_with("I32Extend8S in the function 1"),
+		"{}",
+		sign_ext.reason
+	);
+}
+
+#[test]
+fn imports_sections_and_exports_are_reported() {
+	let report = validate(
+		r#"
+		(module
+			(import "env" "abort" (func))
+			(import "seal0" "memory" (memory 1 1))
+			(memory 1)
+			(func (export "call"))
+		)
+		"#,
+	);
+	assert_eq!(
+		report
+			.issues
+			.iter()
+			.map(|issue| (issue.kind, issue.name.as_str()))
+			.collect::<Vec<_>>(),
+		vec![
+			(IssueKind::Import, "env.abort"),
+			(IssueKind::Import, "seal0.memory"),
+			(IssueKind::Section, "memory"),
+			(IssueKind::Export, "deploy"),
+		]
+	);
+
+	let report = ep_sandbox::validate(b"\0asm");
+	assert_eq!(report.issues[0].kind, IssueKind::Malformed);
+}